@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	// protoc before 3.15 (e.g. Debian bullseye's) only accepts optional fields behind this flag
+	tonic_build::configure()
+		.protoc_arg("--experimental_allow_proto3_optional")
+		.compile(&["proto/streams.proto"], &["proto"])?;
+	Ok(())
+}
@@ -0,0 +1,232 @@
+//! A typed wrapper around the GRPC stubs of a Validated Streams node.
+
+use crate::{
+	errors::Error,
+	ids::{ConflictSetId, EventId, FeedId, StreamId},
+	proto::{
+		streams_client::StreamsClient, EventPayloadRequest, EventStatusRequest,
+		EventStatusResponse, FeedValueRequest, FeedValueResponse, ReportFeedValueRequest,
+		WitnessEventRequest, WitnessEventStatus, WitnessEventsRequest,
+	},
+	validated_events::{self, ValidatedEvents, ValidatedEventsOptions},
+};
+use tonic::{
+	codegen::InterceptedService,
+	metadata::{Ascii, MetadataValue},
+	service::Interceptor,
+	transport::{Channel, Endpoint},
+	Code, Request, Status,
+};
+
+/// The GRPC stubs, with the [AuthToken] of the client attached to every request.
+pub(crate) type InnerClient = StreamsClient<InterceptedService<Channel, AuthToken>>;
+
+/// An [Interceptor] adding a bearer token (as configured with `--grpc-auth-tokens` on the node)
+/// to requests.
+#[derive(Clone, Debug, Default)]
+pub struct AuthToken(Option<MetadataValue<Ascii>>);
+
+impl AuthToken {
+	/// Creates an [AuthToken] sending the given token, or nothing if [None].
+	pub fn new(token: Option<&str>) -> Result<Self, Error> {
+		token
+			.map(|token| format!("Bearer {token}").parse().map_err(|_| Error::InvalidAuthToken))
+			.transpose()
+			.map(Self)
+	}
+}
+
+impl Interceptor for AuthToken {
+	fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+		if let Some(token) = &self.0 {
+			request.metadata_mut().insert("authorization", token.clone());
+		}
+		Ok(request)
+	}
+}
+
+/// A client of the Validated Streams GRPC API. Cloning it is cheap and reuses the same connection.
+#[derive(Clone)]
+pub struct Client {
+	inner: InnerClient,
+}
+
+impl Client {
+	/// Creates a client for the node at the given address (e.g. `http://127.0.0.1:5555`). The
+	/// connection is established lazily, and re-established automatically whenever it drops.
+	pub fn connect(addr: impl Into<String>, auth_token: Option<&str>) -> Result<Self, Error> {
+		let channel = Endpoint::from_shared(addr.into())?.connect_lazy();
+		Self::with_channel(channel, auth_token)
+	}
+
+	/// Creates a client using an already configured [Channel], e.g. one set up with TLS.
+	pub fn with_channel(channel: Channel, auth_token: Option<&str>) -> Result<Self, Error> {
+		Ok(Self { inner: StreamsClient::with_interceptor(channel, AuthToken::new(auth_token)?) })
+	}
+
+	/// Witnesses an event, having the node sign and gossip it.
+	pub async fn witness_event(&self, stream_id: StreamId, event_id: EventId) -> Result<(), Error> {
+		self.inner
+			.clone()
+			.witness_event(WitnessEventRequest {
+				event_id: event_id.to_vec(),
+				stream_id: stream_id.to_vec(),
+				..Default::default()
+			})
+			.await?;
+		Ok(())
+	}
+
+	/// Witnesses an event as a member of a conflict set. Fails with a `FAILED_PRECONDITION`
+	/// [Error::Status] if the node already witnessed another member of the set, or if another
+	/// member was already validated.
+	pub async fn witness_conflicting_event(
+		&self,
+		stream_id: StreamId,
+		conflict_set: ConflictSetId,
+		event_id: EventId,
+	) -> Result<(), Error> {
+		self.inner
+			.clone()
+			.witness_event(WitnessEventRequest {
+				event_id: event_id.to_vec(),
+				stream_id: stream_id.to_vec(),
+				conflict_set: conflict_set.to_vec(),
+				..Default::default()
+			})
+			.await?;
+		Ok(())
+	}
+
+	/// Witnesses an event given by its payload, having the node store the payload, and sign and
+	/// gossip the resulting event. Returns the id of the event.
+	pub async fn witness_event_payload(
+		&self,
+		stream_id: StreamId,
+		payload: Vec<u8>,
+	) -> Result<EventId, Error> {
+		let event_id = EventId::from_payload(&payload);
+		self.inner
+			.clone()
+			.witness_event(WitnessEventRequest {
+				event_id: event_id.to_vec(),
+				stream_id: stream_id.to_vec(),
+				payload,
+				..Default::default()
+			})
+			.await?;
+		Ok(event_id)
+	}
+
+	/// Witnesses a batch of events of one stream at once. Returns the outcome for each of the
+	/// events, in order.
+	pub async fn witness_events(
+		&self,
+		stream_id: StreamId,
+		event_ids: &[EventId],
+	) -> Result<Vec<WitnessEventStatus>, Error> {
+		let response = self
+			.inner
+			.clone()
+			.witness_events(WitnessEventsRequest {
+				event_ids: event_ids.iter().map(EventId::to_vec).collect(),
+				stream_id: stream_id.to_vec(),
+			})
+			.await?
+			.into_inner();
+		if response.statuses.len() != event_ids.len() {
+			return Err(Error::InvalidResponse(format!(
+				"got {} statuses for {} events",
+				response.statuses.len(),
+				event_ids.len()
+			)))
+		}
+		Ok(response
+			.statuses
+			.into_iter()
+			.map(|status| {
+				WitnessEventStatus::from_i32(status).unwrap_or(WitnessEventStatus::Failed)
+			})
+			.collect())
+	}
+
+	/// Gets the current status of an event.
+	pub async fn event_status(
+		&self,
+		stream_id: StreamId,
+		event_id: EventId,
+	) -> Result<EventStatusResponse, Error> {
+		Ok(self
+			.inner
+			.clone()
+			.get_event_status(EventStatusRequest {
+				event_id: event_id.to_vec(),
+				stream_id: stream_id.to_vec(),
+			})
+			.await?
+			.into_inner())
+	}
+
+	/// Gets the payload of an event, or [None] if the node does not have it.
+	pub async fn event_payload(&self, event_id: EventId) -> Result<Option<Vec<u8>>, Error> {
+		match self
+			.inner
+			.clone()
+			.get_event_payload(EventPayloadRequest { event_id: event_id.to_vec() })
+			.await
+		{
+			Ok(response) => Ok(Some(response.into_inner().payload)),
+			Err(status) if status.code() == Code::NotFound => Ok(None),
+			Err(status) => Err(status.into()),
+		}
+	}
+
+	/// Reports a value of a feed for a round, having the node sign and gossip it. Once 2/3 of the
+	/// authorities have reported, the median of the values is stored on-chain.
+	pub async fn report_feed_value(
+		&self,
+		feed_id: FeedId,
+		round: u64,
+		value: i64,
+	) -> Result<(), Error> {
+		self.inner
+			.clone()
+			.report_feed_value(ReportFeedValueRequest { feed_id: feed_id.to_vec(), round, value })
+			.await?;
+		Ok(())
+	}
+
+	/// Gets the median value of a feed stored on-chain for a round, or for the latest round if
+	/// [None]. Returns [None] if no value was stored yet.
+	pub async fn feed_value(
+		&self,
+		feed_id: FeedId,
+		round: Option<u64>,
+	) -> Result<Option<FeedValueResponse>, Error> {
+		match self
+			.inner
+			.clone()
+			.get_feed_value(FeedValueRequest {
+				feed_id: feed_id.to_vec(),
+				round: round.unwrap_or(0),
+				latest: round.is_none(),
+			})
+			.await
+		{
+			Ok(response) => Ok(Some(response.into_inner())),
+			Err(status) if status.code() == Code::NotFound => Ok(None),
+			Err(status) => Err(status.into()),
+		}
+	}
+
+	/// Streams the validated events of finalized blocks. See [ValidatedEvents] for the guarantees
+	/// the stream provides.
+	pub fn validated_events(&self, options: ValidatedEventsOptions) -> ValidatedEvents {
+		validated_events::validated_events(self.inner.clone(), options)
+	}
+
+	/// Returns the raw GRPC stubs, for functionality not covered by [Client].
+	pub fn inner(&self) -> StreamsClient<InterceptedService<Channel, AuthToken>> {
+		self.inner.clone()
+	}
+}
@@ -0,0 +1,58 @@
+//! Error types for the Validated Streams client.
+
+use std::{error::Error as E, fmt};
+
+/// An error which has occurred while talking to a Validated Streams node.
+#[derive(Debug)]
+pub enum Error {
+	/// We failed to set up the connection to the node
+	Transport(tonic::transport::Error),
+	/// The node responded with an error
+	Status(tonic::Status),
+	/// The authentication token contains characters not allowed in a GRPC header
+	InvalidAuthToken,
+	/// An event or stream id was not exactly 32 bytes long
+	InvalidIdLength(usize),
+	/// An event or stream id string was not valid hex
+	InvalidIdHex(String),
+	/// The node skipped some blocks while streaming validated events
+	Gap {
+		/// The number of the block which should have come next
+		expected: u32,
+		/// The number of the block which was received instead
+		received: u32,
+	},
+	/// The node sent a response which does not follow the protocol
+	InvalidResponse(String),
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Transport(e) => write!(f, "Transport error, {e}"),
+			Error::Status(status) =>
+				write!(f, "Node returned {:?}: {}", status.code(), status.message()),
+			Error::InvalidAuthToken => write!(f, "Invalid authentication token"),
+			Error::InvalidIdLength(length) =>
+				write!(f, "Invalid id length {length} (expected 32 bytes)"),
+			Error::InvalidIdHex(reason) => write!(f, "Invalid id hex, {reason}"),
+			Error::Gap { expected, received } =>
+				write!(f, "Expected block #{expected}, but received block #{received}"),
+			Error::InvalidResponse(reason) => write!(f, "Invalid response from node, {reason}"),
+		}
+	}
+}
+impl E for Error {}
+
+#[doc(hidden)] // Enable use of `?` operator.
+impl From<tonic::transport::Error> for Error {
+	fn from(e: tonic::transport::Error) -> Error {
+		Error::Transport(e)
+	}
+}
+
+#[doc(hidden)] // Enable use of `?` operator.
+impl From<tonic::Status> for Error {
+	fn from(status: tonic::Status) -> Error {
+		Error::Status(status)
+	}
+}
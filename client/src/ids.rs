@@ -0,0 +1,118 @@
+//! Typed identifiers of events and streams.
+
+use crate::errors::Error;
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use std::{fmt, str::FromStr};
+
+/// The id of an event; a hash (or similar) of exactly 32 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventId(pub [u8; 32]);
+
+impl EventId {
+	/// Returns the id the node assigns to an event with the given payload; that is, the BLAKE2b-256
+	/// hash of the payload bytes.
+	pub fn from_payload(payload: &[u8]) -> Self {
+		Self(blake2_256(payload))
+	}
+}
+
+/// The id of a stream (namespace) of events; exactly 32 bytes, with all zeroes being the default
+/// stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(pub [u8; 32]);
+
+impl StreamId {
+	/// The default stream, used by clients which do not specify a stream.
+	pub const DEFAULT: Self = Self([0; 32]);
+
+	/// Returns a stream id derived from an application name, as the BLAKE2b-256 hash of the name.
+	pub fn from_name(name: &str) -> Self {
+		Self(blake2_256(name.as_bytes()))
+	}
+}
+
+/// The id of a conflict set; exactly 32 bytes. Of the events witnessed as members of the same
+/// conflict set, at most one is ever validated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConflictSetId(pub [u8; 32]);
+
+impl ConflictSetId {
+	/// Returns a conflict set id derived from a name, as the BLAKE2b-256 hash of the name.
+	pub fn from_name(name: &str) -> Self {
+		Self(blake2_256(name.as_bytes()))
+	}
+}
+
+/// The id of a numeric feed (e.g. a price or a temperature); exactly 32 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FeedId(pub [u8; 32]);
+
+impl FeedId {
+	/// Returns a feed id derived from a name, as the BLAKE2b-256 hash of the name.
+	pub fn from_name(name: &str) -> Self {
+		Self(blake2_256(name.as_bytes()))
+	}
+}
+
+fn blake2_256(data: &[u8]) -> [u8; 32] {
+	Blake2b::<U32>::digest(data).into()
+}
+
+/// Implements the conversions shared by [EventId], [StreamId], [ConflictSetId] and [FeedId].
+macro_rules! impl_id_conversions {
+	($id:ident) => {
+		impl $id {
+			/// Returns the raw bytes of the id.
+			pub fn as_bytes(&self) -> &[u8; 32] {
+				&self.0
+			}
+
+			/// Returns the raw bytes of the id, as used in protobuf messages.
+			pub fn to_vec(&self) -> Vec<u8> {
+				self.0.to_vec()
+			}
+		}
+
+		impl From<[u8; 32]> for $id {
+			fn from(bytes: [u8; 32]) -> Self {
+				Self(bytes)
+			}
+		}
+
+		impl TryFrom<&[u8]> for $id {
+			type Error = Error;
+
+			fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+				bytes.try_into().map(Self).map_err(|_| Error::InvalidIdLength(bytes.len()))
+			}
+		}
+
+		impl AsRef<[u8]> for $id {
+			fn as_ref(&self) -> &[u8] {
+				&self.0
+			}
+		}
+
+		impl fmt::Display for $id {
+			fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+				write!(f, "0x{}", hex::encode(self.0))
+			}
+		}
+
+		impl FromStr for $id {
+			type Err = Error;
+
+			/// Parses a hex string, with or without a `0x` prefix.
+			fn from_str(s: &str) -> Result<Self, Error> {
+				let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))
+					.map_err(|e| Error::InvalidIdHex(e.to_string()))?;
+				Self::try_from(bytes.as_slice())
+			}
+		}
+	};
+}
+
+impl_id_conversions!(EventId);
+impl_id_conversions!(StreamId);
+impl_id_conversions!(ConflictSetId);
+impl_id_conversions!(FeedId);
@@ -0,0 +1,28 @@
+//! Client library for the GRPC API of a Validated Streams node.
+//! Wraps the raw [proto] stubs with typed [EventId]-s and [StreamId]-s, and provides a
+//! [Client::validated_events] stream which reconnects and resumes on its own, while checking that
+//! no block is skipped or delivered twice. See
+//! <https://github.com/comrade-coop/validated-streams/blob/master/client/proto/streams.proto>
+//! for the underlying protocol.
+
+#![warn(missing_docs)]
+pub mod client;
+pub mod errors;
+pub mod ids;
+#[cfg(test)]
+pub mod tests;
+pub mod validated_events;
+
+/// The protobuf messages and GRPC stubs of the Validated Streams API, as generated by tonic.
+#[allow(missing_docs)]
+pub mod proto {
+	tonic::include_proto!("validated_streams");
+}
+
+pub use client::{AuthToken, Client};
+pub use errors::Error;
+pub use ids::{ConflictSetId, EventId, FeedId, StreamId};
+pub use validated_events::{
+	BlockSequence, Cursor, StartFrom, ValidatedBlock, ValidatedEvent, ValidatedEvents,
+	ValidatedEventsOptions,
+};
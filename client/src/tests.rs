@@ -0,0 +1,71 @@
+use super::{errors::Error, validated_events::is_transient, BlockSequence, EventId, StreamId};
+use rstest::rstest;
+use std::str::FromStr;
+use tonic::{Code, Status};
+
+#[test]
+fn test_event_id_parsing() {
+	let event_id = EventId([0xab; 32]);
+	let hex = format!("0x{}", "ab".repeat(32));
+	assert_eq!(event_id.to_string(), hex);
+	assert_eq!(EventId::from_str(&hex).unwrap(), event_id);
+	assert_eq!(EventId::from_str(&hex[2..]).unwrap(), event_id);
+
+	assert!(matches!(EventId::from_str("0xabcd"), Err(Error::InvalidIdLength(2))));
+	assert!(matches!(EventId::from_str("0xzz"), Err(Error::InvalidIdHex(_))));
+	assert!(matches!(EventId::try_from(&[0u8; 33][..]), Err(Error::InvalidIdLength(33))));
+}
+
+#[test]
+fn test_payload_event_id() {
+	// BLAKE2b-256 of the empty string
+	assert_eq!(
+		EventId::from_payload(b"").to_string(),
+		"0x0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+	);
+	assert_ne!(StreamId::from_name("app"), StreamId::DEFAULT);
+	assert_eq!(StreamId::default(), StreamId::DEFAULT);
+}
+
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn test_block_sequence_duplicates(#[case] allow_gaps: bool) {
+	let mut sequence = BlockSequence::new(Some(5), allow_gaps);
+	assert!(sequence.receive(5).unwrap());
+	assert!(sequence.receive(6).unwrap());
+	assert!(!sequence.receive(6).unwrap());
+	assert!(!sequence.receive(3).unwrap());
+	assert_eq!(sequence.next_block(), Some(7));
+}
+
+#[test]
+fn test_block_sequence_gaps() {
+	let mut sequence = BlockSequence::new(None, false);
+	assert!(sequence.receive(10).unwrap());
+	assert!(matches!(sequence.receive(12), Err(Error::Gap { expected: 11, received: 12 })));
+	// heartbeats do not hide gaps
+	sequence.skip_to(20);
+	assert_eq!(sequence.next_block(), Some(11));
+
+	let mut sequence = BlockSequence::new(Some(10), true);
+	assert!(sequence.receive(12).unwrap());
+	sequence.skip_to(20);
+	assert_eq!(sequence.next_block(), Some(20));
+	assert!(!sequence.receive(19).unwrap());
+	sequence.skip_to(15);
+	assert_eq!(sequence.next_block(), Some(20));
+}
+
+#[rstest]
+#[case(Code::Unavailable, true)]
+#[case(Code::DeadlineExceeded, true)]
+#[case(Code::Cancelled, true)]
+#[case(Code::ResourceExhausted, true)]
+#[case(Code::Aborted, false)]
+#[case(Code::Unknown, false)]
+#[case(Code::Internal, false)]
+#[case(Code::InvalidArgument, false)]
+fn test_transient_errors(#[case] code: Code, #[case] transient: bool) {
+	assert_eq!(is_transient(&Status::new(code, "")), transient);
+}
@@ -0,0 +1,350 @@
+//! A resilient stream of the validated events of finalized blocks.
+
+use crate::{
+	client::InnerClient,
+	errors::Error,
+	ids::{ConflictSetId, EventId, StreamId},
+	proto::{ValidatedEventsRequest, ValidatedEventsResponse},
+};
+use futures::{stream, Stream};
+use std::{pin::Pin, time::Duration};
+use tonic::{Code, Status, Streaming};
+
+/// An opaque position in the stream of validated events, as produced by the node. Persist the
+/// cursor of the last processed [ValidatedBlock] to resume from it after a restart.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Cursor(pub Vec<u8>);
+
+/// Where a [ValidatedEvents] stream starts from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StartFrom {
+	/// The latest finalized block.
+	#[default]
+	Latest,
+	/// The block with the given number.
+	Block(u32),
+	/// The block after the given cursor.
+	Cursor(Cursor),
+}
+
+/// Options for [crate::Client::validated_events].
+#[derive(Clone, Debug)]
+pub struct ValidatedEventsOptions {
+	/// Where to start streaming from.
+	pub start_from: StartFrom,
+	/// The last block to stream (inclusive); the stream ends after it. [None] streams forever.
+	pub to_block: Option<u32>,
+	/// Only stream events of these streams; empty for all streams.
+	pub stream_ids: Vec<StreamId>,
+	/// Do not yield blocks without any (matching) events.
+	pub skip_empty_blocks: bool,
+	/// Ask the node for a heartbeat after this much silence; if nothing arrives for twice as long,
+	/// the connection is considered dead and re-established. [None] disables heartbeats.
+	pub heartbeat_interval: Option<Duration>,
+	/// How long to wait before the first reconnection attempt; doubled after every failed
+	/// attempt, up to `max_reconnect_delay`.
+	pub reconnect_delay: Duration,
+	/// The longest time to wait between reconnection attempts.
+	pub max_reconnect_delay: Duration,
+}
+
+impl Default for ValidatedEventsOptions {
+	fn default() -> Self {
+		Self {
+			start_from: StartFrom::Latest,
+			to_block: None,
+			stream_ids: vec![],
+			skip_empty_blocks: false,
+			heartbeat_interval: Some(Duration::from_secs(30)),
+			reconnect_delay: Duration::from_millis(500),
+			max_reconnect_delay: Duration::from_secs(30),
+		}
+	}
+}
+
+/// An event validated in a finalized block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatedEvent {
+	/// The stream the event belongs to.
+	pub stream_id: StreamId,
+	/// The id of the event.
+	pub event_id: EventId,
+	/// The conflict set the event was validated as a member of, if any.
+	pub conflict_set: Option<ConflictSetId>,
+	/// The index of the extrinsic which validated the event within its block.
+	pub extrinsic_index: u32,
+	/// The public keys of the authorities whose proofs were used to validate the event.
+	pub signers: Vec<Vec<u8>>,
+}
+
+/// The validated events of a finalized block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatedBlock {
+	/// The number of the finalized block.
+	pub block_number: u32,
+	/// The hash of the finalized block.
+	pub block_hash: Vec<u8>,
+	/// The events validated in the block, in the order of the extrinsics validating them.
+	pub events: Vec<ValidatedEvent>,
+	/// The cursor pointing after this block, for resuming the stream later.
+	pub cursor: Cursor,
+	/// The number of the latest finalized block at the time the node sent this block.
+	pub latest_finalized_block: u32,
+}
+
+impl ValidatedBlock {
+	fn from_response(block_number: u32, response: ValidatedEventsResponse) -> Result<Self, Error> {
+		let events = response
+			.events
+			.into_iter()
+			.map(|event| {
+				Ok(ValidatedEvent {
+					stream_id: if event.stream_id.is_empty() {
+						StreamId::DEFAULT
+					} else {
+						event.stream_id.as_slice().try_into()?
+					},
+					event_id: event.event_id.as_slice().try_into()?,
+					conflict_set: if event.conflict_set.is_empty() {
+						None
+					} else {
+						Some(event.conflict_set.as_slice().try_into()?)
+					},
+					extrinsic_index: event.extrinsic_index,
+					signers: event.signers,
+				})
+			})
+			.collect::<Result<_, Error>>()
+			.map_err(|e| Error::InvalidResponse(e.to_string()))?;
+		Ok(Self {
+			block_number,
+			block_hash: response.block_hash,
+			events,
+			cursor: Cursor(response.cursor),
+			latest_finalized_block: response.latest_finalized_block,
+		})
+	}
+}
+
+/// Checks the block numbers of a stream of validated events for gaps and duplicates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockSequence {
+	next_block: Option<u32>,
+	allow_gaps: bool,
+}
+
+impl BlockSequence {
+	/// Creates a [BlockSequence] expecting `next_block` first ([None] accepts any block). With
+	/// `allow_gaps`, skipped blocks are not an error, e.g. when the node skips empty blocks.
+	pub fn new(next_block: Option<u32>, allow_gaps: bool) -> Self {
+		Self { next_block, allow_gaps }
+	}
+
+	/// Returns the number of the block expected next, if known.
+	pub fn next_block(&self) -> Option<u32> {
+		self.next_block
+	}
+
+	/// Records a received block. Returns `false` for blocks which were already received, and
+	/// [Error::Gap] if blocks were skipped when that is not allowed.
+	pub fn receive(&mut self, block_number: u32) -> Result<bool, Error> {
+		match self.next_block {
+			Some(next_block) if block_number < next_block => Ok(false),
+			Some(next_block) if block_number > next_block && !self.allow_gaps =>
+				Err(Error::Gap { expected: next_block, received: block_number }),
+			_ => {
+				self.next_block = Some(block_number.saturating_add(1));
+				Ok(true)
+			},
+		}
+	}
+
+	/// Records that the node went through all blocks before `next_block` (as reported by
+	/// heartbeats); only moves forward when gaps are allowed.
+	pub fn skip_to(&mut self, next_block: u32) {
+		if self.allow_gaps && self.next_block.map_or(true, |current| next_block > current) {
+			self.next_block = Some(next_block);
+		}
+	}
+}
+
+/// A stream of [ValidatedBlock]-s, as returned by [crate::Client::validated_events].
+/// When the connection to the node breaks, the stream reconnects on its own (with exponential
+/// backoff), resuming after the last block it delivered. Blocks are yielded in order, and no
+/// block is yielded twice; if the node skips a block (other than empty blocks, when
+/// [ValidatedEventsOptions::skip_empty_blocks] is set), an [Error::Gap] is yielded instead. The
+/// stream ends after the first error it does not recover from.
+pub type ValidatedEvents = Pin<Box<dyn Stream<Item = Result<ValidatedBlock, Error>> + Send>>;
+
+/// The state of a [ValidatedEvents] stream.
+struct ValidatedEventsState {
+	client: InnerClient,
+	options: ValidatedEventsOptions,
+	responses: Option<Streaming<ValidatedEventsResponse>>,
+	cursor: Option<Cursor>,
+	sequence: BlockSequence,
+	reconnect_delay: Duration,
+}
+
+impl ValidatedEventsState {
+	/// Builds the request for (re)opening the stream, resuming from the last cursor if any.
+	fn request(&self) -> ValidatedEventsRequest {
+		let mut request = ValidatedEventsRequest {
+			to_block: self.options.to_block,
+			skip_empty_blocks: self.options.skip_empty_blocks,
+			heartbeat_interval: self.heartbeat_interval_secs().unwrap_or(0),
+			stream_ids: self.options.stream_ids.iter().map(StreamId::to_vec).collect(),
+			..Default::default()
+		};
+		match (&self.cursor, &self.options.start_from) {
+			(Some(cursor), _) | (None, StartFrom::Cursor(cursor)) =>
+				request.cursor = cursor.0.clone(),
+			(None, StartFrom::Block(block)) => request.from_block = *block,
+			(None, StartFrom::Latest) => request.from_latest = true,
+		}
+		request
+	}
+
+	/// The heartbeat interval in whole seconds, as the node supports no finer granularity.
+	fn heartbeat_interval_secs(&self) -> Option<u32> {
+		self.options
+			.heartbeat_interval
+			.map(|interval| interval.as_secs().clamp(1, u32::MAX.into()) as u32)
+	}
+
+	/// Waits before the next reconnection attempt, and increases the delay for the one after it.
+	async fn backoff(&mut self) {
+		tokio::time::sleep(self.reconnect_delay).await;
+		self.reconnect_delay = (self.reconnect_delay * 2).min(self.options.max_reconnect_delay);
+	}
+
+	/// Whether the stream has delivered everything up to [ValidatedEventsOptions::to_block].
+	fn finished(&self) -> bool {
+		match (self.options.to_block, self.sequence.next_block()) {
+			(Some(to_block), Some(next_block)) => next_block > to_block,
+			_ => false,
+		}
+	}
+
+	/// Processes a response from the node, returning the block to yield, if any.
+	fn handle_response(
+		&mut self,
+		response: ValidatedEventsResponse,
+	) -> Result<Option<ValidatedBlock>, Error> {
+		if response.heartbeat {
+			self.sequence.skip_to(response.next_block);
+			if !response.cursor.is_empty() {
+				self.cursor = Some(Cursor(response.cursor));
+			}
+			return Ok(None)
+		}
+		let block_number = response
+			.next_block
+			.checked_sub(1)
+			.ok_or_else(|| Error::InvalidResponse("next_block is 0".to_string()))?;
+		if !self.sequence.receive(block_number)? {
+			log::debug!("Dropping duplicate block #{block_number}");
+			return Ok(None)
+		}
+		let block = ValidatedBlock::from_response(block_number, response)?;
+		self.cursor = Some(block.cursor.clone());
+		if self.options.skip_empty_blocks && block.events.is_empty() {
+			return Ok(None)
+		}
+		Ok(Some(block))
+	}
+
+	/// Produces the next item of the stream; [None] once the stream is over.
+	async fn next(&mut self) -> Option<Result<ValidatedBlock, Error>> {
+		loop {
+			if self.finished() {
+				return None
+			}
+			if self.responses.is_none() {
+				match self.client.validated_events(self.request()).await {
+					Ok(responses) => self.responses = Some(responses.into_inner()),
+					Err(status) if is_transient(&status) => {
+						log::warn!("Failed opening validated events stream: {status}");
+						self.backoff().await;
+					},
+					Err(status) => return Some(Err(status.into())),
+				}
+				continue
+			}
+			let heartbeat_interval = self.heartbeat_interval_secs();
+			let responses = self.responses.as_mut().expect("checked above");
+			let message = match heartbeat_interval {
+				Some(interval) => match tokio::time::timeout(
+					Duration::from_secs(u64::from(interval) * 2),
+					responses.message(),
+				)
+				.await
+				{
+					Ok(message) => message,
+					Err(_) => Err(Status::unavailable("no heartbeat received in time")),
+				},
+				None => responses.message().await,
+			};
+			match message {
+				Ok(Some(response)) => {
+					self.reconnect_delay = self.options.reconnect_delay;
+					match self.handle_response(response) {
+						Ok(Some(block)) => return Some(Ok(block)),
+						Ok(None) => continue,
+						Err(e) => return Some(Err(e)),
+					}
+				},
+				// The node closes the stream after to_block; otherwise, it is shutting down
+				Ok(None) => {
+					self.responses = None;
+					if !self.finished() {
+						self.backoff().await;
+					}
+				},
+				Err(status) if is_transient(&status) => {
+					log::warn!("Validated events stream interrupted: {status}");
+					self.responses = None;
+					self.backoff().await;
+				},
+				Err(status) => return Some(Err(status.into())),
+			}
+		}
+	}
+}
+
+/// Creates a [ValidatedEvents] stream using the given GRPC stubs.
+pub(crate) fn validated_events(
+	client: InnerClient,
+	options: ValidatedEventsOptions,
+) -> ValidatedEvents {
+	let start_block = match options.start_from {
+		StartFrom::Block(block) => Some(block),
+		StartFrom::Latest | StartFrom::Cursor(_) => None,
+	};
+	let state = ValidatedEventsState {
+		client,
+		sequence: BlockSequence::new(start_block, options.skip_empty_blocks),
+		reconnect_delay: options.reconnect_delay,
+		options,
+		responses: None,
+		cursor: None,
+	};
+	// The state becomes None after an error, ending the stream.
+	Box::pin(stream::unfold(Some(state), |state| async move {
+		let mut state = state?;
+		match state.next().await? {
+			Ok(block) => Some((Ok(block), Some(state))),
+			Err(e) => Some((Err(e), None)),
+		}
+	}))
+}
+
+/// Whether an error status is likely caused by a connection problem or an overloaded node, and
+/// worth retrying after a backoff. Other codes, including `Aborted`, `Unknown` and `Internal`,
+/// indicate a failure of the request itself, which would only fail again.
+pub(crate) fn is_transient(status: &Status) -> bool {
+	matches!(
+		status.code(),
+		Code::Unavailable | Code::DeadlineExceeded | Code::Cancelled | Code::ResourceExhausted
+	)
+}
@@ -2,7 +2,7 @@
 #![cfg(feature = "off-chain-proofs")]
 
 use crate::{
-	events::{verify_events_validity, AuthoritiesList},
+	events::{verify_events_validity, AuthoritiesList, MissingProofs},
 	proofs::EventProofsTrait,
 };
 use codec::Codec;
@@ -27,7 +27,8 @@ use tokio::sync::oneshot;
 /// the finalization of blocks that lack sufficient signatures. Currently, when the node falls
 /// behind (as determined by [SyncingService::is_major_syncing]), the node will start forwarding
 /// blocks directly to the internal [BlockImport], regardless of signatures. This is intended to
-/// help in cases where a single missing event proof hangs the whole node.
+/// help in cases where a single missing event proof hangs the whole node. Events whose proofs are
+/// missing are reported to [MissingProofs], so that they are requested from peers.
 pub struct ValidatedStreamsBlockImport<
 	Block: BlockT,
 	I,
@@ -41,6 +42,7 @@ pub struct ValidatedStreamsBlockImport<
 	event_proofs: Arc<EventProofs>,
 	sync_service: Shared<oneshot::Receiver<Arc<SyncingService>>>,
 	block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+	missing_proofs: MissingProofs,
 	phantom: std::marker::PhantomData<(Block, AuthorityId)>,
 }
 
@@ -53,6 +55,7 @@ impl<Block: BlockT, I, Client, EventProofs, SyncingService, AuthorityId>
 		client: Arc<Client>,
 		event_proofs: Arc<EventProofs>,
 		block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+		missing_proofs: MissingProofs,
 	) -> (Self, impl FnOnce(Arc<SyncingService>)) {
 		let (sync_service_sender, sync_service_receiver) = oneshot::channel();
 
@@ -63,6 +66,7 @@ impl<Block: BlockT, I, Client, EventProofs, SyncingService, AuthorityId>
 				event_proofs,
 				sync_service: sync_service_receiver.shared(),
 				block_state,
+				missing_proofs,
 				phantom: PhantomData,
 			},
 			move |sync_service| {
@@ -82,6 +86,7 @@ impl<Block: BlockT, I: Clone, Client, EventProofs, SyncingService, AuthorityId>
 			event_proofs: self.event_proofs.clone(),
 			sync_service: self.sync_service.clone(),
 			block_state: self.block_state.clone(),
+			missing_proofs: self.missing_proofs.clone(),
 			phantom: PhantomData,
 		}
 	}
@@ -142,6 +147,7 @@ where
 							"❌ Block rejeceted containing {} unwitnessed events",
 							unwitnessed_ids.len()
 						);
+						self.missing_proofs.report(unwitnessed_ids);
 						return Err(ConsensusError::ClientImport(
 							"Block contains unwitnessed events".to_string(),
 						))
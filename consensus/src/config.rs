@@ -1,8 +1,12 @@
 //! Configurations needed by the Validated Streams node
 
-use libp2p::{core::multiaddr::Protocol, Multiaddr};
+use crate::{
+	events::DEFAULT_MAX_BATCH_SIZE,
+	gossip::{GossipConfig, Multiplexer},
+};
+use libp2p::{core::multiaddr::Protocol, Multiaddr, PeerId};
 
-use std::{fmt, net::SocketAddr, str::FromStr};
+use std::{fmt, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
 
 /// Network configuration for the Validated Streams node
 /// Currently this is a type alias to [ValidatedStreamsNetworkParams], but would be changed to its
@@ -16,8 +20,46 @@ pub struct ValidatedStreamsNetworkParams {
 	/// Do not expose to external machines or public-facing addresses as doing so is extremely
 	/// insecure and would result in anyone being able to trick this node into witnessing arbitrary
 	/// events.
+	/// Can be either a TCP socket address or a `unix:`-prefixed path to a Unix domain socket.
 	#[clap(long, default_value = "127.0.0.1:6000")]
-	pub grpc_addr: Vec<SocketAddr>,
+	pub grpc_addr: Vec<GrpcAddr>,
+
+	/// File permissions (in octal) of the Unix domain sockets created for --grpc-addr, restricting
+	/// which local users and groups can connect to them.
+	#[clap(long, default_value = "660", value_parser = parse_socket_mode)]
+	pub grpc_socket_mode: u32,
+
+	/// Maximum size in bytes of the raw event payloads accepted by the GRPC server. Payloads are
+	/// kept in the node's local content store, so this bounds how much storage a single witnessed
+	/// event can use.
+	#[clap(long, default_value_t = 64 * 1024)]
+	pub max_event_payload_size: usize,
+
+	/// PEM-encoded certificate to serve GRPC over TLS with. Requires --grpc-tls-key.
+	#[clap(long, requires = "grpc_tls_key")]
+	pub grpc_tls_cert: Option<PathBuf>,
+
+	/// PEM-encoded private key matching --grpc-tls-cert.
+	#[clap(long, requires = "grpc_tls_cert")]
+	pub grpc_tls_key: Option<PathBuf>,
+
+	/// PEM-encoded CA certificate used to verify GRPC client certificates. When set, clients must
+	/// present a certificate signed by this CA (mutual TLS). Requires --grpc-tls-cert.
+	#[clap(long, requires = "grpc_tls_cert")]
+	pub grpc_tls_client_ca: Option<PathBuf>,
+
+	/// File listing the bearer tokens accepted by the GRPC server, one `<client name>:<token>` per
+	/// line. When set, every GRPC call must include an `authorization: Bearer <token>` header, and
+	/// the client name is logged with every witnessed event.
+	#[clap(long)]
+	pub grpc_auth_tokens: Option<PathBuf>,
+
+	/// Transport used for gossiping by the Validated Streams consensus: either a separate libp2p
+	/// gossipsub swarm (configured with the other --gossip-* flags), or a notification protocol
+	/// of the Substrate network, reusing its port, peers, and peer discovery. The latter cannot
+	/// request missed proofs from peers, so it is only available with on-chain proofs.
+	#[clap(long, value_enum, default_value = "libp2p")]
+	pub gossip_transport: GossipTransport,
 
 	/// Port used for libp2p gossipsub by the Validated Streams consensus. The same addresses will
 	/// be used as those passed to the Substrate network (--listen-addr, --bootnodes) Can be either
@@ -26,9 +68,140 @@ pub struct ValidatedStreamsNetworkParams {
 	#[clap(long, default_value_t = PortOrOffset::Offset(10))]
 	pub gossip_port: PortOrOffset,
 
-	/// Override for the bootnodes used for gossiping by the Validated Streams consensus.
+	/// Override for the bootnodes used for gossiping by the Validated Streams consensus. Addresses
+	/// ending with `/p2p/<peer id>` are only connected to if the peer has that ID.
 	#[clap(long)]
 	pub gossip_bootnodes: Vec<Multiaddr>,
+
+	/// File holding the ed25519 secret key (raw or hex-encoded) the gossip identifies itself with;
+	/// a new key is generated and saved there if the file does not exist. By default, the gossip
+	/// shares the key (and thus, the peer ID) of the Substrate network, so that --bootnodes can be
+	/// reused for gossiping as-is.
+	#[clap(long)]
+	pub gossip_key_file: Option<PathBuf>,
+
+	/// Peer IDs allowed to take part in the gossip without proving ownership of a current
+	/// authority key, e.g. sentry nodes relaying for validators. All other peers are disconnected
+	/// unless they sign a handshake with an authority key.
+	#[clap(long)]
+	pub gossip_allowed_peers: Vec<PeerId>,
+
+	/// Accept gossip from every peer of the Substrate network when using --gossip-transport
+	/// substrate, which has no authority handshake. Only meant for networks whose peers are all
+	/// trusted; without it, the node refuses to start with that transport.
+	#[clap(long)]
+	pub gossip_trust_substrate_peers: bool,
+
+	/// How many outgoing gossip messages can be queued before witnessing slows down.
+	#[clap(long, default_value_t = 64)]
+	pub gossip_inbox_size: usize,
+
+	/// The target number of peers each gossip message is sent to directly (gossipsub's D). For
+	/// large validator sets, raising it lowers the number of hops a message takes.
+	#[clap(long, default_value_t = 6)]
+	pub gossip_mesh_n: usize,
+
+	/// The minimum number of peers messages are sent to directly (gossipsub's D_low).
+	#[clap(long, default_value_t = 5)]
+	pub gossip_mesh_n_low: usize,
+
+	/// The maximum number of peers messages are sent to directly (gossipsub's D_high).
+	#[clap(long, default_value_t = 12)]
+	pub gossip_mesh_n_high: usize,
+
+	/// Interval in milliseconds between gossipsub heartbeats.
+	#[clap(long, default_value_t = 1000)]
+	pub gossip_heartbeat_interval: u64,
+
+	/// Maximum size in bytes of a gossip message.
+	#[clap(long, default_value_t = 65536)]
+	pub gossip_max_transmit_size: usize,
+
+	/// Time in seconds for which received gossip messages are remembered, so that duplicates are
+	/// ignored.
+	#[clap(long, default_value_t = 60)]
+	pub gossip_duplicate_cache_ttl: u64,
+
+	/// Disable discovery of gossip peers on the local network with mDNS.
+	#[clap(long)]
+	pub gossip_no_mdns: bool,
+
+	/// Stream multiplexer used for gossip connections; all peers must use the same one.
+	#[clap(long, value_enum, default_value = "mplex")]
+	pub gossip_multiplexer: Multiplexer,
+
+	/// Window in milliseconds over which the events witnessed by this node are collected and
+	/// gossiped as a single message, lowering the per-message overhead at high event rates at the
+	/// cost of that much latency. 0 gossips every event in a message of its own.
+	#[clap(long, default_value_t = 0)]
+	pub gossip_batch_window: u64,
+
+	/// Maximum number of events gossiped in a single batch, 128 by default. Each event takes up to
+	/// 193 bytes, so keep the batch within --gossip-max-transmit-size.
+	#[clap(long, default_value_t = DEFAULT_MAX_BATCH_SIZE)]
+	pub gossip_batch_size: usize,
+}
+
+impl ValidatedStreamsNetworkParams {
+	/// Returns the [GossipConfig] corresponding to the --gossip-* parameters.
+	pub fn gossip_config(&self) -> GossipConfig {
+		GossipConfig {
+			inbox_size: self.gossip_inbox_size,
+			mesh_n: self.gossip_mesh_n,
+			mesh_n_low: self.gossip_mesh_n_low,
+			mesh_n_high: self.gossip_mesh_n_high,
+			heartbeat_interval: Duration::from_millis(self.gossip_heartbeat_interval),
+			max_transmit_size: self.gossip_max_transmit_size,
+			duplicate_cache_time: Duration::from_secs(self.gossip_duplicate_cache_ttl),
+			mdns: !self.gossip_no_mdns,
+			multiplexer: self.gossip_multiplexer,
+		}
+	}
+}
+
+/// The transport used for gossiping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum GossipTransport {
+	/// A separate libp2p gossipsub swarm, listening on --gossip-port.
+	Libp2p,
+	/// A notification protocol of the Substrate network.
+	Substrate,
+}
+
+/// An address the GRPC server listens on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrpcAddr {
+	/// A TCP socket address.
+	Tcp(SocketAddr),
+	/// A path to a Unix domain socket.
+	Unix(PathBuf),
+}
+
+impl fmt::Display for GrpcAddr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Tcp(addr) => fmt::Display::fmt(addr, f),
+			Self::Unix(path) => write!(f, "unix:{}", path.display()),
+		}
+	}
+}
+
+impl FromStr for GrpcAddr {
+	type Err = <SocketAddr as FromStr>::Err;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s.strip_prefix("unix:") {
+			Some(path) => Self::Unix(PathBuf::from(path)),
+			None => Self::Tcp(SocketAddr::from_str(s)?),
+		})
+	}
+}
+
+fn parse_socket_mode(s: &str) -> Result<u32, String> {
+	match u32::from_str_radix(s, 8) {
+		Ok(mode) if mode <= 0o777 => Ok(mode),
+		_ => Err(format!("{s} is not a valid octal file mode")),
+	}
 }
 
 /// A specific port number or an offset from the base port number. Used to subtly adjust an address
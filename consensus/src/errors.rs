@@ -9,6 +9,8 @@ pub enum Error {
 	LockFail(String),
 	/// The client submitted an incorrect signature
 	BadWitnessedEventSignature(String),
+	/// A message was signed by a key which is not among the current authorities
+	UnknownSigner(String),
 	/// We failed to serialize a message
 	SerilizationFailure(String),
 	/// We failed to sign a message
@@ -17,6 +19,14 @@ pub enum Error {
 	Database(String),
 	/// The current node is not a validator
 	NotAValidator,
+	/// The event was already validated
+	AlreadyValidated,
+	/// The block with the given number is unknown to the node, or its body was pruned
+	BlockUnavailable(u32),
+	/// Another member of the event's conflict set was already validated
+	ConflictSetResolved,
+	/// The event conflicts with another event witnessed by the same authority
+	ConflictingVote(String),
 	/// Any other error
 	Other(String),
 }
@@ -26,10 +36,18 @@ impl fmt::Display for Error {
 			Error::LockFail(r) => write!(f, "Failed locking resource {r}"),
 			Error::BadWitnessedEventSignature(source) =>
 				write!(f, "Received bad witnessed event signature from {source}"),
+			Error::UnknownSigner(source) =>
+				write!(f, "Received {source} signed by a non-authority"),
 			Error::SerilizationFailure(reason) => write!(f, "Serialization failed due to {reason}"),
 			Error::SigningFailure(reason) => write!(f, "Signing failed due to {reason}"),
 			Error::Database(reason) => write!(f, "Database error, {reason}"),
 			Error::NotAValidator => write!(f, "Not a validator"),
+			Error::AlreadyValidated => write!(f, "Event already validated"),
+			Error::BlockUnavailable(number) =>
+				write!(f, "Block #{number} is not available (was it pruned?)"),
+			Error::ConflictSetResolved =>
+				write!(f, "Another member of the conflict set was already validated"),
+			Error::ConflictingVote(reason) => write!(f, "Conflicting vote, {reason}"),
 			Error::Other(reason) => write!(f, "{reason}"),
 		}
 	}
@@ -43,6 +61,13 @@ impl From<Box<bincode::ErrorKind>> for Error {
 	}
 }
 
+#[doc(hidden)] // Enable use of `?` operator.
+impl From<codec::Error> for Error {
+	fn from(e: codec::Error) -> Error {
+		Error::SerilizationFailure(format!("{e}"))
+	}
+}
+
 #[doc(hidden)] // Enable use of `?` operator.
 impl From<sp_keystore::Error> for Error {
 	fn from(e: sp_keystore::Error) -> Error {
@@ -0,0 +1,94 @@
+//! Batching of the events witnessed by the local node into single gossip messages
+
+use super::{GossipMessage, PeerVersions};
+use crate::{errors::Error, gossip::Gossip, proofs::WitnessedEvent};
+use futures::{
+	channel::mpsc::{channel, Receiver, Sender},
+	SinkExt, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+/// How many events to batch at most by default. Batches are gossiped as SCALE-encoded
+/// [pallet_validated_streams::WitnessProof]-s, each taking up to 193 bytes (two 32-byte ids, an
+/// optional 32-byte conflict set, a 32-byte key and a 64-byte signature), behind a 12-byte
+/// envelope. A batch of 128 is thus about 25 KB, well within gossipsub's default maximum message
+/// size of 64 KiB, which fits up to about 330 proofs after gossipsub's own framing.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 128;
+
+/// An envelope for multiple [WitnessedEvent]-s gossiped as a single message. Each event carries
+/// its own signature, and is verified separately when the batch is unpacked.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WitnessedEventBatch {
+	/// The events in the batch
+	pub events: Vec<WitnessedEvent>,
+}
+
+/// Accumulates the events witnessed by the local node and gossips them in batches, trading a bit
+/// of latency for less per-message overhead at high event rates. Cloning it is safe and reuses
+/// the same batches.
+#[derive(Clone)]
+pub struct WitnessBatcher {
+	tx: Sender<WitnessedEvent>,
+}
+
+/// A handle used to start a [WitnessBatcher].
+#[must_use]
+pub struct WitnessBatcherService {
+	rc: Receiver<WitnessedEvent>,
+	gossip: Gossip,
+	peer_versions: Arc<PeerVersions>,
+	window: Duration,
+	max_batch_size: usize,
+}
+
+impl WitnessBatcher {
+	/// Creates a new [WitnessBatcher] and a [WitnessBatcherService] that can be used to start it.
+	/// A batch is gossiped once `window` has passed since its first event was added, or once it
+	/// holds `max_batch_size` events, whichever comes first.
+	pub fn create(
+		gossip: Gossip,
+		peer_versions: Arc<PeerVersions>,
+		window: Duration,
+		max_batch_size: usize,
+	) -> (Self, WitnessBatcherService) {
+		let (tx, rc) = channel(max_batch_size);
+		(Self { tx }, WitnessBatcherService { rc, gossip, peer_versions, window, max_batch_size })
+	}
+
+	/// Adds a witnessed event to the current batch.
+	pub async fn add(&self, witnessed_event: WitnessedEvent) -> Result<(), Error> {
+		self.tx
+			.clone()
+			.send(witnessed_event)
+			.await
+			.map_err(|e| Error::Other(format!("Failed batching witnessed event: {e}")))
+	}
+}
+
+impl WitnessBatcherService {
+	/// Starts gossiping batches. This function only returns once all [WitnessBatcher]-s are
+	/// dropped, so make sure to spawn it as a separate task.
+	pub async fn run(self) {
+		let Self { mut rc, mut gossip, peer_versions, window, max_batch_size } = self;
+		while let Some(first_event) = rc.next().await {
+			let mut events = vec![first_event];
+			let deadline = tokio::time::Instant::now() + window;
+			while events.len() < max_batch_size {
+				match tokio::time::timeout_at(deadline, rc.next()).await {
+					Ok(Some(witnessed_event)) => events.push(witnessed_event),
+					Ok(None) | Err(_) => break,
+				}
+			}
+
+			let count = events.len();
+			match GossipMessage::WitnessedEventBatch(WitnessedEventBatch { events })
+				.publish(&mut gossip, &peer_versions)
+				.await
+			{
+				Ok(()) => log::trace!("Gossiped a batch of {count} witnessed events"),
+				Err(e) => log::error!("Failed serializing batch of witnessed events: {e}"),
+			}
+		}
+	}
+}
@@ -0,0 +1,179 @@
+//! Catching up on the proofs of events witnessed while the node was offline
+
+use super::{verify_events_validity, BlockStateCache, GossipMessage, MIN_GOSSIP_VERSION};
+use crate::{errors::Error, gossip::Gossip, proofs::EventProofsTrait};
+use codec::Codec;
+use futures::{
+	channel::mpsc::{channel, Receiver, Sender},
+	StreamExt,
+};
+use pallet_validated_streams::ValidatedStreamsApi;
+use sc_client_api::{BlockBackend, HeaderBackend};
+use serde::{Deserialize, Serialize};
+use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
+use sp_consensus_aura::AuraApi;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{sync::Arc, time::Duration};
+
+/// The most events whose proofs are asked for in a single [CatchUpRequest].
+pub const MAX_CATCH_UP_IDS: usize = 128;
+
+/// The most proofs sent back in response to a single [CatchUpRequest].
+pub const MAX_CATCH_UP_PROOFS: usize = 4096;
+
+/// How many unfinalized blocks to look for missing proofs in on startup.
+const MAX_CATCH_UP_BLOCKS: usize = 256;
+
+/// How long to wait on startup before asking peers for missing proofs, so that the gossip has
+/// connected to them.
+const CATCH_UP_STARTUP_DELAY: Duration = Duration::from_secs(10);
+
+/// How many reports of missing proofs to buffer before dropping further ones.
+const MISSING_PROOFS_CAPACITY: usize = 64;
+
+/// A request for every proof a peer holds for the given events, answered with a
+/// [super::WitnessedEventBatch]. Both are sent as [GossipMessage]-s.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CatchUpRequest {
+	/// The (stream id, event id) pairs of the events
+	pub ids: Vec<(H256, H256)>,
+}
+
+/// Reports the events whose proofs are missing (e.g. when importing a block), so that they are
+/// requested from peers. Cloning it is safe and reports to the same receiver.
+#[derive(Clone)]
+pub struct MissingProofs {
+	tx: Sender<Vec<(H256, H256)>>,
+}
+
+/// The receiving end of [MissingProofs], consumed by [run_catch_up].
+pub struct MissingProofsReceiver {
+	rc: Receiver<Vec<(H256, H256)>>,
+}
+
+/// Creates a connected [MissingProofs] and [MissingProofsReceiver] pair.
+pub fn missing_proofs_channel() -> (MissingProofs, MissingProofsReceiver) {
+	let (tx, rc) = channel(MISSING_PROOFS_CAPACITY);
+	(MissingProofs { tx }, MissingProofsReceiver { rc })
+}
+
+impl MissingProofs {
+	/// Reports events whose proofs are missing. Never waits; reports are dropped if too many are
+	/// pending already.
+	pub fn report(&self, ids: Vec<(H256, H256)>) {
+		if let Err(e) = self.tx.clone().try_send(ids) {
+			log::debug!("Dropping report of missing proofs: {e}");
+		}
+	}
+}
+
+/// Asks peers for the proofs of events in unfinalized blocks which we lack proofs for, and then
+/// for the proofs of any events reported through [MissingProofs]. Responses are processed by the
+/// [super::EventGossipHandler]. This function only returns once all [MissingProofs] are dropped,
+/// so make sure to spawn it as a separate task.
+pub async fn run_catch_up<Block, Client, EventProofs, AuthorityId>(
+	client: Arc<Client>,
+	event_proofs: Arc<EventProofs>,
+	block_state: BlockStateCache<Block>,
+	mut gossip: Gossip,
+	missing_proofs: MissingProofsReceiver,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block>
+		+ BlockBackend<Block>
+		+ ProvideRuntimeApi<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	EventProofs: EventProofsTrait + Send + Sync,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+{
+	tokio::time::sleep(CATCH_UP_STARTUP_DELAY).await;
+	match find_unwitnessed_events::<Block, Client, EventProofs, AuthorityId>(
+		&client,
+		&event_proofs,
+		&block_state,
+	) {
+		Ok(ids) => request_proofs(&mut gossip, ids).await,
+		Err(e) => log::warn!("Failed looking for unwitnessed events: {e}"),
+	}
+
+	let mut rc = missing_proofs.rc;
+	while let Some(ids) = rc.next().await {
+		request_proofs(&mut gossip, ids).await;
+	}
+}
+
+/// Sends [CatchUpRequest]-s for the given events, in chunks of [MAX_CATCH_UP_IDS].
+async fn request_proofs(gossip: &mut Gossip, ids: Vec<(H256, H256)>) {
+	if ids.is_empty() {
+		return
+	}
+	log::info!("Asking peers for the proofs of {} events", ids.len());
+	for chunk in ids.chunks(MAX_CATCH_UP_IDS) {
+		// Requests are answered in the version they are sent in, so ask in one every peer supports
+		let request = GossipMessage::CatchUpRequest(CatchUpRequest { ids: chunk.to_vec() });
+		match request.encode_as(MIN_GOSSIP_VERSION) {
+			Ok(request) => gossip.request_from_peers(request).await,
+			Err(e) => log::error!("Failed serializing catch-up request: {e}"),
+		}
+	}
+}
+
+/// Returns the events in the unfinalized blocks of the best chain which do not have enough
+/// proofs.
+fn find_unwitnessed_events<Block, Client, EventProofs, AuthorityId>(
+	client: &Arc<Client>,
+	event_proofs: &Arc<EventProofs>,
+	block_state: &BlockStateCache<Block>,
+) -> Result<Vec<(H256, H256)>, Error>
+where
+	Block: BlockT,
+	Client: HeaderBackend<Block>
+		+ BlockBackend<Block>
+		+ ProvideRuntimeApi<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	EventProofs: EventProofsTrait + Send + Sync,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+{
+	let info = client.info();
+	let mut block_hash = info.best_hash;
+	let mut unwitnessed_ids = Vec::new();
+	for _ in 0..MAX_CATCH_UP_BLOCKS {
+		if block_hash == info.finalized_hash {
+			break
+		}
+		let parent_hash =
+			match client.header(block_hash).map_err(|e| Error::Other(e.to_string()))? {
+				Some(header) => *header.parent_hash(),
+				None => break,
+			};
+		if let Some(block_extrinsics) =
+			client.block_body(block_hash).map_err(|e| Error::Other(e.to_string()))?
+		{
+			let extrinsic_ids =
+				client.runtime_api().get_extrinsic_ids(parent_hash, &block_extrinsics)?;
+			unwitnessed_ids.extend(verify_events_validity::<
+				Block,
+				EventProofs,
+				Client,
+				AuthorityId,
+			>(
+				block_state.clone(),
+				client.clone(),
+				parent_hash,
+				event_proofs.clone(),
+				extrinsic_ids,
+			)?);
+		}
+		block_hash = parent_hash;
+	}
+	Ok(unwitnessed_ids)
+}
@@ -0,0 +1,103 @@
+//! Tracking of the votes for the alternatives of conflict sets
+
+use crate::errors::Error;
+use lru::LruCache;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{collections::HashMap, num::NonZeroUsize, sync::Mutex};
+
+/// How many conflict sets (and conflict set members) to keep track of by default.
+pub const DEFAULT_CONFLICT_SETS_CAPACITY: usize = 4096;
+
+/// Tracks which alternative (event) of each conflict set every authority has witnessed. A conflict
+/// set groups mutually exclusive events; an honest authority witnesses at most one of them, so
+/// that a network voting on A or B finalizes A, B, or neither (see
+/// [super::AuthoritiesList::target]). Only the most recently used conflict sets are kept in memory.
+pub struct ConflictSetVotes {
+	inner: Mutex<ConflictSetVotesInner>,
+}
+
+struct ConflictSetVotesInner {
+	/// The alternative each authority voted for, keyed by (stream id, conflict set id).
+	votes: LruCache<(H256, H256), HashMap<CryptoTypePublicPair, H256>>,
+	/// The conflict set of each event witnessed as a member of one, keyed by (stream id, event
+	/// id).
+	members: LruCache<(H256, H256), H256>,
+}
+
+impl ConflictSetVotes {
+	/// Creates a new [ConflictSetVotes], tracking up to `capacity` conflict sets.
+	pub fn new(capacity: NonZeroUsize) -> Self {
+		Self {
+			inner: Mutex::new(ConflictSetVotesInner {
+				votes: LruCache::new(capacity),
+				members: LruCache::new(capacity),
+			}),
+		}
+	}
+
+	/// Records that an authority witnessed an event as a member of a conflict set. Fails with
+	/// [Error::ConflictingVote] if the authority already witnessed another member of the set, or if
+	/// the event was already witnessed as a member of another set; recording the same vote twice is
+	/// fine.
+	pub fn record(
+		&self,
+		stream_id: H256,
+		conflict_set: H256,
+		event_id: H256,
+		authority: &CryptoTypePublicPair,
+	) -> Result<(), Error> {
+		let mut inner = self.inner.lock()?;
+
+		if let Some(existing_set) = inner.members.get(&(stream_id, event_id)) {
+			if *existing_set != conflict_set {
+				return Err(Error::ConflictingVote(format!(
+					"event {event_id} is already a member of conflict set {existing_set}"
+				)))
+			}
+		}
+
+		if let Some(votes) = inner.votes.get_mut(&(stream_id, conflict_set)) {
+			match votes.get(authority) {
+				Some(voted_event_id) if *voted_event_id != event_id =>
+					return Err(Error::ConflictingVote(format!(
+						"authority already witnessed {voted_event_id} in conflict set {conflict_set}"
+					))),
+				Some(_) => {},
+				None => {
+					votes.insert(authority.clone(), event_id);
+				},
+			}
+		} else {
+			inner
+				.votes
+				.put((stream_id, conflict_set), HashMap::from([(authority.clone(), event_id)]));
+		}
+		inner.members.put((stream_id, event_id), conflict_set);
+
+		Ok(())
+	}
+
+	/// Returns the conflict set an event was witnessed as a member of, if known.
+	pub fn conflict_set_of(&self, stream_id: H256, event_id: H256) -> Result<Option<H256>, Error> {
+		Ok(self.inner.lock()?.members.peek(&(stream_id, event_id)).cloned())
+	}
+
+	/// Returns the number of authorities which witnessed each member of a conflict set.
+	pub fn tally(&self, stream_id: H256, conflict_set: H256) -> Result<HashMap<H256, u16>, Error> {
+		let inner = self.inner.lock()?;
+		let mut tally = HashMap::new();
+		if let Some(votes) = inner.votes.peek(&(stream_id, conflict_set)) {
+			for event_id in votes.values() {
+				*tally.entry(*event_id).or_default() += 1;
+			}
+		}
+		Ok(tally)
+	}
+}
+
+impl Default for ConflictSetVotes {
+	fn default() -> Self {
+		Self::new(NonZeroUsize::new(DEFAULT_CONFLICT_SETS_CAPACITY).unwrap())
+	}
+}
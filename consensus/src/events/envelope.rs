@@ -0,0 +1,227 @@
+//! The versioned envelope gossip messages are wrapped in
+
+use super::{CatchUpRequest, FeedReport, WitnessedEventBatch};
+use crate::{
+	errors::Error,
+	gossip::Gossip,
+	proofs::{WitnessProof, WitnessedEvent},
+};
+use codec::{Decode, Encode};
+use libp2p::gossipsub::IdentTopic;
+use serde::{Deserialize, Serialize};
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// The topic on which all [GossipMessage]-s are gossiped.
+pub const GOSSIP_TOPIC: &str = "ValidatedStreams";
+
+/// The newest version of the [GossipMessage] encoding, produced by this node once all its peers
+/// support it (see [PeerVersions]). It is only increased for changes that older nodes cannot
+/// decode; fields appended at the end of the envelope are ignored by older nodes and need no new
+/// version.
+///
+/// Since version 2, proofs of events are SCALE-encoded [WitnessProof]-s, as understood by the
+/// pallet, instead of bincode-encoded [WitnessedEvent]-s.
+pub const GOSSIP_VERSION: u16 = 2;
+
+/// The oldest [GossipMessage] encoding this node can decode.
+pub const MIN_GOSSIP_VERSION: u16 = 1;
+
+/// The first version in which proofs of events are SCALE-encoded.
+const SCALE_PROOFS_VERSION: u16 = 2;
+
+/// How often nodes announce the gossip versions they support.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long proofs keep being gossiped in [MIN_GOSSIP_VERSION] after the last heartbeat of a peer
+/// that does not support the current [GOSSIP_VERSION] (or after startup).
+pub const LEGACY_PEER_TIMEOUT: Duration = Duration::from_secs(3 * HEARTBEAT_INTERVAL.as_secs());
+
+/// The kinds of [GossipMessage]; new kinds get new numbers, so that older nodes can skip them.
+mod kind {
+	pub const WITNESSED_EVENT: u16 = 0;
+	pub const WITNESSED_EVENT_BATCH: u16 = 1;
+	pub const CATCH_UP_REQUEST: u16 = 2;
+	pub const HEARTBEAT: u16 = 3;
+	pub const FEED_REPORT: u16 = 4;
+}
+
+/// The wire format of a [GossipMessage]: a version and a kind, followed by the message itself.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	version: u16,
+	kind: u16,
+	payload: Vec<u8>,
+}
+
+/// An announcement of the [GossipMessage] versions a node supports, so that operators can tell
+/// when peers are running newer versions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+	/// The oldest version the node can decode
+	pub min_version: u16,
+	/// The version the node produces
+	pub max_version: u16,
+}
+
+impl Default for Heartbeat {
+	fn default() -> Self {
+		Self { min_version: MIN_GOSSIP_VERSION, max_version: GOSSIP_VERSION }
+	}
+}
+
+/// Tracks, from their [Heartbeat]-s, whether peers that only support older [GossipMessage]
+/// versions are still around, so that proofs are gossiped in a version every peer can decode
+/// while validators are upgraded one by one.
+pub struct PeerVersions {
+	last_legacy_heartbeat: Mutex<Instant>,
+}
+
+impl Default for PeerVersions {
+	/// Until the first heartbeats arrive, peers are assumed to run older versions.
+	fn default() -> Self {
+		Self { last_legacy_heartbeat: Mutex::new(Instant::now()) }
+	}
+}
+
+impl PeerVersions {
+	/// Records the versions a peer announced.
+	pub fn note_heartbeat(&self, heartbeat: &Heartbeat) {
+		if heartbeat.max_version < GOSSIP_VERSION {
+			if let Ok(mut last_legacy_heartbeat) = self.last_legacy_heartbeat.lock() {
+				*last_legacy_heartbeat = Instant::now();
+			}
+		}
+	}
+
+	/// Returns the version to gossip proofs of events in: [MIN_GOSSIP_VERSION] if a peer that does
+	/// not support [GOSSIP_VERSION] was heard from within [LEGACY_PEER_TIMEOUT], or the current
+	/// version otherwise.
+	pub fn proofs_version(&self) -> u16 {
+		let legacy_peers =
+			self.last_legacy_heartbeat.lock().map_or(true, |last_legacy_heartbeat| {
+				last_legacy_heartbeat.elapsed() < LEGACY_PEER_TIMEOUT
+			});
+		if legacy_peers {
+			MIN_GOSSIP_VERSION
+		} else {
+			GOSSIP_VERSION
+		}
+	}
+}
+
+/// A message gossiped between validators, or sent in a catch-up request or response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GossipMessage {
+	/// A single proof of an event
+	WitnessedEvent(WitnessedEvent),
+	/// Multiple proofs of events
+	WitnessedEventBatch(WitnessedEventBatch),
+	/// A request for the proofs of events
+	CatchUpRequest(CatchUpRequest),
+	/// An announcement of the versions a node supports
+	Heartbeat(Heartbeat),
+	/// A value reported for a round of a feed
+	FeedReport(FeedReport),
+}
+
+impl GossipMessage {
+	/// Encodes the message in the current [GOSSIP_VERSION].
+	pub fn encode(&self) -> Result<Vec<u8>, Error> {
+		self.encode_as(GOSSIP_VERSION)
+	}
+
+	/// Encodes the message in the given version, between [MIN_GOSSIP_VERSION] and
+	/// [GOSSIP_VERSION].
+	pub fn encode_as(&self, version: u16) -> Result<Vec<u8>, Error> {
+		let scale_proofs = version >= SCALE_PROOFS_VERSION;
+		let (kind, payload) = match self {
+			Self::WitnessedEvent(message) if scale_proofs =>
+				(kind::WITNESSED_EVENT, message.to_proof()?.encode()),
+			Self::WitnessedEvent(message) => (kind::WITNESSED_EVENT, bincode::serialize(message)?),
+			Self::WitnessedEventBatch(message) if scale_proofs =>
+				(kind::WITNESSED_EVENT_BATCH, WitnessedEvent::encode_proofs(&message.events)?),
+			Self::WitnessedEventBatch(message) =>
+				(kind::WITNESSED_EVENT_BATCH, bincode::serialize(message)?),
+			Self::CatchUpRequest(message) => (kind::CATCH_UP_REQUEST, bincode::serialize(message)?),
+			Self::Heartbeat(message) => (kind::HEARTBEAT, bincode::serialize(message)?),
+			Self::FeedReport(message) => (kind::FEED_REPORT, bincode::serialize(message)?),
+		};
+		Ok(bincode::serialize(&Envelope { version, kind, payload })?)
+	}
+
+	/// Decodes a message. Messages of versions or kinds this node does not know of, as sent by
+	/// newer nodes, are skipped by returning [None]; only malformed messages are errors.
+	pub fn decode(bytes: &[u8]) -> Result<Option<Self>, Error> {
+		Ok(Self::decode_versioned(bytes)?.map(|(_, message)| message))
+	}
+
+	/// Decodes a message like [GossipMessage::decode], also returning the version it was encoded
+	/// in.
+	pub fn decode_versioned(bytes: &[u8]) -> Result<Option<(u16, Self)>, Error> {
+		let envelope = bincode::deserialize::<Envelope>(bytes)?;
+		if !(MIN_GOSSIP_VERSION..=GOSSIP_VERSION).contains(&envelope.version) {
+			log::debug!("Skipping gossip message of unsupported version {}", envelope.version);
+			return Ok(None)
+		}
+		let payload = envelope.payload.as_slice();
+		let scale_proofs = envelope.version >= SCALE_PROOFS_VERSION;
+		let message = match envelope.kind {
+			kind::WITNESSED_EVENT if scale_proofs =>
+				Self::WitnessedEvent(WitnessProof::decode(&mut &*payload)?.into()),
+			kind::WITNESSED_EVENT => Self::WitnessedEvent(bincode::deserialize(payload)?),
+			kind::WITNESSED_EVENT_BATCH if scale_proofs =>
+				Self::WitnessedEventBatch(WitnessedEventBatch {
+					events: WitnessedEvent::decode_proofs(payload)?,
+				}),
+			kind::WITNESSED_EVENT_BATCH =>
+				Self::WitnessedEventBatch(bincode::deserialize(payload)?),
+			kind::CATCH_UP_REQUEST => Self::CatchUpRequest(bincode::deserialize(payload)?),
+			kind::HEARTBEAT => Self::Heartbeat(bincode::deserialize(payload)?),
+			kind::FEED_REPORT => Self::FeedReport(bincode::deserialize(payload)?),
+			kind => {
+				log::debug!("Skipping gossip message of unknown kind {kind}");
+				return Ok(None)
+			},
+		};
+		Ok(Some((envelope.version, message)))
+	}
+
+	/// Returns the version the message is gossiped in. Only proofs changed encoding in
+	/// [GOSSIP_VERSION], so other messages are always sent in [MIN_GOSSIP_VERSION], which every
+	/// peer can decode.
+	pub fn gossip_version(&self, peer_versions: &PeerVersions) -> u16 {
+		match self {
+			Self::WitnessedEvent(_) | Self::WitnessedEventBatch(_) =>
+				peer_versions.proofs_version(),
+			_ => MIN_GOSSIP_VERSION,
+		}
+	}
+
+	/// Encodes the message in a version all peers can decode (see [PeerVersions]) and publishes
+	/// it to the [Gossip] on the [GOSSIP_TOPIC].
+	pub async fn publish(
+		&self,
+		gossip: &mut Gossip,
+		peer_versions: &PeerVersions,
+	) -> Result<(), Error> {
+		let message = self.encode_as(self.gossip_version(peer_versions))?;
+		gossip.publish(IdentTopic::new(GOSSIP_TOPIC), message).await;
+		Ok(())
+	}
+}
+
+/// Periodically announces the gossip versions this node supports. This function never returns,
+/// so make sure to spawn it as a separate task.
+pub async fn send_heartbeats(mut gossip: Gossip) {
+	let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+	loop {
+		interval.tick().await;
+		match GossipMessage::Heartbeat(Heartbeat::default()).encode_as(MIN_GOSSIP_VERSION) {
+			Ok(heartbeat) => gossip.publish(IdentTopic::new(GOSSIP_TOPIC), heartbeat).await,
+			Err(e) => log::error!("Failed sending gossip heartbeat: {e}"),
+		}
+	}
+}
@@ -0,0 +1,89 @@
+//! Collection of the values authorities report for numeric feeds
+
+use crate::errors::Error;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{collections::HashMap, num::NonZeroUsize, sync::Mutex};
+
+/// How many feed rounds to collect reports for by default.
+pub const DEFAULT_FEED_ROUNDS_CAPACITY: usize = 1024;
+
+/// A value of a feed (e.g. a price or a temperature) reported by an authority for a round; the
+/// numeric counterpart of a [crate::proofs::WitnessedEvent]. Signatures are assumed to be sr25519
+/// signatures of [pallet_validated_streams::feed_report_message].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeedReport {
+	/// The signature of the report
+	pub signature: Vec<u8>,
+	/// The public key which was used to produce the signature
+	pub pub_key: CryptoTypePublicPair,
+	/// The id of the feed
+	pub feed_id: H256,
+	/// The round the value is reported for, e.g. a timestamp or a sequence number
+	pub round: u64,
+	/// The reported value
+	pub value: i64,
+}
+
+/// Collects the [FeedReport]-s received for each round of each feed, until enough authorities have
+/// reported for the median to be computed on-chain. Only the most recently used rounds are kept
+/// in memory.
+pub struct FeedReports {
+	reports: Mutex<LruCache<(H256, u64), HashMap<CryptoTypePublicPair, (i64, Vec<u8>)>>>,
+}
+
+impl FeedReports {
+	/// Creates a new [FeedReports], collecting reports for up to `capacity` rounds.
+	pub fn new(capacity: NonZeroUsize) -> Self {
+		Self { reports: Mutex::new(LruCache::new(capacity)) }
+	}
+
+	/// Stores a report. Fails with [Error::ConflictingVote] if the authority already reported a
+	/// different value for the same round; reporting the same value twice is fine.
+	pub fn add_report(&self, report: &FeedReport) -> Result<(), Error> {
+		let mut reports = self.reports.lock()?;
+		let round_reports =
+			reports.get_or_insert_mut((report.feed_id, report.round), Default::default);
+		match round_reports.get(&report.pub_key) {
+			Some((value, _)) if *value != report.value => Err(Error::ConflictingVote(format!(
+				"authority already reported {value} for round {} of feed {}",
+				report.round, report.feed_id
+			))),
+			Some(_) => Ok(()),
+			None => {
+				round_reports
+					.insert(report.pub_key.clone(), (report.value, report.signature.clone()));
+				Ok(())
+			},
+		}
+	}
+
+	/// Returns the reported values and their signatures for a round of a feed, keeping only those
+	/// of the given authorities.
+	pub fn get_reports(
+		&self,
+		feed_id: &H256,
+		round: u64,
+		authorities: &[CryptoTypePublicPair],
+	) -> Result<HashMap<CryptoTypePublicPair, (i64, Vec<u8>)>, Error> {
+		let reports = self.reports.lock()?;
+		Ok(reports
+			.peek(&(*feed_id, round))
+			.map(|round_reports| {
+				round_reports
+					.iter()
+					.filter(|(key, _)| authorities.contains(key))
+					.map(|(key, report)| (key.clone(), report.clone()))
+					.collect()
+			})
+			.unwrap_or_default())
+	}
+}
+
+impl Default for FeedReports {
+	fn default() -> Self {
+		Self::new(NonZeroUsize::new(DEFAULT_FEED_ROUNDS_CAPACITY).unwrap())
+	}
+}
@@ -1,14 +1,18 @@
 //! Service which processes all the incoming events
 
-use super::{get_latest_authorities_list, BlockStateCache};
+use super::{
+	get_latest_authorities_list, BlockStateCache, CatchUpRequest, ConflictSetVotes, EventUpdate,
+	EventUpdates, FeedReport, FeedReports, GossipMessage, Heartbeat, PeerVersions,
+	WitnessedEventBatch, GOSSIP_TOPIC, GOSSIP_VERSION, MAX_CATCH_UP_IDS, MAX_CATCH_UP_PROOFS,
+};
 use crate::{
 	errors::Error,
-	gossip::GossipHandler,
-	proofs::{EventProofsTrait, WitnessedEvent},
+	gossip::{GossipHandler, MessageVerdict},
+	proofs::{EventProofsTrait, WitnessProof, WitnessedEvent},
 };
 use async_trait::async_trait;
 use codec::Codec;
-use libp2p::gossipsub::IdentTopic;
+use libp2p::gossipsub::{IdentTopic, TopicHash};
 use pallet_validated_streams::ValidatedStreamsApi;
 use sc_client_api::{BlockchainEvents, HeaderBackend};
 use sc_transaction_pool_api::{
@@ -24,18 +28,56 @@ use sp_core::{
 use sp_runtime::{
 	app_crypto::CryptoTypePublicPair, generic::BlockId, transaction_validity::InvalidTransaction,
 };
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashMap},
+	marker::PhantomData,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
 
-/// The topic on which the [EventGossipHandler] listens.
-pub const WITNESSED_EVENTS_TOPIC: &str = "WitnessedEvent";
+/// Decides whether a gossiped message is relayed further, from the outcome of processing it.
+fn message_verdict<T>(result: Result<T, Error>, kind: &str) -> MessageVerdict {
+	match result {
+		Ok(_) => MessageVerdict::Accept,
+		// Proofs of finalized events, or of events whose conflict set was resolved, are stale
+		Err(e @ (Error::AlreadyValidated | Error::ConflictSetResolved)) => {
+			log::debug!("Ignoring stale {kind}: {e}");
+			MessageVerdict::Ignore
+		},
+		// Signed by a key which is not an authority as far as we know; our view of the authority
+		// set may lag behind that of the peer, so the message is not held against it
+		Err(e @ Error::UnknownSigner(_)) => {
+			log::debug!("Ignoring {kind}: {e}");
+			MessageVerdict::Ignore
+		},
+		// Badly signed or malformed
+		Err(e @ (Error::BadWitnessedEventSignature(_) | Error::SerilizationFailure(_))) => {
+			log::warn!("Rejecting invalid {kind}: {e}");
+			MessageVerdict::Reject
+		},
+		Err(e) => {
+			log::error!("failed processing {kind}: {:?}", e);
+			MessageVerdict::Ignore
+		},
+	}
+}
 
 /// Service that handles incoming gossip, maintains the [EventProofs] storage,
 /// and submits extrinsics for proofs that we have collected the necessary signatures for.
+/// Likewise, it collects [FeedReport]-s and submits the signed values of a feed round once enough
+/// authorities have reported, for the pallet to store their median.
 pub struct EventGossipHandler<TxPool, Client, EventProofs, AuthorityId, Block: BlockT> {
 	event_proofs: Arc<EventProofs>,
 	tx_pool: Arc<TxPool>,
 	client: Arc<Client>,
 	block_state: BlockStateCache<Block>,
+	event_updates: EventUpdates,
+	conflict_votes: Arc<ConflictSetVotes>,
+	feed_reports: FeedReports,
+	peer_versions: Arc<PeerVersions>,
+	newer_version_warned: AtomicBool,
 	phantom: PhantomData<AuthorityId>,
 }
 
@@ -55,17 +97,148 @@ where
 	Block: BlockT,
 	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
 {
-	/// The topic on which the [EventGossipHandler] listens.
-	pub const WITNESSED_EVENTS_TOPIC: &str = "WitnessedEvent";
-
 	/// Creates a new EventGossipHandler
 	pub fn new(
 		client: Arc<Client>,
 		event_proofs: Arc<EventProofs>,
 		tx_pool: Arc<TxPool>,
 		block_state: BlockStateCache<Block>,
+		event_updates: EventUpdates,
+		conflict_votes: Arc<ConflictSetVotes>,
+		peer_versions: Arc<PeerVersions>,
 	) -> Self {
-		Self { client, event_proofs, tx_pool, phantom: PhantomData, block_state }
+		Self {
+			client,
+			event_proofs,
+			tx_pool,
+			phantom: PhantomData,
+			block_state,
+			event_updates,
+			conflict_votes,
+			feed_reports: FeedReports::default(),
+			peer_versions,
+			newer_version_warned: AtomicBool::new(false),
+		}
+	}
+
+	/// Handles a [GossipMessage] received on the [GOSSIP_TOPIC], returning whether it should be
+	/// relayed further.
+	async fn handle_gossip_message(&self, message: GossipMessage) -> MessageVerdict {
+		match message {
+			GossipMessage::WitnessedEvent(witnessed_event) =>
+				message_verdict(self.handle_witnessed_event(witnessed_event).await, "message"),
+			GossipMessage::WitnessedEventBatch(batch) => {
+				let mut verdict = MessageVerdict::Ignore;
+				for witnessed_event in batch.events {
+					let result = self.handle_witnessed_event(witnessed_event).await;
+					verdict = verdict.combine(message_verdict(result, "batched message"));
+				}
+				verdict
+			},
+			GossipMessage::FeedReport(report) =>
+				message_verdict(self.handle_feed_report(report).await, "feed report"),
+			GossipMessage::Heartbeat(heartbeat) => {
+				self.handle_heartbeat(heartbeat);
+				MessageVerdict::Accept
+			},
+			GossipMessage::CatchUpRequest(_) => {
+				log::debug!("Ignoring catch-up request gossiped instead of sent to a peer");
+				MessageVerdict::Ignore
+			},
+		}
+	}
+
+	/// Checks a [GossipMessage] without processing it; see [GossipHandler::validate].
+	fn validate_gossip_message(&self, message: GossipMessage) -> MessageVerdict {
+		match message {
+			GossipMessage::WitnessedEvent(witnessed_event) =>
+				message_verdict(self.check_witnessed_event(witnessed_event), "message"),
+			GossipMessage::WitnessedEventBatch(batch) =>
+				batch
+					.events
+					.into_iter()
+					.fold(MessageVerdict::Ignore, |verdict, witnessed_event| {
+						let result = self.check_witnessed_event(witnessed_event);
+						verdict.combine(message_verdict(result, "batched message"))
+					}),
+			GossipMessage::FeedReport(report) => message_verdict(
+				get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())
+					.and_then(|block_state| block_state.verify_feed_report_origin(report)),
+				"feed report",
+			),
+			GossipMessage::Heartbeat(_) => MessageVerdict::Accept,
+			GossipMessage::CatchUpRequest(_) => MessageVerdict::Ignore,
+		}
+	}
+
+	/// Checks that a witnessed event was signed by a current authority, and that the event was not
+	/// finalized already.
+	fn check_witnessed_event(
+		&self,
+		witnessed_event: WitnessedEvent,
+	) -> Result<WitnessedEvent, Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+		let witnessed_event = block_state.verify_witnessed_event_origin(witnessed_event)?;
+
+		let finalized_hash = self.client.info().finalized_hash;
+		if self
+			.client
+			.runtime_api()
+			.get_event_block(finalized_hash, witnessed_event.stream_id, witnessed_event.event_id)?
+			.is_some()
+		{
+			return Err(Error::AlreadyValidated)
+		}
+		Ok(witnessed_event)
+	}
+
+	/// Records the versions a peer supports in the [PeerVersions], and warns (once) when a peer
+	/// announces a [GossipMessage] version newer than ours, as messages of that version are
+	/// skipped until this node is upgraded.
+	fn handle_heartbeat(&self, heartbeat: Heartbeat) {
+		self.peer_versions.note_heartbeat(&heartbeat);
+		if heartbeat.max_version > GOSSIP_VERSION &&
+			!self.newer_version_warned.swap(true, Ordering::Relaxed)
+		{
+			log::warn!(
+				"A peer gossips messages of version {}, but this node only supports up to version {}; consider upgrading",
+				heartbeat.max_version,
+				GOSSIP_VERSION
+			);
+		}
+	}
+
+	/// Checks that a witnessed event agrees with the conflict sets we know of, and records the
+	/// authority's vote for its alternative. An authority voting for two members of the same set,
+	/// or an event witnessed both with and without a set, is rejected.
+	fn record_conflict_set_vote(&self, witnessed_event: &WitnessedEvent) -> Result<(), Error> {
+		let WitnessedEvent { stream_id, event_id, conflict_set, .. } = *witnessed_event;
+		match conflict_set {
+			Some(conflict_set) => {
+				self.conflict_votes.record(
+					stream_id,
+					conflict_set,
+					event_id,
+					&witnessed_event.pub_key,
+				)?;
+				log::trace!(
+					"Votes in conflict set {} of stream {}: {:?}",
+					conflict_set,
+					stream_id,
+					self.conflict_votes.tally(stream_id, conflict_set)?
+				);
+			},
+			None =>
+				if let Some(conflict_set) =
+					self.conflict_votes.conflict_set_of(stream_id, event_id)?
+				{
+					return Err(Error::ConflictingVote(format!(
+						"event {event_id} was witnessed without its conflict set {conflict_set}"
+					)))
+				},
+		}
+		Ok(())
 	}
 
 	/// every incoming WitnessedEvent event should go through this function for processing the
@@ -73,39 +246,61 @@ where
 	/// and if its not already added it checks whether it reached the required target or not, if it
 	/// did it submits it to the transaction pool
 	async fn handle_witnessed_event(&self, witnessed_event: WitnessedEvent) -> Result<bool, Error> {
+		let witnessed_event = self.check_witnessed_event(witnessed_event)?;
 		let block_state =
 			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
-		let witnessed_event = block_state.verify_witnessed_event_origin(witnessed_event)?;
 
-		self.event_proofs.add_event_proof(&witnessed_event)?;
+		self.record_conflict_set_vote(&witnessed_event)?;
 
-		self.event_proofs
-			.purge_event_stale_signatures(&witnessed_event.event_id, &block_state.authorities)?;
+		self.event_proofs.add_event_proof(&witnessed_event.to_proof()?)?;
 
-		let proof_count = self
-			.event_proofs
-			.get_event_proof_count(&witnessed_event.event_id, &block_state.authorities)?;
+		let WitnessedEvent { stream_id, event_id, conflict_set, .. } = witnessed_event;
+
+		self.event_proofs.purge_event_stale_signatures(
+			&stream_id,
+			&event_id,
+			&block_state.authorities,
+		)?;
+
+		let proof_count = self.event_proofs.get_event_proof_count(
+			&stream_id,
+			&event_id,
+			&block_state.authorities,
+		)?;
+
+		self.event_updates.notify(EventUpdate::ProofReceived {
+			stream_id,
+			event_id,
+			signer: witnessed_event.pub_key.clone(),
+			proof_count,
+			target: block_state.target(),
+		});
 
 		if proof_count >= block_state.target() {
 			#[cfg(feature = "off-chain-proofs")]
 			let proofs = None;
 			#[cfg(not(feature = "off-chain-proofs"))]
-			let proofs = Some(
-				self.event_proofs
-					.get_event_proofs(&witnessed_event.event_id, &block_state.authorities)?,
-			);
+			let proofs = Some(self.event_proofs.get_event_proofs(
+				&stream_id,
+				&event_id,
+				&block_state.authorities,
+			)?);
 
 			log::debug!(
-				"Event:{} has been witnessed by a majority of validators and will be added to TxPool, Current Proof count:{}",
-				witnessed_event.event_id,
+				"Event:{} in stream {} has been witnessed by a majority of validators and will be added to TxPool, Current Proof count:{}",
+				event_id,
+				stream_id,
 				proof_count
 			);
 
-			self.submit_event_extrinsic(witnessed_event.event_id, proofs).await?;
+			self.submit_event_extrinsic(stream_id, event_id, conflict_set, proofs).await?;
+
+			self.event_updates.notify(EventUpdate::Submitted { stream_id, event_id });
 		} else {
 			log::debug!(
-				"Event:{} has been added to the event proofs, Current Proof Count:{}",
-				witnessed_event.event_id,
+				"Event:{} in stream {} has been added to the event proofs, Current Proof Count:{}",
+				event_id,
+				stream_id,
 				proof_count
 			);
 		}
@@ -113,27 +308,113 @@ where
 		Ok(true)
 	}
 
-	/// create a validated streams unsigned extrinsic with the given event_id and submits it to the
-	/// transaction pool
+	/// Processes an incoming [FeedReport]: verifies it, stores it, and once reports from 2/3 of
+	/// the authorities are collected, submits all of them to the transaction pool.
+	async fn handle_feed_report(&self, report: FeedReport) -> Result<(), Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+		let report = block_state.verify_feed_report_origin(report)?;
+		let FeedReport { feed_id, round, .. } = report;
+
+		let best_hash = self.client.info().best_hash;
+		if self.client.runtime_api().get_feed_value(best_hash, feed_id, round)?.is_some() {
+			log::trace!("Ignoring report for already reported round {round} of feed {feed_id}");
+			return Ok(())
+		}
+
+		self.feed_reports.add_report(&report)?;
+
+		let reports = self.feed_reports.get_reports(&feed_id, round, &block_state.authorities)?;
+		if reports.len() < block_state.target().into() {
+			log::debug!(
+				"Feed:{} round {} has been reported by {} validators",
+				feed_id,
+				round,
+				reports.len()
+			);
+			return Ok(())
+		}
+
+		log::debug!(
+			"Feed:{} round {} has been reported by a majority of validators and will be added to TxPool",
+			feed_id,
+			round
+		);
+
+		let reports = reports
+			.into_iter()
+			.map(|(k, (value, signature))| {
+				let pubkey = Public::from_slice(k.1.as_slice()).map_err(|_| {
+					Error::SerilizationFailure("Report key is not an sr25519 key".to_string())
+				})?;
+				let signature = Signature::from_slice(signature.as_slice()).ok_or_else(|| {
+					Error::SerilizationFailure(
+						"Report signature is not an sr25519 signature".to_string(),
+					)
+				})?;
+				Ok((pubkey, (value, signature)))
+			})
+			.collect::<Result<BTreeMap<_, _>, Error>>()?;
+		let unsigned_extrinsic = self
+			.client
+			.runtime_api()
+			.create_feed_extrinsic(best_hash, feed_id, round, reports)?;
+
+		match self.tx_pool.submit_local(&BlockId::hash(best_hash), unsigned_extrinsic) {
+			Ok(_) => Ok(()),
+			Err(x) => match x.into_pool_error() {
+				// Reports arriving after the target produce a competing extrinsic for the round
+				Ok(PoolError::AlreadyImported(_)) | Ok(PoolError::TooLowPriority { .. }) => Ok(()),
+				Ok(PoolError::InvalidTransaction(InvalidTransaction::Stale)) => Ok(()),
+				Ok(e) => Err(Error::Other(e.to_string())),
+				Err(e) => Err(Error::Other(e.to_string())),
+			},
+		}
+	}
+
+	/// Collects the proofs we hold for the events of a [CatchUpRequest], keeping only those of the
+	/// current authorities.
+	fn collect_catch_up_proofs(
+		&self,
+		request: CatchUpRequest,
+	) -> Result<WitnessedEventBatch, Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+		let mut events = Vec::new();
+		for (stream_id, event_id) in request.ids.into_iter().take(MAX_CATCH_UP_IDS) {
+			let proofs = self.event_proofs.get_event_proofs(
+				&stream_id,
+				&event_id,
+				&block_state.authorities,
+			)?;
+			events.extend(proofs.into_values().map(WitnessedEvent::from));
+			if events.len() >= MAX_CATCH_UP_PROOFS {
+				events.truncate(MAX_CATCH_UP_PROOFS);
+				break
+			}
+		}
+		Ok(WitnessedEventBatch { events })
+	}
+
+	/// create a validated streams unsigned extrinsic with the given stream_id and event_id and
+	/// submits it to the transaction pool
 	async fn submit_event_extrinsic(
 		&self,
+		stream_id: H256,
 		event_id: H256,
-		event_proofs: Option<HashMap<CryptoTypePublicPair, Vec<u8>>>,
+		conflict_set: Option<H256>,
+		event_proofs: Option<HashMap<CryptoTypePublicPair, WitnessProof>>,
 	) -> Result<(), Error> {
-		let proofs = event_proofs.map(|x| {
-			x.iter()
-				.map(|(k, v)| {
-					let pubkey = Public::from_slice(k.1.as_slice()).unwrap();
-					let signature = Signature::from_slice(v.clone().as_slice()).unwrap();
-					(pubkey, signature)
-				})
-				.collect()
-		});
+		let proofs = event_proofs
+			.map(|x| x.into_values().map(|proof| (proof.signer, proof.signature)).collect());
 		let best_hash = self.client.info().best_hash;
-		let unsigned_extrinsic = self
-			.client
-			.runtime_api()
-			.create_unsigned_extrinsic(best_hash, event_id, proofs)?;
+		let unsigned_extrinsic = self.client.runtime_api().create_unsigned_extrinsic(
+			best_hash,
+			stream_id,
+			event_id,
+			conflict_set,
+			proofs,
+		)?;
 
 		match self.tx_pool.submit_local(&BlockId::hash(best_hash), unsigned_extrinsic) {
 			Ok(_) => Ok(()),
@@ -165,17 +446,81 @@ where
 	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
 {
 	fn get_topics() -> Vec<IdentTopic> {
-		vec![IdentTopic::new(Self::WITNESSED_EVENTS_TOPIC)]
+		vec![IdentTopic::new(GOSSIP_TOPIC)]
 	}
 
-	async fn handle(&self, message_data: Vec<u8>) {
-		match bincode::deserialize::<WitnessedEvent>(message_data.as_slice()) {
-			Ok(witnessed_event) => {
-				if let Err(e) = self.handle_witnessed_event(witnessed_event).await {
-					log::error!("failed processing message: {:?}", e)
+	async fn handle(&self, topic: &TopicHash, message_data: Vec<u8>) -> MessageVerdict {
+		if *topic != IdentTopic::new(GOSSIP_TOPIC).hash() {
+			log::debug!("Ignoring message on unknown topic {topic}");
+			return MessageVerdict::Ignore
+		}
+		match GossipMessage::decode(message_data.as_slice()) {
+			Ok(Some(message)) => self.handle_gossip_message(message).await,
+			// Messages of unknown versions or kinds cannot be checked, so they are not relayed
+			// (nor do they count against the peer, as they may come from a newer node)
+			Ok(None) => MessageVerdict::Ignore,
+			Err(e) => {
+				log::error!("failed decoding gossip message due to error:{:?}", e);
+				MessageVerdict::Reject
+			},
+		}
+	}
+
+	fn validate(&self, topic: &TopicHash, message_data: &[u8]) -> MessageVerdict {
+		if *topic != IdentTopic::new(GOSSIP_TOPIC).hash() {
+			return MessageVerdict::Ignore
+		}
+		match GossipMessage::decode(message_data) {
+			Ok(Some(message)) => self.validate_gossip_message(message),
+			// Messages of unknown versions or kinds cannot be checked, so they are not relayed
+			// (nor do they count against the peer, as they may come from a newer node)
+			Ok(None) => MessageVerdict::Ignore,
+			Err(e) => {
+				log::debug!("Rejecting undecodable gossip message: {:?}", e);
+				MessageVerdict::Reject
+			},
+		}
+	}
+
+	/// Answers a [CatchUpRequest] with the proofs we hold for its events, in the version of the
+	/// request, so that peers running older versions can catch up too.
+	async fn handle_request(&self, request: Vec<u8>) -> Vec<u8> {
+		let response = match GossipMessage::decode_versioned(request.as_slice()) {
+			Ok(Some((version, GossipMessage::CatchUpRequest(request)))) => self
+				.collect_catch_up_proofs(request)
+				.and_then(|batch| GossipMessage::WitnessedEventBatch(batch).encode_as(version)),
+			Ok(Some(_)) => Err(Error::Other("expected a catch-up request".to_string())),
+			Ok(None) => return Vec::new(),
+			Err(e) => Err(e),
+		};
+		match response {
+			Ok(response) => response,
+			Err(e) => {
+				log::error!("failed answering catch-up request: {:?}", e);
+				Vec::new()
+			},
+		}
+	}
+
+	/// Processes the proofs a peer sent in response to a [CatchUpRequest] as if they were
+	/// gossiped.
+	async fn handle_response(&self, response: Vec<u8>) {
+		if response.is_empty() {
+			return
+		}
+		match GossipMessage::decode(response.as_slice()) {
+			Ok(Some(GossipMessage::WitnessedEventBatch(batch))) => {
+				log::debug!("Received {} proofs from a peer catching up", batch.events.len());
+				for witnessed_event in batch.events {
+					if let Err(e) = self.handle_witnessed_event(witnessed_event).await {
+						log::debug!("failed processing catch-up proof: {:?}", e)
+					}
 				}
 			},
-			Err(e) => log::error!("failed deserilizing message data due to error:{:?}", e),
+			Ok(Some(_)) =>
+				log::error!("expected a batch of proofs in response to a catch-up request"),
+			Ok(None) => {},
+			Err(e) => log::error!("failed deserilizing catch-up response due to error:{:?}", e),
 		}
 	}
 }
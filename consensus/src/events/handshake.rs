@@ -0,0 +1,115 @@
+//! Authentication of gossip peers by the authority keys they hold
+
+use super::{get_authorities_list, get_latest_authorities_list, BlockStateCache};
+use crate::{
+	errors::Error,
+	gossip::{handshake_message, AuthorityProof, Gossip, GossipAuthenticator},
+};
+use async_trait::async_trait;
+use codec::Codec;
+use futures::StreamExt;
+use libp2p::PeerId;
+use pallet_validated_streams::ValidatedStreamsApi;
+use sc_client_api::{BlockchainEvents, HeaderBackend};
+use sp_api::{BlockT, ProvideRuntimeApi};
+use sp_consensus_aura::AuraApi;
+use sp_keystore::CryptoStore;
+use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
+use std::{marker::PhantomData, sync::Arc};
+
+/// A [GossipAuthenticator] which admits the peers that prove ownership of a key among the latest
+/// finalized authorities, signing the handshake with the local AURA key.
+pub struct AuthorityHandshake<Block: BlockT, Client, AuthorityId> {
+	client: Arc<Client>,
+	keystore: Arc<dyn CryptoStore>,
+	block_state: BlockStateCache<Block>,
+	phantom: PhantomData<AuthorityId>,
+}
+
+impl<Block, Client, AuthorityId> AuthorityHandshake<Block, Client, AuthorityId>
+where
+	Block: BlockT,
+{
+	/// Creates a new [AuthorityHandshake]
+	pub fn new(
+		client: Arc<Client>,
+		keystore: Arc<dyn CryptoStore>,
+		block_state: BlockStateCache<Block>,
+	) -> Self {
+		Self { client, keystore, block_state, phantom: PhantomData }
+	}
+}
+
+#[async_trait]
+impl<Block, Client, AuthorityId> GossipAuthenticator
+	for AuthorityHandshake<Block, Client, AuthorityId>
+where
+	Block: BlockT,
+	Client: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+{
+	async fn create_proof(&self, local_peer_id: &PeerId) -> Result<Option<AuthorityProof>, Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+		let supported_keys = self.keystore.supported_keys(AURA, block_state.authorities).await?;
+		let pub_key = match supported_keys.get(0) {
+			Some(pub_key) => pub_key,
+			None => return Ok(None),
+		};
+		let signature = self
+			.keystore
+			.sign_with(AURA, pub_key, &handshake_message(local_peer_id))
+			.await?
+			.ok_or_else(|| Error::SigningFailure("Failed getting a signature".to_string()))?;
+		Ok(Some(AuthorityProof { pub_key: pub_key.1.clone(), signature }))
+	}
+
+	fn verify_proof(&self, peer_id: &PeerId, proof: &AuthorityProof) -> Result<(), Error> {
+		get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?
+			.verify_handshake_origin(peer_id, proof)
+	}
+}
+
+/// Has the gossip verify again the proofs of its authenticated peers whenever a finalized block
+/// changes the authorities, so that peers whose keys were removed stop gossiping with us. This
+/// function only returns once the client stops sending finality notifications, so make sure to
+/// spawn it as a separate task.
+pub async fn watch_authority_changes<Block, Client, AuthorityId>(
+	client: Arc<Client>,
+	block_state: BlockStateCache<Block>,
+	mut gossip: Gossip,
+) where
+	Block: BlockT,
+	Client: HeaderBackend<Block>
+		+ BlockchainEvents<Block>
+		+ ProvideRuntimeApi<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+{
+	let mut authorities =
+		get_latest_authorities_list::<Block, Client, AuthorityId>(block_state.clone(), &*client)
+			.map(|list| list.authorities)
+			.ok();
+	let mut finality_notifications = client.finality_notification_stream();
+	while let Some(notification) = finality_notifications.next().await {
+		match get_authorities_list::<Block, Client, AuthorityId>(
+			block_state.clone(),
+			&*client,
+			notification.hash,
+		) {
+			Ok(list) if authorities.as_ref() != Some(&list.authorities) => {
+				log::info!("Authorities changed; verifying gossip peers again");
+				authorities = Some(list.authorities);
+				gossip.reauthenticate_peers().await;
+			},
+			Ok(_) => {},
+			Err(e) => log::warn!("Failed reading the authorities of a finalized block: {e}"),
+		}
+	}
+}
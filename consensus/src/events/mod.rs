@@ -2,9 +2,11 @@
 
 use crate::{
 	errors::Error,
+	gossip::{handshake_message, AuthorityProof},
 	proofs::{EventProofsTrait, WitnessedEvent},
 };
 use codec::Codec;
+use libp2p::PeerId;
 use lru::LruCache;
 use pallet_validated_streams::ValidatedStreamsApi;
 use sc_client_api::HeaderBackend;
@@ -19,12 +21,36 @@ use std::sync::{Arc, Mutex};
 #[cfg(test)]
 pub mod tests;
 
+mod batch;
+mod catch_up;
+mod conflicts;
+mod envelope;
+mod feeds;
 mod gossip;
+mod handshake;
+mod status;
 mod validate;
+mod watch;
 mod witness;
 
+pub use batch::{
+	WitnessBatcher, WitnessBatcherService, WitnessedEventBatch, DEFAULT_MAX_BATCH_SIZE,
+};
+pub use catch_up::{
+	missing_proofs_channel, run_catch_up, CatchUpRequest, MissingProofs, MissingProofsReceiver,
+	MAX_CATCH_UP_IDS, MAX_CATCH_UP_PROOFS,
+};
+pub use conflicts::{ConflictSetVotes, DEFAULT_CONFLICT_SETS_CAPACITY};
+pub use envelope::{
+	send_heartbeats, GossipMessage, Heartbeat, PeerVersions, GOSSIP_TOPIC, GOSSIP_VERSION,
+	HEARTBEAT_INTERVAL, LEGACY_PEER_TIMEOUT, MIN_GOSSIP_VERSION,
+};
+pub use feeds::{FeedReport, FeedReports, DEFAULT_FEED_ROUNDS_CAPACITY};
 pub use gossip::EventGossipHandler;
+pub use handshake::{watch_authority_changes, AuthorityHandshake};
+pub use status::EventStatusProvider;
 pub use validate::EventValidator;
+pub use watch::{watch_chain_events, EventUpdate, EventUpdates};
 pub use witness::EventWitnesser;
 
 /// A cache for the list of authorities in a block.
@@ -62,7 +88,12 @@ impl AuthoritiesList {
 					)
 				})?;
 
-			if pubkey.verify(&witnessed_event.event_id, &signature) {
+			let message = pallet_validated_streams::witnessed_event_message(
+				&witnessed_event.stream_id,
+				&witnessed_event.event_id,
+				witnessed_event.conflict_set.as_ref(),
+			);
+			if pubkey.verify(&message, &signature) {
 				Ok(witnessed_event)
 			} else {
 				Err(Error::BadWitnessedEventSignature(
@@ -70,9 +101,63 @@ impl AuthoritiesList {
 				))
 			}
 		} else {
-			Err(Error::BadWitnessedEventSignature(
-				"WitnessedEvent was signed by non-validator".to_string(),
-			))
+			Err(Error::UnknownSigner("WitnessedEvent".to_string()))
+		}
+	}
+
+	/// Verifies that the feed report was signed by one of the authorities, and that the signature
+	/// covers the reported value.
+	pub fn verify_feed_report_origin(&self, report: FeedReport) -> Result<FeedReport, Error> {
+		if !self.authorities.contains(&report.pub_key) {
+			return Err(Error::UnknownSigner("FeedReport".to_string()))
+		}
+		let pubkey = Public::from_slice(report.pub_key.1.as_slice()).map_err(|_| {
+			Error::BadWitnessedEventSignature(
+				"Can't retrieve sr25519 keys from FeedReport".to_string(),
+			)
+		})?;
+		let signature = Signature::from_slice(report.signature.as_slice()).ok_or_else(|| {
+			Error::BadWitnessedEventSignature(
+				"Can't create sr25519 signature from FeedReport".to_string(),
+			)
+		})?;
+		let message = pallet_validated_streams::feed_report_message(
+			&report.feed_id,
+			report.round,
+			report.value,
+		);
+		if pubkey.verify(&message, &signature) {
+			Ok(report)
+		} else {
+			Err(Error::BadWitnessedEventSignature("Incorrect FeedReport signature".to_string()))
+		}
+	}
+
+	/// Verifies that a gossip handshake proof was signed by one of the authorities for the given
+	/// peer ID.
+	pub fn verify_handshake_origin(
+		&self,
+		peer_id: &PeerId,
+		proof: &AuthorityProof,
+	) -> Result<(), Error> {
+		let pub_key = CryptoTypePublicPair(sp_core::sr25519::CRYPTO_ID, proof.pub_key.clone());
+		if !self.authorities.contains(&pub_key) {
+			return Err(Error::UnknownSigner("Handshake".to_string()))
+		}
+		let pubkey = Public::from_slice(proof.pub_key.as_slice()).map_err(|_| {
+			Error::BadWitnessedEventSignature(
+				"Can't retrieve sr25519 keys from handshake".to_string(),
+			)
+		})?;
+		let signature = Signature::from_slice(proof.signature.as_slice()).ok_or_else(|| {
+			Error::BadWitnessedEventSignature(
+				"Can't create sr25519 signature from handshake".to_string(),
+			)
+		})?;
+		if pubkey.verify(&handshake_message(peer_id), &signature) {
+			Ok(())
+		} else {
+			Err(Error::BadWitnessedEventSignature("Incorrect handshake signature".to_string()))
 		}
 	}
 
@@ -94,15 +179,15 @@ impl AuthoritiesList {
 	}
 }
 
-/// Returns the list of events that we do not have enough witnesses for, using the authorities in
-/// the given block.
+/// Returns the list of (stream id, event id) pairs that we do not have enough witnesses for, using
+/// the authorities in the given block.
 pub(crate) fn verify_events_validity<Block, EventProofs, Client, AuthorityId>(
 	block_state: BlockStateCache<Block>,
 	client: Arc<Client>,
 	authorities_block_id: <Block as BlockT>::Hash,
 	event_proofs: Arc<EventProofs>,
-	ids: Vec<H256>,
-) -> Result<Vec<H256>, Error>
+	ids: Vec<(H256, H256)>,
+) -> Result<Vec<(H256, H256)>, Error>
 where
 	Block: BlockT,
 	Client: ProvideRuntimeApi<Block> + Send + Sync + 'static,
@@ -115,11 +200,14 @@ where
 		get_authorities_list(block_state, client.as_ref(), authorities_block_id)?;
 	let target = authorities_list.target();
 	let mut unprepared_ids = Vec::new();
-	for id in ids {
-		let current_count =
-			event_proofs.get_event_proof_count(&id, &authorities_list.authorities)?;
+	for (stream_id, event_id) in ids {
+		let current_count = event_proofs.get_event_proof_count(
+			&stream_id,
+			&event_id,
+			&authorities_list.authorities,
+		)?;
 		if current_count < target {
-			unprepared_ids.push(id);
+			unprepared_ids.push((stream_id, event_id));
 		}
 	}
 	Ok(unprepared_ids)
@@ -0,0 +1,134 @@
+//! Service which reports the status of events
+
+use super::{get_latest_authorities_list, BlockStateCache};
+use crate::{
+	errors::Error,
+	proofs::EventProofsTrait,
+	traits::{EventStatus, EventStatusTrait, FeedValue},
+};
+use async_trait::async_trait;
+use codec::Codec;
+use pallet_validated_streams::ValidatedStreamsApi;
+use sc_client_api::HeaderBackend;
+use sc_transaction_pool_api::{InPoolTransaction, TransactionPool};
+use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
+use sp_consensus_aura::AuraApi;
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{marker::PhantomData, sync::Arc};
+
+/// A service which collects the status of an event from the [EventProofsTrait] storage, the
+/// transaction pool, and the chain.
+pub struct EventStatusProvider<TxPool, Client, EventProofs, AuthorityId, Block: BlockT> {
+	event_proofs: Arc<EventProofs>,
+	tx_pool: Arc<TxPool>,
+	client: Arc<Client>,
+	block_state: BlockStateCache<Block>,
+	phantom: PhantomData<AuthorityId>,
+}
+
+impl<TxPool, Client, EventProofs, AuthorityId, Block>
+	EventStatusProvider<TxPool, Client, EventProofs, AuthorityId, Block>
+where
+	Block: BlockT,
+{
+	/// Creates a new EventStatusProvider
+	pub fn new(
+		client: Arc<Client>,
+		event_proofs: Arc<EventProofs>,
+		tx_pool: Arc<TxPool>,
+		block_state: BlockStateCache<Block>,
+	) -> Self {
+		Self { client, event_proofs, tx_pool, phantom: PhantomData, block_state }
+	}
+}
+
+#[async_trait]
+impl<TxPool, Client, EventProofs, AuthorityId, Block> EventStatusTrait
+	for EventStatusProvider<TxPool, Client, EventProofs, AuthorityId, Block>
+where
+	TxPool: TransactionPool<Block = Block> + 'static,
+	Client: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	EventProofs: EventProofsTrait + Send + Sync + 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	Block: BlockT,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
+{
+	async fn get_event_status(
+		&self,
+		stream_id: H256,
+		event_id: H256,
+	) -> Result<EventStatus, Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+
+		let proofs =
+			self.event_proofs
+				.get_event_proofs(&stream_id, &event_id, &block_state.authorities)?;
+
+		let info = self.client.info();
+		let runtime_api = self.client.runtime_api();
+
+		let included_in = match runtime_api.get_event_block(info.best_hash, stream_id, event_id)? {
+			Some(block_number) => {
+				let block_hash = self
+					.client
+					.hash(block_number)
+					.map_err(|e| Error::Other(e.to_string()))?
+					.ok_or_else(|| Error::Other(format!("Missing block {block_number:?}")))?;
+				Some((block_number.into(), H256::from_slice(block_hash.as_ref())))
+			},
+			None => None,
+		};
+		let finalized =
+			runtime_api.get_event_block(info.finalized_hash, stream_id, event_id)?.is_some();
+
+		let in_transaction_pool = if included_in.is_none() {
+			let ready_extrinsics =
+				self.tx_pool.ready().map(|tx| tx.data().clone()).collect::<Vec<_>>();
+			runtime_api
+				.get_extrinsic_ids(info.best_hash, &ready_extrinsics)?
+				.contains(&(stream_id, event_id))
+		} else {
+			false
+		};
+
+		Ok(EventStatus {
+			proof_count: proofs.len() as u16,
+			target: block_state.target(),
+			signers: proofs.into_keys().collect(),
+			in_transaction_pool,
+			included_in,
+			finalized,
+		})
+	}
+
+	async fn get_feed_value(
+		&self,
+		feed_id: H256,
+		round: Option<u64>,
+	) -> Result<Option<FeedValue>, Error> {
+		let best_hash = self.client.info().best_hash;
+		let runtime_api = self.client.runtime_api();
+
+		let value = match round {
+			Some(round) => runtime_api
+				.get_feed_value(best_hash, feed_id, round)?
+				.map(|median| (round, median)),
+			None => runtime_api.get_latest_feed_value(best_hash, feed_id)?,
+		};
+		match value {
+			Some((round, median)) => {
+				let signers = runtime_api
+					.get_feed_signers(best_hash, feed_id, round)?
+					.iter()
+					.map(CryptoTypePublicPair::from)
+					.collect();
+				Ok(Some(FeedValue { round, median, signers }))
+			},
+			None => Ok(None),
+		}
+	}
+}
@@ -1,5 +1,13 @@
-use super::AuthoritiesList;
-use crate::proofs::WitnessedEvent;
+use super::{
+	AuthoritiesList, CatchUpRequest, ConflictSetVotes, EventUpdate, EventUpdates, FeedReport,
+	FeedReports, GossipMessage, Heartbeat, PeerVersions, WitnessedEventBatch,
+	DEFAULT_MAX_BATCH_SIZE, GOSSIP_VERSION, MIN_GOSSIP_VERSION,
+};
+use crate::{
+	errors::Error, gossip::GossipConfig, proofs::WitnessedEvent, traits::EventWatcherTrait,
+};
+use codec::Encode;
+use futures::StreamExt;
 use rstest::rstest;
 use sc_keystore::LocalKeystore;
 use sp_core::{sr25519::Public, H256};
@@ -10,9 +18,10 @@ use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
 async fn test_verify_events() {
 	// simple witnessed event
 	let keystore = LocalKeystore::in_memory();
+	let stream_id = H256::repeat_byte(7);
 	let event_id = H256::repeat_byte(0);
 	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
-	let witnessed_event = create_witnessed_event(event_id, &keystore, key).await;
+	let witnessed_event = create_witnessed_event(stream_id, event_id, &keystore, key).await;
 	let validators_list = vec![CryptoTypePublicPair::from(key)];
 	let block_state = AuthoritiesList::new(validators_list);
 
@@ -35,6 +44,18 @@ async fn test_verify_events() {
 	let result = block_state.verify_witnessed_event_origin(bad_sig_event);
 	assert!(result.is_err());
 
+	// the signature does not cover the same event in another stream
+	let mut other_stream_event = witnessed_event.clone();
+	other_stream_event.stream_id = H256::repeat_byte(8);
+	let result = block_state.verify_witnessed_event_origin(other_stream_event);
+	assert!(result.is_err());
+
+	// nor does it cover the event as a member of a conflict set
+	let mut conflict_set_event = witnessed_event.clone();
+	conflict_set_event.conflict_set = Some(H256::repeat_byte(9));
+	let result = block_state.verify_witnessed_event_origin(conflict_set_event);
+	assert!(result.is_err());
+
 	let mut invalid_key_event = witnessed_event.clone();
 	invalid_key_event.pub_key = CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(0)));
 	let result = block_state.verify_witnessed_event_origin(invalid_key_event);
@@ -43,7 +64,7 @@ async fn test_verify_events() {
 	//receive an event from a non-validator
 	let no_validators_block_state = AuthoritiesList::new(vec![]);
 	let result = no_validators_block_state.verify_witnessed_event_origin(witnessed_event);
-	assert!(result.is_err());
+	assert!(matches!(result, Err(Error::UnknownSigner(_))));
 }
 
 #[rstest]
@@ -61,15 +82,216 @@ fn test_calculate_target(#[case] validator_count: u8, #[case] target: u16) {
 	assert_eq!(block_state.target(), target);
 }
 
+#[tokio::test]
+async fn test_event_updates() {
+	let event_updates = EventUpdates::new(16);
+	assert!(!event_updates.has_subscribers());
+	// updates sent before subscribing are not received
+	event_updates
+		.notify(EventUpdate::Submitted { stream_id: H256::zero(), event_id: H256::repeat_byte(0) });
+
+	let mut updates = event_updates.subscribe();
+	assert!(event_updates.has_subscribers());
+	let update = EventUpdate::Submitted { stream_id: H256::zero(), event_id: H256::repeat_byte(1) };
+	event_updates.notify(update.clone());
+	assert_eq!(updates.next().await, Some(update));
+	drop(event_updates);
+	assert_eq!(updates.next().await, None);
+}
+
+#[test]
+fn test_conflict_set_votes() {
+	let votes = ConflictSetVotes::default();
+	let stream_id = H256::repeat_byte(7);
+	let conflict_set = H256::repeat_byte(9);
+	let (event_a, event_b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+	let authorities = (0..3)
+		.map(|x| CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(x))))
+		.collect::<Vec<_>>();
+
+	votes.record(stream_id, conflict_set, event_a, &authorities[0]).unwrap();
+	votes.record(stream_id, conflict_set, event_a, &authorities[1]).unwrap();
+	votes.record(stream_id, conflict_set, event_b, &authorities[2]).unwrap();
+	// voting for the same alternative again is fine
+	votes.record(stream_id, conflict_set, event_a, &authorities[0]).unwrap();
+
+	let tally = votes.tally(stream_id, conflict_set).unwrap();
+	assert_eq!(tally.get(&event_a), Some(&2));
+	assert_eq!(tally.get(&event_b), Some(&1));
+
+	// an authority cannot switch alternatives
+	assert!(votes.record(stream_id, conflict_set, event_b, &authorities[0]).is_err());
+	// an event cannot belong to two conflict sets
+	assert!(votes
+		.record(stream_id, H256::repeat_byte(10), event_a, &authorities[2])
+		.is_err());
+
+	assert_eq!(votes.conflict_set_of(stream_id, event_a).unwrap(), Some(conflict_set));
+	// conflict sets of different streams are separate
+	assert_eq!(votes.conflict_set_of(H256::zero(), event_a).unwrap(), None);
+	assert!(votes.tally(H256::zero(), conflict_set).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_feed_reports() {
+	let keystore = LocalKeystore::in_memory();
+	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let authority = CryptoTypePublicPair::from(key);
+	let block_state = AuthoritiesList::new(vec![authority.clone()]);
+	let feed_id = H256::repeat_byte(3);
+
+	let signature = keystore
+		.sign_with(
+			AURA,
+			&authority,
+			&pallet_validated_streams::feed_report_message(&feed_id, 1, -42),
+		)
+		.await
+		.unwrap()
+		.unwrap();
+	let report =
+		FeedReport { signature, pub_key: authority.clone(), feed_id, round: 1, value: -42 };
+	assert_eq!(block_state.verify_feed_report_origin(report.clone()).unwrap(), report);
+
+	// the signature covers the value and the round
+	let mut other_value_report = report.clone();
+	other_value_report.value = 42;
+	assert!(block_state.verify_feed_report_origin(other_value_report.clone()).is_err());
+	let mut other_round_report = report.clone();
+	other_round_report.round = 2;
+	assert!(block_state.verify_feed_report_origin(other_round_report).is_err());
+
+	let feed_reports = FeedReports::default();
+	feed_reports.add_report(&report).unwrap();
+	feed_reports.add_report(&report).unwrap();
+	// an authority cannot report two values for the same round
+	assert!(feed_reports.add_report(&other_value_report).is_err());
+
+	let reports = feed_reports.get_reports(&feed_id, 1, &[authority.clone()]).unwrap();
+	assert_eq!(reports.get(&authority).map(|(value, _)| *value), Some(-42));
+	assert!(feed_reports.get_reports(&feed_id, 1, &[]).unwrap().is_empty());
+	assert!(feed_reports.get_reports(&feed_id, 2, &[authority]).unwrap().is_empty());
+}
+
+/// test that batches round-trip, and that a full batch fits in a default gossip message
+#[tokio::test]
+async fn test_witnessed_event_batch() {
+	let keystore = LocalKeystore::in_memory();
+	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let mut witnessed_event =
+		create_witnessed_event(H256::repeat_byte(7), H256::repeat_byte(1), &keystore, key).await;
+	witnessed_event.conflict_set = Some(H256::repeat_byte(2));
+	let batch = WitnessedEventBatch { events: vec![witnessed_event; DEFAULT_MAX_BATCH_SIZE] };
+
+	let message = GossipMessage::WitnessedEventBatch(batch);
+	let encoded_message = message.encode().unwrap();
+	assert!(encoded_message.len() <= GossipConfig::default().max_transmit_size);
+	assert_eq!(GossipMessage::decode(&encoded_message).unwrap(), Some(message));
+}
+
+/// test that gossip messages round-trip, and that unknown versions and kinds are skipped
+#[tokio::test]
+async fn test_gossip_message_envelope() {
+	let keystore = LocalKeystore::in_memory();
+	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let witnessed_event =
+		create_witnessed_event(H256::repeat_byte(7), H256::repeat_byte(1), &keystore, key).await;
+	let messages = vec![
+		GossipMessage::WitnessedEvent(witnessed_event.clone()),
+		GossipMessage::WitnessedEventBatch(WitnessedEventBatch {
+			events: vec![witnessed_event.clone()],
+		}),
+		GossipMessage::CatchUpRequest(CatchUpRequest {
+			ids: vec![(H256::repeat_byte(7), H256::repeat_byte(1))],
+		}),
+		GossipMessage::Heartbeat(Heartbeat::default()),
+		GossipMessage::FeedReport(FeedReport {
+			signature: vec![],
+			pub_key: CryptoTypePublicPair::from(key),
+			feed_id: H256::repeat_byte(3),
+			round: 1,
+			value: 42,
+		}),
+	];
+	for message in messages {
+		let encoded = message.encode().unwrap();
+		assert_eq!(GossipMessage::decode(&encoded).unwrap(), Some(message));
+	}
+
+	// proofs are SCALE-encoded, as understood by the pallet
+	let encoded = GossipMessage::WitnessedEvent(witnessed_event.clone()).encode().unwrap();
+	let (_, _, payload) = bincode::deserialize::<(u16, u16, Vec<u8>)>(&encoded).unwrap();
+	assert_eq!(payload, witnessed_event.to_proof().unwrap().encode());
+
+	// envelopes are (version, kind, payload); version 1 proofs were bincode-encoded
+	let payload = bincode::serialize(&witnessed_event).unwrap();
+	let version_1 = bincode::serialize(&(1u16, 0u16, payload)).unwrap();
+	assert_eq!(
+		GossipMessage::decode(&version_1).unwrap(),
+		Some(GossipMessage::WitnessedEvent(witnessed_event))
+	);
+	let payload = bincode::serialize(&Heartbeat::default()).unwrap();
+	let newer_version = bincode::serialize(&(GOSSIP_VERSION + 1, 3u16, payload.clone())).unwrap();
+	assert_eq!(GossipMessage::decode(&newer_version).unwrap(), None);
+	let unknown_kind = bincode::serialize(&(GOSSIP_VERSION, u16::MAX, payload)).unwrap();
+	assert_eq!(GossipMessage::decode(&unknown_kind).unwrap(), None);
+	assert!(GossipMessage::decode(&[1, 2, 3]).is_err());
+}
+
+/// test that proofs are gossiped in the oldest version while older peers may be around, and that
+/// messages of every version round-trip
+#[tokio::test]
+async fn test_gossip_versions() {
+	let keystore = LocalKeystore::in_memory();
+	let key = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let witnessed_event =
+		create_witnessed_event(H256::repeat_byte(7), H256::repeat_byte(1), &keystore, key).await;
+	let proof = GossipMessage::WitnessedEventBatch(WitnessedEventBatch {
+		events: vec![witnessed_event.clone()],
+	});
+	let heartbeat = GossipMessage::Heartbeat(Heartbeat::default());
+
+	let peer_versions = PeerVersions::default();
+	assert_eq!(proof.gossip_version(&peer_versions), MIN_GOSSIP_VERSION);
+	peer_versions.note_heartbeat(&Heartbeat { min_version: 1, max_version: 1 });
+	assert_eq!(proof.gossip_version(&peer_versions), MIN_GOSSIP_VERSION);
+	assert_eq!(heartbeat.gossip_version(&peer_versions), MIN_GOSSIP_VERSION);
+
+	for version in MIN_GOSSIP_VERSION..=GOSSIP_VERSION {
+		for message in [&proof, &heartbeat] {
+			let encoded = message.encode_as(version).unwrap();
+			assert_eq!(
+				GossipMessage::decode_versioned(&encoded).unwrap(),
+				Some((version, message.clone()))
+			);
+		}
+	}
+	// version 1 proofs are bincode-encoded, as older nodes expect
+	let encoded = GossipMessage::WitnessedEvent(witnessed_event.clone()).encode_as(1).unwrap();
+	let (_, _, payload) = bincode::deserialize::<(u16, u16, Vec<u8>)>(&encoded).unwrap();
+	assert_eq!(payload, bincode::serialize(&witnessed_event).unwrap());
+}
+
 async fn create_witnessed_event(
+	stream_id: H256,
 	event_id: H256,
 	keystore: &LocalKeystore,
 	key: Public,
 ) -> WitnessedEvent {
 	let signature = keystore
-		.sign_with(AURA, keystore.keys(AURA).await.unwrap().get(0).unwrap(), event_id.as_bytes())
+		.sign_with(
+			AURA,
+			keystore.keys(AURA).await.unwrap().get(0).unwrap(),
+			&pallet_validated_streams::witnessed_event_message(&stream_id, &event_id, None),
+		)
 		.await
 		.unwrap()
 		.unwrap();
-	WitnessedEvent { event_id, pub_key: CryptoTypePublicPair::from(key), signature }
+	WitnessedEvent {
+		stream_id,
+		event_id,
+		conflict_set: None,
+		pub_key: CryptoTypePublicPair::from(key),
+		signature,
+	}
 }
@@ -1,30 +1,135 @@
 //! Service which returns the stream of finalized events
 
-use crate::{errors::Error, traits::EventValidatorTrait};
+#[cfg(feature = "off-chain-proofs")]
+use super::get_authorities_list;
+use super::BlockStateCache;
+use crate::{
+	errors::Error,
+	proofs::EventProofsTrait,
+	traits::{EventValidatorTrait, FinalizedBlockEvents, ValidatedEventDetails},
+};
 use async_trait::async_trait;
+use codec::Codec;
 use futures::StreamExt;
 use pallet_validated_streams::ValidatedStreamsApi;
 use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
-use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
+use sp_api::{ApiExt, BlockT, HeaderT, ProvideRuntimeApi};
 use sp_blockchain::{lowest_common_ancestor, HeaderMetadata};
+use sp_consensus_aura::AuraApi;
 use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::{marker::PhantomData, sync::Arc};
 
 /// A service which returns the stream of validated/finalized events.
-pub struct EventValidator<Client, Block> {
+pub struct EventValidator<Client, Block: BlockT, EventProofs, AuthorityId> {
 	client: Arc<Client>,
-	phantom: PhantomData<Block>,
+	#[cfg_attr(not(feature = "off-chain-proofs"), allow(dead_code))]
+	event_proofs: Arc<EventProofs>,
+	#[cfg_attr(not(feature = "off-chain-proofs"), allow(dead_code))]
+	block_state: BlockStateCache<Block>,
+	phantom: PhantomData<(Block, AuthorityId)>,
 }
 
-impl<Client: ProvideRuntimeApi<Block>, Block: BlockT> EventValidator<Client, Block> {
-	/// Create a new EventValidator for the given client
-	pub fn new(client: Arc<Client>) -> Self {
-		Self { client, phantom: PhantomData }
+impl<Client, Block, EventProofs, AuthorityId>
+	EventValidator<Client, Block, EventProofs, AuthorityId>
+where
+	Client: ProvideRuntimeApi<Block>,
+	Block: BlockT,
+{
+	/// Create a new EventValidator for the given client. The [EventProofsTrait] instance is used to
+	/// look up the signers of events when proofs are kept off-chain.
+	pub fn new(
+		client: Arc<Client>,
+		event_proofs: Arc<EventProofs>,
+		block_state: BlockStateCache<Block>,
+	) -> Self {
+		Self { client, event_proofs, block_state, phantom: PhantomData }
+	}
+}
+
+impl<Client, Block, EventProofs, AuthorityId>
+	EventValidator<Client, Block, EventProofs, AuthorityId>
+where
+	Client: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	Block: BlockT,
+	EventProofs: EventProofsTrait,
+	AuthorityId: Codec + Send + Sync + 'static,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
+{
+	/// Returns the authorities whose proofs validated an event, reading them from the local
+	/// [EventProofsTrait] storage, using the authorities of the block's parent.
+	#[cfg(feature = "off-chain-proofs")]
+	fn get_event_signers(
+		&self,
+		_block_hash: Block::Hash,
+		parent_hash: Block::Hash,
+		_api_version: u32,
+		stream_id: &H256,
+		event_id: &H256,
+	) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		let authorities_list =
+			get_authorities_list(self.block_state.clone(), self.client.as_ref(), parent_hash)?;
+		Ok(self
+			.event_proofs
+			.get_event_proofs(stream_id, event_id, &authorities_list.authorities)?
+			.into_keys()
+			.collect())
+	}
+
+	/// Returns the authorities whose proofs validated an event, reading them from the chain.
+	/// Version 1 of the [ValidatedStreamsApi] cannot return them, so none are returned for the
+	/// blocks of runtimes that only implement it.
+	#[cfg(not(feature = "off-chain-proofs"))]
+	fn get_event_signers(
+		&self,
+		block_hash: Block::Hash,
+		_parent_hash: Block::Hash,
+		api_version: u32,
+		stream_id: &H256,
+		event_id: &H256,
+	) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		if api_version < 2 {
+			return Ok(Vec::new())
+		}
+		Ok(self
+			.client
+			.runtime_api()
+			.get_event_signers(block_hash, *stream_id, *event_id)?
+			.iter()
+			.map(CryptoTypePublicPair::from)
+			.collect())
+	}
+
+	/// Returns the events validated in a block of a runtime implementing version 1 of the
+	/// [ValidatedStreamsApi], as (extrinsic index, stream id, event id, conflict set id). All
+	/// events of that version are in the default stream and outside of conflict sets; since it
+	/// only lists the event ids of whole lists of extrinsics, each extrinsic is looked at on its
+	/// own to find its index.
+	#[allow(deprecated)]
+	fn get_v1_extrinsic_events(
+		&self,
+		block_hash: Block::Hash,
+		extrinsics: &[Block::Extrinsic],
+	) -> Result<Vec<(u32, H256, H256, Option<H256>)>, Error> {
+		let runtime_api = self.client.runtime_api();
+		let mut events = Vec::new();
+		for (index, extrinsic) in extrinsics.iter().enumerate() {
+			let event_ids = runtime_api
+				.get_extrinsic_ids_before_version_2(block_hash, &vec![extrinsic.clone()])?;
+			events.extend(
+				event_ids
+					.into_iter()
+					.map(|event_id| (index as u32, H256::zero(), event_id, None)),
+			);
+		}
+		Ok(events)
 	}
 }
 
 #[async_trait]
-impl<Client, Block: BlockT> EventValidatorTrait for EventValidator<Client, Block>
+impl<Client, Block, EventProofs, AuthorityId> EventValidatorTrait
+	for EventValidator<Client, Block, EventProofs, AuthorityId>
 where
 	Client: HeaderBackend<Block>
 		+ HeaderMetadata<Block>
@@ -34,41 +139,116 @@ where
 		+ Sync
 		+ Send
 		+ 'static,
-	Client::Api: ValidatedStreamsApi<Block>,
+	Client::Api: ValidatedStreamsApi<Block> + AuraApi<Block, AuthorityId>,
+	Block: BlockT,
+	EventProofs: EventProofsTrait + Send + Sync + 'static,
+	AuthorityId: Codec + Send + Sync + 'static,
+	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
 	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
 {
-	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error> {
-		let mut last_finalized = self.client.info().finalized_hash;
+	async fn get_finalized_block_events(
+		&self,
+		block_num: u32,
+	) -> Result<FinalizedBlockEvents, Error> {
+		let info = self.client.info();
+		let mut last_finalized = info.finalized_hash;
+		let finalized_number: u32 = info.finalized_number.into();
 
-		let block_id = loop {
-			// If the block at block_num is part of the chain...
-			if let Ok(Some(block_hash)) = self.client.block_hash(block_num.into()) {
-				// ...And is part of the finalized chain (LCA between it and the
-				// finalized tip is the block itself)
-				if let Ok(common_ancestor) =
-					lowest_common_ancestor(self.client.as_ref(), last_finalized, block_hash)
-				{
-					if common_ancestor.hash == block_hash {
-						// Then, the block at block_num id was finalized, continue with that hash
-						break block_hash
+		let block_id = if block_num <= finalized_number {
+			// Fast path for already-finalized blocks, used when replaying past events: the
+			// canonical block at that height is the finalized one.
+			self.client
+				.block_hash(block_num.into())
+				.ok()
+				.flatten()
+				.ok_or(Error::BlockUnavailable(block_num))?
+		} else {
+			loop {
+				// If the block at block_num is part of the chain...
+				if let Ok(Some(block_hash)) = self.client.block_hash(block_num.into()) {
+					// ...And is part of the finalized chain (LCA between it and the
+					// finalized tip is the block itself)
+					if let Ok(common_ancestor) =
+						lowest_common_ancestor(self.client.as_ref(), last_finalized, block_hash)
+					{
+						if common_ancestor.hash == block_hash {
+							// Then, the block at block_num id was finalized, continue with that
+							// hash
+							break block_hash
+						}
 					}
 				}
+				// Otherwise, wait for the next change of the finalized chain and try with it again
+				last_finalized =
+					self.client.finality_notification_stream().select_next_some().await.hash;
 			}
-			// Otherwise, wait for the next change of the finalized chain and try with it again
-			last_finalized =
-				self.client.finality_notification_stream().select_next_some().await.hash;
 		};
 
-		let block_extrinsics = self.client.block_body(block_id).ok().flatten().unwrap_or_default();
-
-		Ok(self
+		// Don't return an empty list of events for blocks whose body was pruned; the caller would
+		// not be able to tell them apart from blocks without events.
+		let block_extrinsics = self
 			.client
-			.runtime_api()
-			.get_extrinsic_ids(block_id, &block_extrinsics)
-			.unwrap_or_default())
+			.block_body(block_id)
+			.ok()
+			.flatten()
+			.ok_or(Error::BlockUnavailable(block_num))?;
+		let parent_hash = self
+			.client
+			.header(block_id)
+			.ok()
+			.flatten()
+			.map(|header| *header.parent_hash())
+			.unwrap_or_default();
+
+		let runtime_api = self.client.runtime_api();
+		let api_version = runtime_api
+			.api_version::<dyn ValidatedStreamsApi<Block>>(block_id)?
+			.ok_or_else(|| {
+				Error::Other(format!("Block #{block_num} has no Validated Streams API"))
+			})?;
+		let events = if api_version >= 2 {
+			runtime_api.get_extrinsic_events(block_id, &block_extrinsics)?
+		} else {
+			self.get_v1_extrinsic_events(block_id, &block_extrinsics)?
+		};
+		let events = events
+			.into_iter()
+			.map(|(extrinsic_index, stream_id, event_id, conflict_set)| {
+				Ok(ValidatedEventDetails {
+					signers: self.get_event_signers(
+						block_id,
+						parent_hash,
+						api_version,
+						&stream_id,
+						&event_id,
+					)?,
+					stream_id,
+					event_id,
+					conflict_set,
+					extrinsic_index,
+				})
+			})
+			.collect::<Result<_, Error>>()?;
+
+		Ok(FinalizedBlockEvents { block_hash: H256::from_slice(block_id.as_ref()), events })
 	}
 
 	async fn get_latest_finalized_block(&self) -> Result<u32, Error> {
 		Ok(self.client.info().finalized_number.into())
 	}
+
+	async fn get_finalized_block_hash(&self, block_num: u32) -> Result<Option<H256>, Error> {
+		let finalized_number: u32 = self.client.info().finalized_number.into();
+		if block_num > finalized_number {
+			return Ok(None)
+		}
+		match self
+			.client
+			.block_hash(block_num.into())
+			.map_err(|e| Error::Other(e.to_string()))?
+		{
+			Some(block_hash) => Ok(Some(H256::from_slice(block_hash.as_ref()))),
+			None => Err(Error::BlockUnavailable(block_num)),
+		}
+	}
 }
@@ -0,0 +1,198 @@
+//! Service which broadcasts changes in the lifecycle of events
+
+use crate::traits::EventWatcherTrait;
+use futures::{select, stream, Stream, StreamExt};
+use pallet_validated_streams::ValidatedStreamsApi;
+use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
+use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
+use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{pin::Pin, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// A change in the lifecycle of an event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventUpdate {
+	/// A new proof (signature) of the event was received.
+	ProofReceived {
+		/// The id of the stream the event belongs to.
+		stream_id: H256,
+		/// The id of the event.
+		event_id: H256,
+		/// The authority which signed the event.
+		signer: CryptoTypePublicPair,
+		/// The number of proofs by current authorities held for the event.
+		proof_count: u16,
+		/// The number of proofs needed for the event to be submitted on-chain.
+		target: u16,
+	},
+	/// The event has reached the proofs target and was submitted to the transaction pool.
+	Submitted {
+		/// The id of the stream the event belongs to.
+		stream_id: H256,
+		/// The id of the event.
+		event_id: H256,
+	},
+	/// The event was included in a new best block.
+	Included {
+		/// The id of the stream the event belongs to.
+		stream_id: H256,
+		/// The id of the event.
+		event_id: H256,
+		/// The number of the block which included the event.
+		block_number: u32,
+		/// The hash of the block which included the event.
+		block_hash: H256,
+	},
+	/// The block which included the event was finalized.
+	Finalized {
+		/// The id of the stream the event belongs to.
+		stream_id: H256,
+		/// The id of the event.
+		event_id: H256,
+		/// The number of the finalized block.
+		block_number: u32,
+		/// The hash of the finalized block.
+		block_hash: H256,
+	},
+}
+
+impl EventUpdate {
+	/// Returns the id of the stream of the event this update is about.
+	pub fn stream_id(&self) -> &H256 {
+		match self {
+			Self::ProofReceived { stream_id, .. } |
+			Self::Submitted { stream_id, .. } |
+			Self::Included { stream_id, .. } |
+			Self::Finalized { stream_id, .. } => stream_id,
+		}
+	}
+
+	/// Returns the id of the event this update is about.
+	pub fn event_id(&self) -> &H256 {
+		match self {
+			Self::ProofReceived { event_id, .. } |
+			Self::Submitted { event_id, .. } |
+			Self::Included { event_id, .. } |
+			Self::Finalized { event_id, .. } => event_id,
+		}
+	}
+}
+
+/// A broadcast channel of [EventUpdate]-s. Cloning it is safe and reuses the same channel.
+#[derive(Clone)]
+pub struct EventUpdates {
+	sender: broadcast::Sender<EventUpdate>,
+}
+
+impl EventUpdates {
+	/// Creates a new [EventUpdates] channel, buffering up to `capacity` updates for slow
+	/// subscribers.
+	pub fn new(capacity: usize) -> Self {
+		let (sender, _) = broadcast::channel(capacity);
+		Self { sender }
+	}
+
+	/// Sends an update to all current subscribers.
+	pub fn notify(&self, update: EventUpdate) {
+		// Sending only fails when there are no subscribers, which is fine.
+		let _ = self.sender.send(update);
+	}
+
+	/// Returns whether anyone is currently subscribed to the updates.
+	pub fn has_subscribers(&self) -> bool {
+		self.sender.receiver_count() > 0
+	}
+}
+
+impl EventWatcherTrait for EventUpdates {
+	fn subscribe(&self) -> Pin<Box<dyn Stream<Item = EventUpdate> + Send>> {
+		Box::pin(stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+			loop {
+				match receiver.recv().await {
+					Ok(update) => return Some((update, receiver)),
+					Err(RecvError::Lagged(skipped)) => log::warn!(
+						"Event updates subscriber lagged behind, skipped {skipped} updates"
+					),
+					Err(RecvError::Closed) => return None,
+				}
+			}
+		}))
+	}
+}
+
+/// Follows the imported and finalized blocks of the chain, notifying [EventUpdates] about the
+/// events included in them. This function never returns, so make sure to spawn it as a separate
+/// task.
+pub async fn watch_chain_events<Client, Block>(client: Arc<Client>, event_updates: EventUpdates)
+where
+	Block: BlockT,
+	Client: BlockchainEvents<Block>
+		+ BlockBackend<Block>
+		+ HeaderBackend<Block>
+		+ ProvideRuntimeApi<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+	Client::Api: ValidatedStreamsApi<Block>,
+	<<Block as BlockT>::Header as HeaderT>::Number: Into<u32>,
+{
+	let mut import_notifications = client.import_notification_stream();
+	let mut finality_notifications = client.finality_notification_stream();
+
+	loop {
+		select! {
+			notification = import_notifications.select_next_some() => {
+				if !notification.is_new_best || !event_updates.has_subscribers() {
+					continue
+				}
+				let block_number = (*notification.header.number()).into();
+				let block_hash = H256::from_slice(notification.hash.as_ref());
+				for (stream_id, event_id) in get_block_event_ids(client.as_ref(), notification.hash) {
+					event_updates.notify(EventUpdate::Included {
+						stream_id,
+						event_id,
+						block_number,
+						block_hash,
+					});
+				}
+			},
+			notification = finality_notifications.select_next_some() => {
+				if !event_updates.has_subscribers() {
+					continue
+				}
+				// The tree route holds the blocks finalized implicitly, in ascending order.
+				for hash in notification.tree_route.iter().chain(std::iter::once(&notification.hash)) {
+					let block_number = match client.number(*hash) {
+						Ok(Some(number)) => number.into(),
+						_ => continue,
+					};
+					let block_hash = H256::from_slice(hash.as_ref());
+					for (stream_id, event_id) in get_block_event_ids(client.as_ref(), *hash) {
+						event_updates.notify(EventUpdate::Finalized {
+							stream_id,
+							event_id,
+							block_number,
+							block_hash,
+						});
+					}
+				}
+			},
+		}
+	}
+}
+
+/// Reads the (stream id, event id) pairs of the events included in a block.
+fn get_block_event_ids<Client, Block>(client: &Client, block_hash: Block::Hash) -> Vec<(H256, H256)>
+where
+	Block: BlockT,
+	Client: BlockBackend<Block> + ProvideRuntimeApi<Block>,
+	Client::Api: ValidatedStreamsApi<Block>,
+{
+	let block_extrinsics = client.block_body(block_hash).ok().flatten().unwrap_or_default();
+
+	client
+		.runtime_api()
+		.get_extrinsic_ids(block_hash, &block_extrinsics)
+		.unwrap_or_default()
+}
@@ -1,10 +1,12 @@
 //! Service which witnesses events from the trusted client
 
-use super::{get_latest_authorities_list, gossip::WITNESSED_EVENTS_TOPIC, AuthoritiesList};
+use super::{
+	get_latest_authorities_list, AuthoritiesList, ConflictSetVotes, FeedReport, GossipMessage,
+	PeerVersions, WitnessBatcher, WitnessedEventBatch, DEFAULT_MAX_BATCH_SIZE,
+};
 use crate::{errors::Error, gossip::Gossip, proofs::WitnessedEvent, traits::EventWitnesserTrait};
 use async_trait::async_trait;
 use codec::Codec;
-use libp2p::gossipsub::IdentTopic;
 use lru::LruCache;
 use pallet_validated_streams::ValidatedStreamsApi;
 use sc_client_api::HeaderBackend;
@@ -24,6 +26,10 @@ pub struct EventWitnesser<Block: BlockT, Client, AuthorityId> {
 	gossip: Gossip,
 	keystore: Arc<dyn CryptoStore>,
 	block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+	conflict_votes: Arc<ConflictSetVotes>,
+	peer_versions: Arc<PeerVersions>,
+	batcher: Option<WitnessBatcher>,
+	max_batch_size: usize,
 	phantom: PhantomData<(Block, AuthorityId)>,
 }
 
@@ -37,8 +43,107 @@ where
 		gossip: Gossip,
 		keystore: Arc<dyn CryptoStore>,
 		block_state: Arc<Mutex<LruCache<<Block as BlockT>::Hash, AuthoritiesList>>>,
+		conflict_votes: Arc<ConflictSetVotes>,
+		peer_versions: Arc<PeerVersions>,
 	) -> Self {
-		Self { client, gossip, keystore, phantom: PhantomData, block_state }
+		Self {
+			client,
+			gossip,
+			keystore,
+			phantom: PhantomData,
+			block_state,
+			conflict_votes,
+			peer_versions,
+			batcher: None,
+			max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+		}
+	}
+
+	/// Sets the maximum number of events in each [WitnessedEventBatch] gossiped by
+	/// witness_events, [DEFAULT_MAX_BATCH_SIZE] by default.
+	pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+		self.max_batch_size = max_batch_size.max(1);
+		self
+	}
+
+	/// Gossips witnessed events in batches through the given [WitnessBatcher], instead of one
+	/// message per event.
+	pub fn with_batcher(mut self, batcher: WitnessBatcher) -> Self {
+		self.batcher = Some(batcher);
+		self
+	}
+
+	/// Signs an event with the given key from the keystore.
+	async fn sign_event(
+		&self,
+		pub_key: &CryptoTypePublicPair,
+		stream_id: H256,
+		event_id: H256,
+		conflict_set: Option<H256>,
+	) -> Result<WitnessedEvent, Error> {
+		let message = pallet_validated_streams::witnessed_event_message(
+			&stream_id,
+			&event_id,
+			conflict_set.as_ref(),
+		);
+		let signature = self
+			.keystore
+			.sign_with(AURA, pub_key, &message)
+			.await?
+			.ok_or_else(|| Error::SigningFailure("Failed getting a signature".to_string()))?;
+
+		log::trace!("Signed event {event_id} in stream {stream_id}");
+
+		Ok(WitnessedEvent {
+			signature,
+			pub_key: pub_key.clone(),
+			stream_id,
+			event_id,
+			conflict_set,
+		})
+	}
+
+	/// Fails if another member of the event's conflict set was validated, or if the signer
+	/// witnessed another member of the set before; otherwise, records the signer's vote for the
+	/// event, so that it does not sign any other member later.
+	fn check_conflict_set(
+		&self,
+		stream_id: H256,
+		event_id: H256,
+		conflict_set: Option<H256>,
+		pub_key: &CryptoTypePublicPair,
+	) -> Result<(), Error>
+	where
+		Client: HeaderBackend<Block> + ProvideRuntimeApi<Block>,
+		Client::Api: ValidatedStreamsApi<Block>,
+	{
+		if let Some(conflict_set) = conflict_set {
+			let best_hash = self.client.info().best_hash;
+			match self.client.runtime_api().get_conflict_set_event(
+				best_hash,
+				stream_id,
+				conflict_set,
+			)? {
+				Some(validated_event_id) if validated_event_id == event_id =>
+					return Err(Error::AlreadyValidated),
+				Some(_) => return Err(Error::ConflictSetResolved),
+				None => {},
+			}
+			self.conflict_votes.record(stream_id, conflict_set, event_id, pub_key)?;
+		}
+		Ok(())
+	}
+
+	/// Serializes a witnessed event and publishes it to the [Gossip], or adds it to the current
+	/// batch if batching.
+	async fn publish_witnessed_event(&self, witnessed_event: &WitnessedEvent) -> Result<(), Error> {
+		if let Some(batcher) = &self.batcher {
+			return batcher.add(witnessed_event.clone()).await
+		}
+
+		GossipMessage::WitnessedEvent(witnessed_event.clone())
+			.publish(&mut self.gossip.clone(), &self.peer_versions)
+			.await
 	}
 }
 
@@ -53,33 +158,148 @@ where
 {
 	/// Witnesses an event by signing and sending it to the [Gossip].
 	/// [EventGossipHandler] will then proceed to add the event to the [EventProofsTrait].
-	async fn witness_event(&self, event_id: H256) -> Result<(), Error> {
+	/// Events of a conflict set are only signed if no other member of the set was validated or
+	/// witnessed by us before.
+	async fn witness_event(
+		&self,
+		stream_id: H256,
+		event_id: H256,
+		conflict_set: Option<H256>,
+	) -> Result<(), Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+
+		log::trace!("To witness event {event_id} in stream {stream_id}");
+
+		let supported_keys = self.keystore.supported_keys(AURA, block_state.authorities).await?;
+
+		let pub_key = supported_keys.get(0).ok_or(Error::NotAValidator)?;
+
+		self.check_conflict_set(stream_id, event_id, conflict_set, pub_key)?;
+
+		let witnessed_event = self.sign_event(pub_key, stream_id, event_id, conflict_set).await?;
+
+		self.publish_witnessed_event(&witnessed_event).await
+	}
+
+	/// Witnesses a batch of events, reusing the same authorities list and key for all of them and
+	/// gossiping their signatures as [WitnessedEventBatch]-es of up to the configured maximum
+	/// batch size (or through the [WitnessBatcher], if any).
+	async fn witness_events(
+		&self,
+		stream_id: H256,
+		event_ids: Vec<H256>,
+	) -> Result<Vec<Result<(), Error>>, Error> {
 		let block_state =
 			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
 
-		log::trace!("To witness event {event_id} {}", event_id);
+		log::trace!("To witness a batch of {} events", event_ids.len());
 
 		let supported_keys = self.keystore.supported_keys(AURA, block_state.authorities).await?;
 
 		let pub_key = supported_keys.get(0).ok_or(Error::NotAValidator)?;
+
+		let already_validated = {
+			let best_hash = self.client.info().best_hash;
+			let runtime_api = self.client.runtime_api();
+			event_ids
+				.iter()
+				.map(|event_id| runtime_api.is_event_valid(best_hash, stream_id, *event_id))
+				.collect::<Result<Vec<_>, _>>()?
+		};
+
+		let mut results = Vec::with_capacity(event_ids.len());
+		let mut witnessed_events = Vec::with_capacity(event_ids.len());
+		for (event_id, already_validated) in event_ids.into_iter().zip(already_validated) {
+			if already_validated {
+				results.push(Err(Error::AlreadyValidated));
+				continue
+			}
+			let witnessed_event = match self.sign_event(pub_key, stream_id, event_id, None).await {
+				Ok(witnessed_event) => witnessed_event,
+				Err(e) => {
+					results.push(Err(e));
+					continue
+				},
+			};
+			if let Some(batcher) = &self.batcher {
+				results.push(batcher.add(witnessed_event).await);
+				continue
+			}
+			witnessed_events.push((results.len(), witnessed_event));
+			results.push(Ok(()));
+		}
+
+		let mut gossip = self.gossip.clone();
+		for chunk in witnessed_events.chunks(self.max_batch_size) {
+			let (indices, events): (Vec<_>, Vec<_>) = chunk.iter().cloned().unzip();
+			if let Err(e) = GossipMessage::WitnessedEventBatch(WitnessedEventBatch { events })
+				.publish(&mut gossip, &self.peer_versions)
+				.await
+			{
+				for index in indices {
+					results[index] =
+						Err(Error::Other(format!("Failed serializing witnessed event: {e}")));
+				}
+			}
+		}
+
+		Ok(results)
+	}
+
+	/// Reports a value of a feed by signing it together with the feed id and round, and sending it
+	/// to the [Gossip]. [EventGossipHandler] will then collect it with the reports of the other
+	/// authorities.
+	async fn report_feed_value(&self, feed_id: H256, round: u64, value: i64) -> Result<(), Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
+
+		let best_hash = self.client.info().best_hash;
+		if self.client.runtime_api().get_feed_value(best_hash, feed_id, round)?.is_some() {
+			return Err(Error::AlreadyValidated)
+		}
+
+		let supported_keys = self.keystore.supported_keys(AURA, block_state.authorities).await?;
+		let pub_key = supported_keys.get(0).ok_or(Error::NotAValidator)?;
+
+		let message = pallet_validated_streams::feed_report_message(&feed_id, round, value);
 		let signature = self
 			.keystore
-			.sign_with(AURA, pub_key, event_id.as_bytes())
+			.sign_with(AURA, pub_key, &message)
 			.await?
 			.ok_or_else(|| Error::SigningFailure("Failed getting a signature".to_string()))?;
 
-		log::trace!("Signed event {event_id} {}", event_id);
+		log::trace!("Signed value {value} for round {round} of feed {feed_id}");
 
-		let witnessed_event = WitnessedEvent { signature, pub_key: pub_key.clone(), event_id };
+		let report = FeedReport { signature, pub_key: pub_key.clone(), feed_id, round, value };
+		GossipMessage::FeedReport(report)
+			.publish(&mut self.gossip.clone(), &self.peer_versions)
+			.await
+	}
+
+	/// Witnesses an event signed outside of the node by verifying the signature against the
+	/// current [AuthoritiesList] and sending it to the [Gossip].
+	async fn witness_signed_event(&self, witnessed_event: WitnessedEvent) -> Result<(), Error> {
+		let block_state =
+			get_latest_authorities_list(self.block_state.clone(), self.client.as_ref())?;
 
-		let serilized_event = bincode::serialize(&witnessed_event)
-			.map_err(|e| Error::SerilizationFailure(e.to_string()))?;
+		let witnessed_event = block_state.verify_witnessed_event_origin(witnessed_event)?;
 
-		self.gossip
-			.clone()
-			.publish(IdentTopic::new(WITNESSED_EVENTS_TOPIC), serilized_event)
-			.await;
+		log::trace!(
+			"Received pre-signed event {} in stream {}",
+			witnessed_event.event_id,
+			witnessed_event.stream_id
+		);
 
-		Ok(())
+		// The same checks as for events we sign ourselves, so that a key does not end up voting
+		// for two members of a conflict set
+		self.check_conflict_set(
+			witnessed_event.stream_id,
+			witnessed_event.event_id,
+			witnessed_event.conflict_set,
+			&witnessed_event.pub_key,
+		)?;
+
+		self.publish_witnessed_event(&witnessed_event).await
 	}
 }
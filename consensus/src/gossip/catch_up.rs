@@ -0,0 +1,76 @@
+//! A request/response protocol with which peers can catch up on messages they missed, e.g. while
+//! offline. Requests and responses are opaque to the gossip, and are left to the
+//! [super::GossipHandler].
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{
+	core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName},
+	request_response::RequestResponseCodec,
+};
+use std::io;
+
+/// The largest request accepted.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// The largest response accepted.
+const MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The libp2p protocol used for catching up.
+#[derive(Clone, Debug, Default)]
+pub struct CatchUpProtocol;
+
+impl ProtocolName for CatchUpProtocol {
+	fn protocol_name(&self) -> &[u8] {
+		b"/vstreams/catch-up/1"
+	}
+}
+
+/// Encodes catch-up requests and responses, both of which are raw bytes.
+#[derive(Clone, Debug, Default)]
+pub struct CatchUpCodec;
+
+#[async_trait]
+impl RequestResponseCodec for CatchUpCodec {
+	type Protocol = CatchUpProtocol;
+	type Request = Vec<u8>;
+	type Response = Vec<u8>;
+
+	async fn read_request<T>(&mut self, _: &CatchUpProtocol, io: &mut T) -> io::Result<Vec<u8>>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		read_length_prefixed(io, MAX_REQUEST_SIZE).await
+	}
+
+	async fn read_response<T>(&mut self, _: &CatchUpProtocol, io: &mut T) -> io::Result<Vec<u8>>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		read_length_prefixed(io, MAX_RESPONSE_SIZE).await
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		_: &CatchUpProtocol,
+		io: &mut T,
+		request: Vec<u8>,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, request).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		_: &CatchUpProtocol,
+		io: &mut T,
+		response: Vec<u8>,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_length_prefixed(io, response).await
+	}
+}
@@ -0,0 +1,263 @@
+//! A handshake in which gossip peers prove that they hold a current authority key.
+
+use crate::errors::Error;
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{
+	core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName},
+	gossipsub::{GossipsubMessage, MessageId},
+	request_response::RequestResponseCodec,
+	PeerId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	sync::Arc,
+};
+
+/// The largest handshake message accepted; proofs are well below that.
+const MAX_HANDSHAKE_SIZE: usize = 1024;
+
+/// How many gossip messages relayed by a peer are held while its handshake is in progress; later
+/// ones are ignored.
+const MAX_PENDING_MESSAGES: usize = 64;
+
+/// Returns the message an authority signs to prove that a gossip peer ID belongs to it. The peer ID
+/// is authenticated by the transport, so the proof cannot be replayed by other peers. At 28 bytes
+/// plus the peer ID (38 bytes for ed25519 keys), it cannot be mistaken for a witnessed event or a
+/// feed report.
+pub fn handshake_message(peer_id: &PeerId) -> Vec<u8> {
+	let mut message = b"vstreams/gossip-handshake/1:".to_vec();
+	message.extend_from_slice(&peer_id.to_bytes());
+	message
+}
+
+/// A proof that the sender of the handshake holds an authority key: a signature of
+/// [handshake_message] for the sender's own gossip peer ID.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorityProof {
+	/// The public key of the authority
+	pub pub_key: Vec<u8>,
+	/// The signature of the handshake message by that key
+	pub signature: Vec<u8>,
+}
+
+/// Decides which peers may take part in the gossip, based on the [AuthorityProof]-s they present.
+#[async_trait]
+pub trait GossipAuthenticator {
+	/// Returns a proof that the current node holds a current authority key, bound to its gossip
+	/// peer ID; [None] if it holds none (e.g. for sentry nodes).
+	async fn create_proof(&self, local_peer_id: &PeerId) -> Result<Option<AuthorityProof>, Error>;
+
+	/// Checks that a proof is signed by a current authority for the given peer ID.
+	fn verify_proof(&self, peer_id: &PeerId, proof: &AuthorityProof) -> Result<(), Error>;
+}
+
+/// Tracks which connected peers have been authenticated, and thus have their messages accepted.
+/// Messages relayed by peers whose handshake is still in progress are held until it completes, as
+/// reporting them to gossipsub right away would have them dropped as duplicates when other peers
+/// relay them later.
+pub(super) struct PeerAuthentication {
+	authenticator: Option<Arc<dyn GossipAuthenticator + Send + Sync>>,
+	allowed_peers: HashSet<PeerId>,
+	authenticated: HashMap<PeerId, AuthorityProof>,
+	pending: HashMap<PeerId, Vec<(MessageId, GossipsubMessage)>>,
+}
+
+impl PeerAuthentication {
+	/// Creates a [PeerAuthentication] which accepts all peers, as long as there is no
+	/// authenticator.
+	pub(super) fn new(
+		authenticator: Option<Arc<dyn GossipAuthenticator + Send + Sync>>,
+		allowed_peers: HashSet<PeerId>,
+	) -> Self {
+		Self {
+			authenticator,
+			allowed_peers,
+			authenticated: HashMap::new(),
+			pending: HashMap::new(),
+		}
+	}
+
+	/// Whether messages from the peer are accepted.
+	pub(super) fn is_authenticated(&self, peer_id: &PeerId) -> bool {
+		self.authenticator.is_none() ||
+			self.allowed_peers.contains(peer_id) ||
+			self.authenticated.contains_key(peer_id)
+	}
+
+	/// Whether the peer may stay connected without presenting a valid proof.
+	pub(super) fn is_allowed(&self, peer_id: &PeerId) -> bool {
+		self.authenticator.is_none() || self.allowed_peers.contains(peer_id)
+	}
+
+	/// Returns the proof of the current node, to send to a newly-connected peer.
+	pub(super) async fn create_proof(&self, local_peer_id: &PeerId) -> Option<AuthorityProof> {
+		let authenticator = self.authenticator.as_ref()?;
+		match authenticator.create_proof(local_peer_id).await {
+			Ok(proof) => proof,
+			Err(e) => {
+				log::warn!("Failed creating gossip handshake proof: {e}");
+				None
+			},
+		}
+	}
+
+	/// Verifies the proof presented by a peer, authenticating it if the proof is valid. Returns
+	/// whether the peer may stay connected.
+	pub(super) fn receive_proof(
+		&mut self,
+		peer_id: &PeerId,
+		proof: Option<AuthorityProof>,
+	) -> bool {
+		let authenticator = match &self.authenticator {
+			Some(authenticator) => authenticator,
+			None => return true,
+		};
+		match proof.map(|proof| (authenticator.verify_proof(peer_id, &proof), proof)) {
+			Some((Ok(()), proof)) => {
+				log::debug!("Authenticated gossip peer {peer_id}");
+				self.authenticated.insert(*peer_id, proof);
+				true
+			},
+			Some((Err(e), _)) => {
+				log::info!("Gossip peer {peer_id} presented an invalid proof: {e}");
+				self.is_allowed(peer_id)
+			},
+			None => self.is_allowed(peer_id) || self.authenticated.contains_key(peer_id),
+		}
+	}
+
+	/// Verifies again the proofs of all authenticated peers, e.g. after the authorities changed.
+	/// Returns the peers which are no longer authenticated and should be disconnected.
+	pub(super) fn reverify(&mut self) -> Vec<PeerId> {
+		let authenticator = match &self.authenticator {
+			Some(authenticator) => authenticator,
+			None => return Vec::new(),
+		};
+		let mut revoked = Vec::new();
+		self.authenticated.retain(|peer_id, proof| {
+			match authenticator.verify_proof(peer_id, proof) {
+				Ok(()) => true,
+				Err(e) => {
+					log::info!("Gossip peer {peer_id} is no longer authenticated: {e}");
+					revoked.push(*peer_id);
+					false
+				},
+			}
+		});
+		revoked.retain(|peer_id| !self.allowed_peers.contains(peer_id));
+		revoked
+	}
+
+	/// Holds a message relayed by a peer until its handshake completes. Returns the ID of the
+	/// message back if too many messages of the peer are held already.
+	pub(super) fn hold_message(
+		&mut self,
+		peer_id: PeerId,
+		message_id: MessageId,
+		message: GossipsubMessage,
+	) -> Result<(), MessageId> {
+		let pending = self.pending.entry(peer_id).or_default();
+		if pending.len() >= MAX_PENDING_MESSAGES {
+			return Err(message_id)
+		}
+		pending.push((message_id, message));
+		Ok(())
+	}
+
+	/// Returns the messages held for a peer, e.g. once it is authenticated.
+	pub(super) fn take_pending(&mut self, peer_id: &PeerId) -> Vec<(MessageId, GossipsubMessage)> {
+		self.pending.remove(peer_id).unwrap_or_default()
+	}
+
+	/// Forgets about a peer once it is no longer connected, returning the messages still held for
+	/// it.
+	pub(super) fn remove(&mut self, peer_id: &PeerId) -> Vec<(MessageId, GossipsubMessage)> {
+		self.authenticated.remove(peer_id);
+		self.take_pending(peer_id)
+	}
+}
+
+/// The libp2p protocol used for the handshake.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeProtocol;
+
+impl ProtocolName for HandshakeProtocol {
+	fn protocol_name(&self) -> &[u8] {
+		b"/vstreams/handshake/1"
+	}
+}
+
+/// Encodes handshake requests and responses, both of which carry the [AuthorityProof] of their
+/// sender, if it has one.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeCodec;
+
+#[async_trait]
+impl RequestResponseCodec for HandshakeCodec {
+	type Protocol = HandshakeProtocol;
+	type Request = Option<AuthorityProof>;
+	type Response = Option<AuthorityProof>;
+
+	async fn read_request<T>(
+		&mut self,
+		_: &HandshakeProtocol,
+		io: &mut T,
+	) -> io::Result<Self::Request>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		read_proof(io).await
+	}
+
+	async fn read_response<T>(
+		&mut self,
+		_: &HandshakeProtocol,
+		io: &mut T,
+	) -> io::Result<Self::Response>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		read_proof(io).await
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		_: &HandshakeProtocol,
+		io: &mut T,
+		request: Self::Request,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_proof(io, &request).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		_: &HandshakeProtocol,
+		io: &mut T,
+		response: Self::Response,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		write_proof(io, &response).await
+	}
+}
+
+async fn read_proof<T: AsyncRead + Unpin + Send>(io: &mut T) -> io::Result<Option<AuthorityProof>> {
+	let bytes = read_length_prefixed(io, MAX_HANDSHAKE_SIZE).await?;
+	bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_proof<T: AsyncWrite + Unpin + Send>(
+	io: &mut T,
+	proof: &Option<AuthorityProof>,
+) -> io::Result<()> {
+	let bytes =
+		bincode::serialize(proof).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	write_length_prefixed(io, bytes).await
+}
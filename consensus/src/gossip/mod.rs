@@ -1,5 +1,6 @@
 //! A module for gossiping messages with a swarm of peers.
 
+use crate::errors::Error;
 use async_trait::async_trait;
 use futures::{
 	channel::mpsc::{channel, Receiver, Sender},
@@ -8,26 +9,53 @@ use futures::{
 };
 use libp2p::{
 	core::{muxing::StreamMuxerBox, transport::Boxed, upgrade},
-	gossipsub::{self, Gossipsub, GossipsubEvent, IdentTopic, MessageAuthenticity},
+	gossipsub::{
+		self, Gossipsub, GossipsubEvent, GossipsubMessage, IdentTopic, MessageAcceptance,
+		MessageAuthenticity, MessageId, PeerScoreParams, PeerScoreThresholds, TopicHash,
+		TopicScoreParams,
+	},
 	identify::{Behaviour as Identify, Event as IdentifyEvent},
 	identity::{self, Keypair},
 	kad::{record::store::MemoryStore, Kademlia},
 	mdns::tokio::Behaviour as MDns,
 	mplex,
-	swarm::{NetworkBehaviour, SwarmEvent},
-	tcp, tls, Multiaddr, PeerId, Swarm, Transport,
+	request_response::{
+		ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+		RequestResponseMessage,
+	},
+	swarm::{behaviour::toggle::Toggle, NetworkBehaviour, SwarmEvent},
+	tcp, tls, yamux, Multiaddr, PeerId, Swarm, Transport,
 };
 
-use std::sync::Arc;
+use std::{
+	collections::HashSet,
+	fs,
+	io::{self, Write},
+	os::unix::fs::OpenOptionsExt,
+	path::Path,
+	sync::Arc,
+	time::Duration,
+};
 #[cfg(test)]
 pub mod tests;
 
+mod catch_up;
+mod handshake;
+pub mod network;
+pub use catch_up::{CatchUpCodec, CatchUpProtocol};
+use handshake::PeerAuthentication;
+pub use handshake::{
+	handshake_message, AuthorityProof, GossipAuthenticator, HandshakeCodec, HandshakeProtocol,
+};
+
 #[derive(NetworkBehaviour)]
 struct GossipNetworkBehavior {
 	gossipsub: Gossipsub,
 	kademlia: Kademlia<MemoryStore>,
-	mdns: MDns,
+	mdns: Toggle<MDns>,
 	identify: Identify,
+	handshake: RequestResponse<HandshakeCodec>,
+	catch_up: RequestResponse<CatchUpCodec>,
 }
 
 /// Represents an internal message passed between the public Gossip interface and the
@@ -36,22 +64,25 @@ enum GossipOrder {
 	SendMessage(IdentTopic, Vec<u8>),
 	DialPeers(Vec<Multiaddr>),
 	Listen(Multiaddr),
+	Request(Vec<u8>),
+	Reauthenticate,
 }
 
 /// A struct which can be used to send messages to a libp2p gossipsub(+kademlia) network.
 /// Cloning it is safe and reuses the same swarm and gossip network.
 /// # Example Usage
 /// ```
-/// # use consensus_validated_streams::gossip::{Gossip, GossipHandler};
+/// # use consensus_validated_streams::gossip::{Gossip, GossipHandler, MessageVerdict};
 /// # use std::sync::Arc;
 /// # use async_trait::async_trait;
-/// use libp2p::gossipsub::IdentTopic;
+/// use libp2p::gossipsub::{IdentTopic, TopicHash};
 /// struct ExampleHandler {}
 /// #[async_trait]
 /// impl GossipHandler for ExampleHandler {
 ///     fn get_topics() -> Vec<IdentTopic> { vec!(IdentTopic::new("some_topic")) }
-///     async fn handle(&self, message: Vec<u8>) {
-///         println!("Received message! {:?}", message);
+///     async fn handle(&self, topic: &TopicHash, message: Vec<u8>) -> MessageVerdict {
+///         println!("Received message on {}! {:?}", topic, message);
+///         MessageVerdict::Accept
 ///     }
 /// }
 /// # async fn async_stuff() { // Only doctest compilation, as actual usage blocks forever
@@ -74,6 +105,145 @@ pub struct Gossip {
 #[must_use]
 pub struct GossipService {
 	rc: Receiver<GossipOrder>,
+	key: Keypair,
+	config: GossipConfig,
+	gossipsub_config: gossipsub::GossipsubConfig,
+	authentication: PeerAuthentication,
+}
+
+/// The stream multiplexer used by the gossip transport.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Multiplexer {
+	/// The mplex multiplexer
+	Mplex,
+	/// The yamux multiplexer
+	Yamux,
+}
+
+/// Tunable parameters of a [Gossip] and its swarm. The defaults match those of libp2p's
+/// gossipsub.
+#[derive(Debug, Clone)]
+pub struct GossipConfig {
+	/// How many orders can be queued for the [GossipService] before senders have to wait.
+	pub inbox_size: usize,
+	/// The target number of peers in the gossipsub mesh of each topic.
+	pub mesh_n: usize,
+	/// The number of peers below which more are added to the mesh.
+	pub mesh_n_low: usize,
+	/// The number of peers above which some are removed from the mesh.
+	pub mesh_n_high: usize,
+	/// The time between gossipsub heartbeats, which maintain the mesh and gossip message IDs.
+	pub heartbeat_interval: Duration,
+	/// The largest message that can be sent or received.
+	pub max_transmit_size: usize,
+	/// How long the IDs of seen messages are remembered, so that duplicates are ignored.
+	pub duplicate_cache_time: Duration,
+	/// Whether to discover peers on the local network with mDNS.
+	pub mdns: bool,
+	/// The stream multiplexer to use for connections.
+	pub multiplexer: Multiplexer,
+}
+
+impl Default for GossipConfig {
+	fn default() -> Self {
+		Self {
+			inbox_size: 64,
+			mesh_n: 6,
+			mesh_n_low: 5,
+			mesh_n_high: 12,
+			heartbeat_interval: Duration::from_secs(1),
+			max_transmit_size: 65536,
+			duplicate_cache_time: Duration::from_secs(60),
+			mdns: true,
+			multiplexer: Multiplexer::Mplex,
+		}
+	}
+}
+
+impl GossipConfig {
+	/// Builds the gossipsub configuration, failing if the parameters are inconsistent (e.g. if
+	/// the mesh sizes are not ordered).
+	fn gossipsub_config(&self) -> Result<gossipsub::GossipsubConfig, Error> {
+		gossipsub::GossipsubConfigBuilder::default()
+			.mesh_n(self.mesh_n)
+			.mesh_n_low(self.mesh_n_low)
+			.mesh_n_high(self.mesh_n_high)
+			// Keep the outbound quota within what gossipsub allows for the mesh sizes; this is 2
+			// (the gossipsub default) for the default sizes
+			.mesh_outbound_min(self.mesh_n_low.min(self.mesh_n / 2).saturating_sub(1))
+			.heartbeat_interval(self.heartbeat_interval)
+			.max_transmit_size(self.max_transmit_size)
+			.duplicate_cache_time(self.duplicate_cache_time)
+			// Messages are validated manually, so that only messages relayed by authenticated
+			// peers are propagated further
+			.validate_messages()
+			.build()
+			.map_err(|e| Error::Other(format!("Invalid gossipsub configuration: {e}")))
+	}
+}
+
+/// The penalty for each invalid message a peer relays on a topic. Since the penalty grows with the
+/// square of the number of invalid messages, a peer relaying 3 of them within a minute or so
+/// drops below the graylist threshold and is disconnected.
+const INVALID_MESSAGE_WEIGHT: f64 = -10.0;
+
+/// How much of the invalid message count of a peer is kept every second.
+const INVALID_MESSAGE_DECAY: f64 = 0.99;
+
+/// Builds the gossipsub peer scoring parameters for the given topics. Peers are penalized for
+/// relaying messages which the [GossipHandler] rejects, but not for being quiet, as validators
+/// only gossip when events are witnessed.
+fn peer_score_params(topics: &[IdentTopic]) -> (PeerScoreParams, PeerScoreThresholds) {
+	let topic_params = TopicScoreParams {
+		topic_weight: 1.0,
+		mesh_message_deliveries_weight: 0.0,
+		mesh_failure_penalty_weight: 0.0,
+		invalid_message_deliveries_weight: INVALID_MESSAGE_WEIGHT,
+		invalid_message_deliveries_decay: INVALID_MESSAGE_DECAY,
+		..Default::default()
+	};
+	let params = PeerScoreParams {
+		topics: topics.iter().map(|topic| (topic.hash(), topic_params.clone())).collect(),
+		..Default::default()
+	};
+	(params, PeerScoreThresholds::default())
+}
+
+/// The outcome of handling a gossiped message, deciding whether it is relayed further and how the
+/// peer that relayed it is scored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageVerdict {
+	/// The message is valid, and is relayed to other peers
+	Accept,
+	/// The message is invalid (e.g. badly signed, or malformed); it is dropped, and the peer that
+	/// relayed it is penalized
+	Reject,
+	/// The message is not useful (e.g. a proof of an event which was already finalized, or one
+	/// signed by a key we do not know as an authority); it is dropped, but the peer that relayed
+	/// it is not penalized
+	Ignore,
+}
+
+impl MessageVerdict {
+	/// Combines the verdicts on the parts of a message, e.g. on the events of a batch: a message
+	/// with any rejected part is rejected, and otherwise, one with any accepted part is accepted.
+	pub fn combine(self, other: Self) -> Self {
+		match (self, other) {
+			(Self::Reject, _) | (_, Self::Reject) => Self::Reject,
+			(Self::Accept, _) | (_, Self::Accept) => Self::Accept,
+			_ => Self::Ignore,
+		}
+	}
+}
+
+impl From<MessageVerdict> for MessageAcceptance {
+	fn from(verdict: MessageVerdict) -> Self {
+		match verdict {
+			MessageVerdict::Accept => MessageAcceptance::Accept,
+			MessageVerdict::Reject => MessageAcceptance::Reject,
+			MessageVerdict::Ignore => MessageAcceptance::Ignore,
+		}
+	}
 }
 
 /// A handler for all messages received or sent by a [Gossip]
@@ -84,17 +254,54 @@ pub trait GossipHandler {
 	fn get_topics() -> Vec<IdentTopic>;
 
 	/// Handles a message received on any of the topics this [GossipHandler] is subscribed to,
-	/// *or* a message sent by the [Gossip] to other peers.
-	/// Currently, messages are not differentiated by topic or origin.
-	async fn handle(&self, message: Vec<u8>);
+	/// *or* a message sent by the [Gossip] to other peers, along with the topic it was sent on.
+	/// Currently, messages are not differentiated by origin. The returned [MessageVerdict] decides
+	/// whether a received message is relayed further; it is disregarded for sent messages.
+	async fn handle(&self, topic: &TopicHash, message: Vec<u8>) -> MessageVerdict;
+
+	/// Checks a received message without processing it, for transports which decide whether to
+	/// relay a message before it is handled (see [network]). Only the checks that do not depend on
+	/// the processing of other messages (e.g. decoding and signatures) belong here. By default, all
+	/// messages are accepted.
+	fn validate(&self, _topic: &TopicHash, _message: &[u8]) -> MessageVerdict {
+		MessageVerdict::Accept
+	}
+
+	/// Answers a request sent by a peer with [Gossip::request_from_peers], e.g. for messages it
+	/// missed while offline. By default, requests are answered with an empty response.
+	async fn handle_request(&self, _request: Vec<u8>) -> Vec<u8> {
+		Vec::new()
+	}
+
+	/// Handles the response of a peer to a request sent with [Gossip::request_from_peers].
+	async fn handle_response(&self, _response: Vec<u8>) {}
 }
 
 impl Gossip {
-	/// Creates a new [Gossip] and a [GossipService] that can be used to start it.
+	/// Creates a new [Gossip] and a [GossipService] that can be used to start it, using a fresh
+	/// identity key; the peer ID of the gossip will change every time.
 	pub fn create() -> (Self, GossipService) {
-		let (tx, rc) = channel(64); // TODO: make inbox size configurable?
+		Self::create_with_key(identity::Keypair::generate_ed25519())
+	}
 
-		(Self { tx }, GossipService { rc })
+	/// Creates a new [Gossip] and a [GossipService] that can be used to start it, using the given
+	/// identity key (and thus, the peer ID derived from it).
+	pub fn create_with_key(key: Keypair) -> (Self, GossipService) {
+		Self::create_with_config(key, GossipConfig::default())
+			.expect("The default gossip configuration is valid")
+	}
+
+	/// Creates a new [Gossip] and a [GossipService] that can be used to start it, using the given
+	/// identity key and [GossipConfig].
+	pub fn create_with_config(
+		key: Keypair,
+		config: GossipConfig,
+	) -> Result<(Self, GossipService), Error> {
+		let gossipsub_config = config.gossipsub_config()?;
+		let (tx, rc) = channel(config.inbox_size);
+
+		let authentication = PeerAuthentication::new(None, HashSet::new());
+		Ok((Self { tx }, GossipService { rc, key, config, gossipsub_config, authentication }))
 	}
 
 	/// Publishes a message to peers subscribed to a specific topic
@@ -112,6 +319,18 @@ impl Gossip {
 		self.send_order(GossipOrder::Listen(address)).await;
 	}
 
+	/// Sends a request to all connected (and authenticated) peers; their responses are passed to
+	/// [GossipHandler::handle_response] as they arrive.
+	pub async fn request_from_peers(&mut self, request: Vec<u8>) {
+		self.send_order(GossipOrder::Request(request)).await;
+	}
+
+	/// Verifies again the proofs presented by authenticated peers, disconnecting those which no
+	/// longer hold a current authority key. To be called whenever the authorities change.
+	pub async fn reauthenticate_peers(&mut self) {
+		self.send_order(GossipOrder::Reauthenticate).await;
+	}
+
 	/// Send an order to the internal channel between the Gossip and
 	/// GossipService::run -- creating an "Actor" model out of the two.
 	async fn send_order(&mut self, order: GossipOrder) {
@@ -123,28 +342,48 @@ impl Gossip {
 }
 
 impl GossipService {
+	/// Requires peers to authenticate using the given [GossipAuthenticator] when they connect.
+	/// Peers which fail to present a valid [AuthorityProof] are disconnected and messages they
+	/// relay are dropped, except for the `allowed_peers` (e.g. sentry nodes), which may stay
+	/// connected without a proof. Messages relayed while the handshake is in progress are held
+	/// until it completes. Without an authenticator, all peers are accepted.
+	pub fn with_authenticator(
+		mut self,
+		authenticator: Arc<dyn GossipAuthenticator + Send + Sync>,
+		allowed_peers: HashSet<PeerId>,
+	) -> Self {
+		self.authentication = PeerAuthentication::new(Some(authenticator), allowed_peers);
+		self
+	}
+
 	/// Starts the gossip service. This function never returns, so make sure to spawn it as a
 	/// separate task.
 	pub async fn run<H: GossipHandler + Send + Sync + 'static>(self, handler: Arc<H>) -> ! {
-		let mut swarm = Self::create_swarm();
+		let mut swarm = Self::create_swarm(self.key, &self.config, self.gossipsub_config);
 
-		for topic in H::get_topics() {
-			swarm.behaviour_mut().gossipsub.subscribe(&topic).ok();
+		let topics = H::get_topics();
+		for topic in &topics {
+			swarm.behaviour_mut().gossipsub.subscribe(topic).ok();
+		}
+		let (params, thresholds) = peer_score_params(&topics);
+		if let Err(e) = swarm.behaviour_mut().gossipsub.with_peer_score(params, thresholds) {
+			log::error!("Failed enabling gossip peer scoring: {e}");
 		}
 
-		Self::run_loop(&mut swarm, self.rc, handler.as_ref()).await
+		Self::run_loop(&mut swarm, self.rc, self.authentication, handler.as_ref()).await
 	}
 
 	/// Runs a select loop that handles events from the network and from orders
 	async fn run_loop<H: GossipHandler + Send + Sync>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
 		mut rc: Receiver<GossipOrder>,
+		mut authentication: PeerAuthentication,
 		handler: &H,
 	) -> ! {
 		loop {
 			select! {
-				order = rc.select_next_some() => Self::handle_incoming_order(swarm, order, handler).await,
-				event = swarm.select_next_some() => Self::handle_incoming_event(swarm, event, handler).await,
+				order = rc.select_next_some() => Self::handle_incoming_order(swarm, order, &mut authentication, handler).await,
+				event = swarm.select_next_some() => Self::handle_incoming_event(swarm, event, &mut authentication, handler).await,
 			}
 		}
 	}
@@ -153,6 +392,7 @@ impl GossipService {
 	async fn handle_incoming_order<H: GossipHandler + Send>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
 		order: GossipOrder,
+		authentication: &mut PeerAuthentication,
 		handler: &H,
 	) {
 		match order {
@@ -160,7 +400,7 @@ impl GossipService {
 				if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, message.clone()) {
 					log::info!("Failed Gossiping message with Error: {:?}", e);
 				}
-				handler.handle(message).await;
+				handler.handle(&topic.hash(), message).await;
 				log::trace!("Gossiped a message!");
 			},
 			GossipOrder::DialPeers(peers) => {
@@ -172,6 +412,21 @@ impl GossipService {
 					log::info!("Failed listening on provided Address: {:?}", e);
 				}
 			},
+			GossipOrder::Request(request) => {
+				let peers = swarm
+					.connected_peers()
+					.filter(|peer_id| authentication.is_authenticated(peer_id))
+					.cloned()
+					.collect::<Vec<_>>();
+				log::debug!("Sending a request to {} peers", peers.len());
+				for peer_id in peers {
+					swarm.behaviour_mut().catch_up.send_request(&peer_id, request.clone());
+				}
+			},
+			GossipOrder::Reauthenticate =>
+				for peer_id in authentication.reverify() {
+					Self::disconnect_unauthenticated(swarm, peer_id);
+				},
 		}
 	}
 
@@ -179,20 +434,83 @@ impl GossipService {
 	async fn handle_incoming_event<H: GossipHandler + Send>(
 		swarm: &mut Swarm<GossipNetworkBehavior>,
 		event: SwarmEvent<GossipNetworkBehaviorEvent, impl std::fmt::Display>,
+		authentication: &mut PeerAuthentication,
 		handler: &H,
 	) {
 		match event {
 			SwarmEvent::NewListenAddr { address, .. } => log::info!("Listening on {:?}", address),
+			SwarmEvent::ConnectionEstablished { peer_id, num_established, .. }
+				if num_established.get() == 1 =>
+			{
+				let local_peer_id = *swarm.local_peer_id();
+				let proof = authentication.create_proof(&local_peer_id).await;
+				swarm.behaviour_mut().handshake.send_request(&peer_id, proof);
+			},
+			SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } =>
+				for (message_id, _) in authentication.remove(&peer_id) {
+					Self::ignore_message(swarm, &message_id, &peer_id);
+				},
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Handshake(
+				RequestResponseEvent::Message { peer, message },
+			)) => match message {
+				RequestResponseMessage::Request { request, channel, .. } => {
+					if authentication.receive_proof(&peer, request) {
+						let local_peer_id = *swarm.local_peer_id();
+						let proof = authentication.create_proof(&local_peer_id).await;
+						swarm.behaviour_mut().handshake.send_response(channel, proof).ok();
+						Self::handle_pending_messages(swarm, authentication, peer, handler).await;
+					} else {
+						Self::disconnect_unauthenticated(swarm, peer);
+					}
+				},
+				RequestResponseMessage::Response { response, .. } =>
+					if authentication.receive_proof(&peer, response) {
+						Self::handle_pending_messages(swarm, authentication, peer, handler).await;
+					} else {
+						Self::disconnect_unauthenticated(swarm, peer);
+					},
+			},
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::CatchUp(
+				RequestResponseEvent::Message { peer, message },
+			)) =>
+				if !authentication.is_authenticated(&peer) {
+					log::debug!("Ignoring request or response of unauthenticated peer {peer}");
+				} else {
+					match message {
+						RequestResponseMessage::Request { request, channel, .. } => {
+							let response = handler.handle_request(request).await;
+							swarm.behaviour_mut().catch_up.send_response(channel, response).ok();
+						},
+						RequestResponseMessage::Response { response, .. } =>
+							handler.handle_response(response).await,
+					}
+				},
+			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Handshake(
+				RequestResponseEvent::OutboundFailure { peer, error, .. },
+			)) =>
+				if !authentication.is_authenticated(&peer) {
+					log::info!("Gossip handshake with {peer} failed: {error}");
+					Self::disconnect_unauthenticated(swarm, peer);
+				},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
 				GossipsubEvent::Subscribed { peer_id, topic },
 			)) => {
 				log::info!("{:?} subscribed to topic {:?}", peer_id, topic);
 			},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Gossipsub(
-				GossipsubEvent::Message { message, .. },
-			)) => {
-				handler.handle(message.data).await;
-			},
+				GossipsubEvent::Message { propagation_source, message_id, message },
+			)) =>
+				if authentication.is_authenticated(&propagation_source) {
+					Self::handle_message(swarm, message_id, propagation_source, message, handler)
+						.await;
+				} else if let Err(message_id) =
+					authentication.hold_message(propagation_source, message_id, message)
+				{
+					log::debug!(
+						"Dropping message relayed by unauthenticated peer {propagation_source}"
+					);
+					Self::ignore_message(swarm, &message_id, &propagation_source);
+				},
 			SwarmEvent::Behaviour(GossipNetworkBehaviorEvent::Identify(
 				IdentifyEvent::Received { info, peer_id },
 			)) =>
@@ -203,6 +521,80 @@ impl GossipService {
 		}
 	}
 
+	/// Passes a message relayed by an authenticated peer to the handler, and reports the verdict
+	/// to gossipsub so that it is only propagated further if it was accepted
+	async fn handle_message<H: GossipHandler + Send>(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		message_id: MessageId,
+		propagation_source: PeerId,
+		message: GossipsubMessage,
+		handler: &H,
+	) {
+		let verdict = handler.handle(&message.topic, message.data).await;
+		swarm
+			.behaviour_mut()
+			.gossipsub
+			.report_message_validation_result(&message_id, &propagation_source, verdict.into())
+			.ok();
+		if verdict == MessageVerdict::Reject {
+			Self::disconnect_graylisted(swarm, propagation_source);
+		}
+	}
+
+	/// Handles the messages held while a peer was handshaking, once it completes; if the peer
+	/// turned out not to be authenticated, they are ignored instead
+	async fn handle_pending_messages<H: GossipHandler + Send>(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		authentication: &mut PeerAuthentication,
+		peer_id: PeerId,
+		handler: &H,
+	) {
+		let authenticated = authentication.is_authenticated(&peer_id);
+		for (message_id, message) in authentication.take_pending(&peer_id) {
+			if authenticated {
+				Self::handle_message(swarm, message_id, peer_id, message, handler).await;
+			} else {
+				Self::ignore_message(swarm, &message_id, &peer_id);
+			}
+		}
+	}
+
+	/// Tells gossipsub to neither propagate a message nor penalize the peer which relayed it
+	fn ignore_message(
+		swarm: &mut Swarm<GossipNetworkBehavior>,
+		message_id: &MessageId,
+		propagation_source: &PeerId,
+	) {
+		swarm
+			.behaviour_mut()
+			.gossipsub
+			.report_message_validation_result(
+				message_id,
+				propagation_source,
+				MessageAcceptance::Ignore,
+			)
+			.ok();
+	}
+
+	/// Disconnects a peer which failed to authenticate
+	fn disconnect_unauthenticated(swarm: &mut Swarm<GossipNetworkBehavior>, peer_id: PeerId) {
+		log::info!("Disconnecting unauthenticated gossip peer {peer_id}");
+		swarm.disconnect_peer_id(peer_id).ok();
+	}
+
+	/// Disconnects a peer whose score dropped below the graylist threshold, e.g. after relaying
+	/// too many invalid messages. Gossipsub ignores such peers, but keeps them connected.
+	fn disconnect_graylisted(swarm: &mut Swarm<GossipNetworkBehavior>, peer_id: PeerId) {
+		let graylist_threshold = PeerScoreThresholds::default().graylist_threshold;
+		match swarm.behaviour().gossipsub.peer_score(&peer_id) {
+			Some(score) if score < graylist_threshold => {
+				log::info!("Disconnecting gossip peer {peer_id} with a score of {score:.1}");
+				swarm.disconnect_peer_id(peer_id).ok();
+			},
+			_ => {},
+		}
+	}
+
 	/// Connects to a slice of peers
 	fn dial_peers(swarm: &mut Swarm<GossipNetworkBehavior>, peers: &[Multiaddr]) {
 		for peer in peers {
@@ -219,33 +611,36 @@ impl GossipService {
 	}
 
 	/// Creates a new gossipsub swarm
-	fn create_swarm() -> Swarm<GossipNetworkBehavior> {
-		let key = Self::create_keys();
-		let transport = Self::get_transport(key.clone());
-		let behaviour = Self::get_behaviour(key.clone());
+	fn create_swarm(
+		key: Keypair,
+		config: &GossipConfig,
+		gossipsub_config: gossipsub::GossipsubConfig,
+	) -> Swarm<GossipNetworkBehavior> {
+		let transport = Self::get_transport(key.clone(), config.multiplexer);
+		let behaviour = Self::get_behaviour(key.clone(), config.mdns, gossipsub_config);
 		let peer_id = PeerId::from(key.public());
 		log::info!("Validated Streams Gossip peer ID: {:?}", peer_id);
 		libp2p::Swarm::with_threadpool_executor(transport, behaviour, peer_id)
 	}
 
-	/// Creates a ed255519 nodekey for the swarm
-	fn create_keys() -> Keypair {
-		identity::Keypair::generate_ed25519()
-	}
-
-	/// Creates a tcp transport over mplex and tls
-	fn get_transport(key: Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
-		tcp::async_io::Transport::new(tcp::Config::default())
+	/// Creates a tcp transport over tls and the given multiplexer
+	fn get_transport(key: Keypair, multiplexer: Multiplexer) -> Boxed<(PeerId, StreamMuxerBox)> {
+		let transport = tcp::async_io::Transport::new(tcp::Config::default())
 			.upgrade(upgrade::Version::V1)
-			.authenticate(tls::Config::new(&key).expect("Failed using tls keys"))
-			.multiplex(mplex::MplexConfig::new())
-			.boxed()
+			.authenticate(tls::Config::new(&key).expect("Failed using tls keys"));
+		match multiplexer {
+			Multiplexer::Mplex => transport.multiplex(mplex::MplexConfig::new()).boxed(),
+			Multiplexer::Yamux => transport.multiplex(yamux::YamuxConfig::default()).boxed(),
+		}
 	}
 
 	/// Assembles a gossipsub behaviour
-	fn get_behaviour(key: Keypair) -> GossipNetworkBehavior {
+	fn get_behaviour(
+		key: Keypair,
+		mdns: bool,
+		gossipsub_config: gossipsub::GossipsubConfig,
+	) -> GossipNetworkBehavior {
 		let peer_id = PeerId::from(key.public());
-		let gossipsub_config = gossipsub::GossipsubConfig::default();
 		let mdns_config = libp2p::mdns::Config::default();
 		let identify_config =
 			libp2p::identify::Config::new("vstreams/1.0.0".to_string(), key.public());
@@ -255,7 +650,53 @@ impl GossipService {
 			gossipsub: gossipsub::Gossipsub::new(message_authenticity, gossipsub_config).unwrap(),
 			identify: Identify::new(identify_config),
 			kademlia: Kademlia::new(peer_id, MemoryStore::new(peer_id)),
-			mdns: MDns::new(mdns_config).expect("Failed to initialize mDNS"),
+			mdns: Toggle::from(
+				mdns.then(|| MDns::new(mdns_config).expect("Failed to initialize mDNS")),
+			),
+			handshake: RequestResponse::new(
+				HandshakeCodec,
+				[(HandshakeProtocol, ProtocolSupport::Full)],
+				RequestResponseConfig::default(),
+			),
+			catch_up: RequestResponse::new(
+				CatchUpCodec,
+				[(CatchUpProtocol, ProtocolSupport::Full)],
+				RequestResponseConfig::default(),
+			),
 		}
 	}
 }
+
+/// Loads the ed25519 identity key of the gossip from a file, generating a new key and saving it to
+/// the file if it does not exist yet. As with Substrate's `--node-key-file`, the file contains
+/// the 32 bytes of the secret key, either raw or hex-encoded.
+pub fn load_or_generate_key(path: &Path) -> io::Result<Keypair> {
+	match fs::read(path) {
+		Ok(bytes) => {
+			let text = String::from_utf8_lossy(&bytes).trim().to_owned();
+			let mut bytes = if text.len() == 64 {
+				hex::decode(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+			} else {
+				bytes
+			};
+			let secret = identity::ed25519::SecretKey::from_bytes(&mut bytes)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			Ok(identity::Keypair::Ed25519(secret.into()))
+		},
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			let secret = identity::ed25519::SecretKey::generate();
+			if let Some(dir) = path.parent() {
+				fs::create_dir_all(dir)?;
+			}
+			fs::OpenOptions::new()
+				.write(true)
+				.create_new(true)
+				.mode(0o600)
+				.open(path)?
+				.write_all(secret.as_ref())?;
+			log::info!("Generated a new gossip key in {}", path.display());
+			Ok(identity::Keypair::Ed25519(secret.into()))
+		},
+		Err(e) => Err(e),
+	}
+}
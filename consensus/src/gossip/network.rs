@@ -0,0 +1,270 @@
+//! Transport of the gossip over Substrate's own network, as a notification protocol next to
+//! GRANDPA's, instead of a separate libp2p swarm.
+
+use super::{GossipHandler, GossipOrder, GossipService, MessageVerdict};
+use futures::{
+	channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+	prelude::*,
+	select, stream,
+};
+use libp2p::{gossipsub::TopicHash, PeerId};
+use sc_network::{
+	config::{NonDefaultSetConfig, NonReservedPeerMode, SetConfig},
+	ProtocolName, ReputationChange,
+};
+use sc_network_gossip::{
+	GossipEngine, Network, Syncing, TopicNotification, ValidationResult, Validator,
+	ValidatorContext,
+};
+use serde::{Deserialize, Serialize};
+use sp_api::BlockT;
+use sp_runtime::traits::{Hash as HashT, Header as HeaderT};
+use std::{
+	collections::{HashMap, HashSet},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+/// The name of the notification protocol, without the genesis hash and fork id prefix.
+const PROTOCOL_NAME: &str = "/validated-streams/1";
+
+/// The reputation change of a peer which sent a message the [GossipHandler] rejected.
+const INVALID_MESSAGE_REPUTATION: i32 = -(1 << 12);
+
+/// The largest notification accepted by the protocol.
+const MAX_NOTIFICATION_SIZE: u64 = 1024 * 1024;
+
+/// How long messages are kept around for peers which connect after they were first received.
+const MESSAGE_LIFETIME: Duration = Duration::from_secs(120);
+
+/// Returns the name of the notification protocol for a chain, prefixed by its genesis hash and
+/// fork id the same way GRANDPA's protocol name is.
+pub fn protocol_standard_name<Hash: AsRef<[u8]>>(
+	genesis_hash: &Hash,
+	fork_id: Option<&str>,
+) -> ProtocolName {
+	let genesis_hash = hex::encode(genesis_hash);
+	match fork_id {
+		Some(fork_id) => format!("/{genesis_hash}/{fork_id}{PROTOCOL_NAME}"),
+		None => format!("/{genesis_hash}{PROTOCOL_NAME}"),
+	}
+	.into()
+}
+
+/// Returns the configuration of the peer set of the notification protocol, to be added to
+/// [sc_network::config::NetworkConfiguration::extra_sets] before the network is built. As with
+/// GRANDPA, peers are not discovered separately; instead, every peer the node syncs with is also
+/// gossiped with.
+pub fn peers_set_config(protocol_name: ProtocolName) -> NonDefaultSetConfig {
+	NonDefaultSetConfig {
+		notifications_protocol: protocol_name,
+		fallback_names: Vec::new(),
+		max_notification_size: MAX_NOTIFICATION_SIZE,
+		handshake: None,
+		set_config: SetConfig {
+			in_peers: 0,
+			out_peers: 0,
+			reserved_nodes: Vec::new(),
+			non_reserved_mode: NonReservedPeerMode::Deny,
+		},
+	}
+}
+
+/// A message as sent over the notification protocol; unlike gossipsub, notifications do not carry
+/// a topic of their own.
+#[derive(Serialize, Deserialize)]
+struct NetworkMessage {
+	topic: String,
+	data: Vec<u8>,
+}
+
+/// Returns the gossip engine topic corresponding to a gossipsub topic.
+fn engine_topic<Block: BlockT>(topic: &TopicHash) -> Block::Hash {
+	<<Block::Header as HeaderT>::Hashing as HashT>::hash(topic.as_str().as_bytes())
+}
+
+/// A [Validator] which keeps the messages on the topics of a [GossipHandler] for
+/// [MESSAGE_LIFETIME], so that they are relayed to other peers and to peers which connect later.
+/// Messages are checked with [GossipHandler::validate] first; those it does not accept are neither
+/// kept nor relayed, and the senders of those it rejects are passed on to be reported.
+struct TopicValidator<Block: BlockT, H> {
+	topics: HashSet<Block::Hash>,
+	received: Mutex<HashMap<Block::Hash, Instant>>,
+	handler: Arc<H>,
+	rejected: UnboundedSender<PeerId>,
+}
+
+impl<Block: BlockT, H> TopicValidator<Block, H> {
+	fn new(topics: HashSet<Block::Hash>, handler: Arc<H>) -> (Self, UnboundedReceiver<PeerId>) {
+		let (rejected, rejected_rc) = unbounded();
+		(Self { topics, received: Mutex::new(HashMap::new()), handler, rejected }, rejected_rc)
+	}
+
+	/// Starts the lifetime of a message.
+	fn note_message(&self, data: &[u8]) {
+		if let Ok(mut received) = self.received.lock() {
+			received.entry(message_hash::<Block>(data)).or_insert_with(Instant::now);
+		}
+	}
+}
+
+fn message_hash<Block: BlockT>(data: &[u8]) -> Block::Hash {
+	<<Block::Header as HeaderT>::Hashing as HashT>::hash(data)
+}
+
+impl<Block: BlockT, H: GossipHandler + Send + Sync> Validator<Block> for TopicValidator<Block, H> {
+	fn validate(
+		&self,
+		_context: &mut dyn ValidatorContext<Block>,
+		sender: &PeerId,
+		data: &[u8],
+	) -> ValidationResult<Block::Hash> {
+		match bincode::deserialize::<NetworkMessage>(data) {
+			Ok(message) => {
+				let topic_hash = TopicHash::from_raw(message.topic);
+				let topic = engine_topic::<Block>(&topic_hash);
+				if !self.topics.contains(&topic) {
+					return ValidationResult::Discard
+				}
+				match self.handler.validate(&topic_hash, &message.data) {
+					MessageVerdict::Accept => {
+						self.note_message(data);
+						ValidationResult::ProcessAndKeep(topic)
+					},
+					MessageVerdict::Reject => {
+						self.rejected.unbounded_send(*sender).ok();
+						ValidationResult::Discard
+					},
+					MessageVerdict::Ignore => ValidationResult::Discard,
+				}
+			},
+			Err(e) => {
+				log::debug!("Discarding malformed gossip message from {sender}: {e}");
+				ValidationResult::Discard
+			},
+		}
+	}
+
+	fn message_expired<'a>(&'a self) -> Box<dyn FnMut(Block::Hash, &[u8]) -> bool + 'a> {
+		let received = self.received.lock().map(|mut received| {
+			received.retain(|_, time| time.elapsed() < MESSAGE_LIFETIME);
+			received.keys().cloned().collect::<HashSet<_>>()
+		});
+		Box::new(move |_topic, data| match &received {
+			Ok(received) => !received.contains(&message_hash::<Block>(data)),
+			Err(_) => true,
+		})
+	}
+}
+
+impl GossipService {
+	/// Starts the gossip service over Substrate's network, using the notification protocol
+	/// registered with [peers_set_config]. This function only returns once the network shuts
+	/// down, so make sure to spawn it as a separate task. Listen and dial orders are ignored, as
+	/// the Substrate network manages its own addresses and peers, and so is the identity key of
+	/// the [GossipService]; nor are peers authenticated beyond what the Substrate network does.
+	/// Requests (see [super::Gossip::request_from_peers]) are not supported either. Messages are
+	/// only relayed once [GossipHandler::validate] accepts them, but before they are handled, so
+	/// checks that [GossipHandler::handle] alone performs do not stop them from spreading. Peers
+	/// sending messages either of them rejects lose reputation, and are eventually disconnected
+	/// by the Substrate network.
+	pub async fn run_on_network<Block, N, S, H>(
+		self,
+		network: N,
+		sync: S,
+		protocol_name: ProtocolName,
+		handler: Arc<H>,
+	) where
+		Block: BlockT,
+		N: Network<Block> + Send + Clone + 'static,
+		S: Syncing<Block> + Send + Clone + 'static,
+		H: GossipHandler + Send + Sync + 'static,
+	{
+		let topics = H::get_topics()
+			.iter()
+			.map(|topic| engine_topic::<Block>(&topic.hash()))
+			.collect::<HashSet<_>>();
+		let (validator, mut rejected) =
+			TopicValidator::<Block, H>::new(topics.clone(), handler.clone());
+		let validator = Arc::new(validator);
+		let mut engine = GossipEngine::new(network, sync, protocol_name, validator.clone(), None);
+		let mut incoming =
+			stream::select_all(topics.into_iter().map(|topic| engine.messages_for(topic)));
+		let mut rc = self.rc;
+
+		loop {
+			select! {
+				order = rc.select_next_some() =>
+					Self::handle_network_order(&mut engine, validator.as_ref(), order, handler.as_ref()).await,
+				notification = incoming.select_next_some() =>
+					Self::handle_network_notification(&engine, notification, handler.as_ref()).await,
+				sender = rejected.select_next_some() => Self::report_invalid(&engine, sender),
+				_ = future::poll_fn(|cx| engine.poll_unpin(cx)).fuse() => {
+					log::info!("Substrate network shut down; stopping the gossip");
+					return
+				},
+			}
+		}
+	}
+
+	/// Handles an incoming channel order when gossiping over Substrate's network
+	async fn handle_network_order<Block: BlockT, H: GossipHandler + Send>(
+		engine: &mut GossipEngine<Block>,
+		validator: &TopicValidator<Block, H>,
+		order: GossipOrder,
+		handler: &H,
+	) {
+		let (topic, message) = match order {
+			GossipOrder::SendMessage(topic, message) => (topic, message),
+			GossipOrder::DialPeers(_) | GossipOrder::Listen(_) | GossipOrder::Reauthenticate => {
+				log::debug!("Ignoring gossip order, peers are managed by the Substrate network");
+				return
+			},
+			GossipOrder::Request(_) => {
+				log::debug!(
+					"Ignoring gossip request, requests are not sent over the Substrate network"
+				);
+				return
+			},
+		};
+		let topic_hash = topic.hash();
+		let network_message =
+			NetworkMessage { topic: topic_hash.to_string(), data: message.clone() };
+		match bincode::serialize(&network_message) {
+			Ok(data) => {
+				validator.note_message(&data);
+				engine.gossip_message(engine_topic::<Block>(&topic_hash), data, false);
+			},
+			Err(e) => log::info!("Failed Gossiping message with Error: {:?}", e),
+		}
+		handler.handle(&topic_hash, message).await;
+		log::trace!("Gossiped a message!");
+	}
+
+	/// Passes a message received over Substrate's network to the handler, lowering the
+	/// reputation of the sender if the handler rejects it
+	async fn handle_network_notification<Block: BlockT, H: GossipHandler + Send>(
+		engine: &GossipEngine<Block>,
+		notification: TopicNotification,
+		handler: &H,
+	) {
+		let verdict = match bincode::deserialize::<NetworkMessage>(&notification.message) {
+			Ok(message) => handler.handle(&TopicHash::from_raw(message.topic), message.data).await,
+			Err(e) => {
+				log::error!("failed deserilizing message data due to error:{:?}", e);
+				MessageVerdict::Reject
+			},
+		};
+		if let (MessageVerdict::Reject, Some(sender)) = (verdict, notification.sender) {
+			Self::report_invalid(engine, sender);
+		}
+	}
+
+	/// Lowers the reputation of a peer which sent an invalid message
+	fn report_invalid<Block: BlockT>(engine: &GossipEngine<Block>, sender: PeerId) {
+		engine.report(
+			sender,
+			ReputationChange::new(INVALID_MESSAGE_REPUTATION, "Invalid Validated Streams gossip"),
+		);
+	}
+}
@@ -1,15 +1,32 @@
-use super::{Gossip, GossipHandler};
-use crate::proofs::WitnessedEvent;
+use super::{
+	handshake::PeerAuthentication, handshake_message, load_or_generate_key, peer_score_params,
+	AuthorityProof, Gossip, GossipAuthenticator, GossipConfig, GossipHandler, MessageVerdict,
+};
+use crate::{errors::Error, proofs::WitnessedEvent};
 use async_trait::async_trait;
-use libp2p::{gossipsub::IdentTopic, Multiaddr};
+use libp2p::{
+	gossipsub::{GossipsubMessage, IdentTopic, MessageId, TopicHash},
+	Multiaddr, PeerId,
+};
 use sp_core::sr25519::Public;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::{
-	sync::{Arc, Mutex},
+	collections::HashSet,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
 	time::Duration,
 };
 pub struct MockGossipHandler {
 	messages: Mutex<Vec<WitnessedEvent>>,
+	responses: Mutex<Vec<Vec<u8>>>,
+}
+
+impl MockGossipHandler {
+	fn new() -> Self {
+		Self { messages: Mutex::new(Vec::new()), responses: Mutex::new(Vec::new()) }
+	}
 }
 #[async_trait]
 impl GossipHandler for MockGossipHandler {
@@ -17,14 +34,26 @@ impl GossipHandler for MockGossipHandler {
 		vec![IdentTopic::new("WitnessedEvent")]
 	}
 
-	async fn handle(&self, message: Vec<u8>) {
+	async fn handle(&self, _topic: &TopicHash, message: Vec<u8>) -> MessageVerdict {
 		match bincode::deserialize::<WitnessedEvent>(message.as_slice()) {
 			Ok(witnessed_event) => {
 				self.messages.lock().unwrap().push(witnessed_event);
+				MessageVerdict::Accept
+			},
+			Err(e) => {
+				log::error!("failed deserilizing message data due to error:{:?}", e);
+				MessageVerdict::Reject
 			},
-			Err(e) => log::error!("failed deserilizing message data due to error:{:?}", e),
 		}
 	}
+
+	async fn handle_request(&self, request: Vec<u8>) -> Vec<u8> {
+		request.into_iter().rev().collect()
+	}
+
+	async fn handle_response(&self, response: Vec<u8>) {
+		self.responses.lock().unwrap().push(response);
+	}
 }
 /// test receiving messages from other peers by creating a mock service that listens on a different
 /// Multiaddr and test that messages sent from self should not be received
@@ -35,8 +64,8 @@ pub async fn test_self_message() {
 	let (mut mock_peer_gossip, mock_peer_service) = Gossip::create();
 	let self_addr: Multiaddr = "/ip4/127.0.0.1/tcp/10001".to_string().parse().unwrap();
 	let peer_mock_addr: Multiaddr = "/ip4/127.0.0.1/tcp/10002".to_string().parse().unwrap();
-	let handler_self = Arc::new(MockGossipHandler { messages: Mutex::new(Vec::new()) });
-	let handler_peer_mock = Arc::new(MockGossipHandler { messages: Mutex::new(Vec::new()) });
+	let handler_self = Arc::new(MockGossipHandler::new());
+	let handler_peer_mock = Arc::new(MockGossipHandler::new());
 	let witnessed_event = create_witnessed_event();
 	//connections to self should be rejected
 
@@ -70,10 +99,238 @@ pub async fn test_self_message() {
 	assert_eq!(handler_peer_mock.messages.lock().unwrap().get(0).unwrap(), &witnessed_event);
 }
 
+/// test that requests are answered by the handlers of connected peers, and that the responses are
+/// passed back to the handler of the requesting peer
+#[tokio::test]
+pub async fn test_request_from_peers() {
+	let (mut streams_gossip, service) = Gossip::create();
+	let (mut mock_peer_gossip, mock_peer_service) = Gossip::create();
+	let peer_mock_addr: Multiaddr = "/ip4/127.0.0.1/tcp/10004".to_string().parse().unwrap();
+	let handler_self = Arc::new(MockGossipHandler::new());
+	let handler_peer_mock = Arc::new(MockGossipHandler::new());
+
+	let handler_self_c = handler_self.clone();
+	tokio::spawn(async move {
+		service.run(handler_self_c).await;
+	});
+	mock_peer_gossip.listen(peer_mock_addr.clone()).await;
+	let handler_peer_mock_c = handler_peer_mock.clone();
+	tokio::spawn(async move {
+		mock_peer_service.run(handler_peer_mock_c).await;
+	});
+
+	// wait for the peer to start, then for the connection to be established
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	streams_gossip.connect_to(vec![peer_mock_addr]).await;
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+
+	streams_gossip.request_from_peers(vec![1, 2, 3]).await;
+
+	// wait for the response
+	tokio::time::sleep(Duration::from_millis(1000)).await;
+	assert_eq!(*handler_self.responses.lock().unwrap(), vec![vec![3, 2, 1]]);
+	assert!(handler_peer_mock.responses.lock().unwrap().is_empty());
+}
+
 fn create_witnessed_event() -> WitnessedEvent {
 	WitnessedEvent {
+		stream_id: sp_core::H256::zero(),
 		event_id: sp_core::H256::repeat_byte(0),
+		conflict_set: None,
 		pub_key: CryptoTypePublicPair::from(Public::from_h256(sp_core::H256::repeat_byte(0))),
 		signature: vec![],
 	}
 }
+
+/// test that a generated gossip key is persisted and loaded again, and that hex-encoded keys (as
+/// written for Substrate's --node-key-file) are accepted
+#[test]
+fn test_load_or_generate_key() {
+	let dir = std::env::temp_dir().join(format!("vstreams-test-gossip-key-{}", std::process::id()));
+	let path = dir.join("gossip_secret_ed25519");
+
+	let key = load_or_generate_key(&path).unwrap();
+	let reloaded_key = load_or_generate_key(&path).unwrap();
+	assert_eq!(key.public(), reloaded_key.public());
+
+	let raw_secret = std::fs::read(&path).unwrap();
+	assert_eq!(raw_secret.len(), 32);
+	let hex_path = dir.join("gossip_secret_ed25519_hex");
+	std::fs::write(&hex_path, hex::encode(&raw_secret)).unwrap();
+	assert_eq!(load_or_generate_key(&hex_path).unwrap().public(), key.public());
+
+	std::fs::write(&hex_path, b"not a key").unwrap();
+	assert!(load_or_generate_key(&hex_path).is_err());
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// An authenticator which "signs" handshakes by copying the handshake message, for a single key
+struct MockAuthenticator;
+#[async_trait]
+impl GossipAuthenticator for MockAuthenticator {
+	async fn create_proof(&self, local_peer_id: &PeerId) -> Result<Option<AuthorityProof>, Error> {
+		Ok(Some(AuthorityProof { pub_key: vec![1], signature: handshake_message(local_peer_id) }))
+	}
+
+	fn verify_proof(&self, peer_id: &PeerId, proof: &AuthorityProof) -> Result<(), Error> {
+		if proof.pub_key == vec![1] && proof.signature == handshake_message(peer_id) {
+			Ok(())
+		} else {
+			Err(Error::BadWitnessedEventSignature("Incorrect handshake signature".to_string()))
+		}
+	}
+}
+
+/// test that only peers presenting a proof for their own peer ID, or allowed peers, are accepted
+#[tokio::test]
+async fn test_peer_authentication() {
+	let authority = PeerId::random();
+	let impostor = PeerId::random();
+	let sentry = PeerId::random();
+	let mut authentication =
+		PeerAuthentication::new(Some(Arc::new(MockAuthenticator)), HashSet::from([sentry]));
+
+	let proof = authentication.create_proof(&authority).await;
+	assert!(proof.is_some());
+	assert!(!authentication.is_authenticated(&authority));
+	assert!(authentication.receive_proof(&authority, proof.clone()));
+	assert!(authentication.is_authenticated(&authority));
+
+	// Proofs are bound to the peer ID, so they cannot be replayed by other peers
+	assert!(!authentication.receive_proof(&impostor, proof));
+	assert!(!authentication.receive_proof(&impostor, None));
+	assert!(!authentication.is_authenticated(&impostor));
+
+	assert!(authentication.receive_proof(&sentry, None));
+	assert!(authentication.is_authenticated(&sentry));
+
+	authentication.remove(&authority);
+	assert!(!authentication.is_authenticated(&authority));
+
+	let mut open_authentication = PeerAuthentication::new(None, HashSet::new());
+	assert!(open_authentication.create_proof(&authority).await.is_none());
+	assert!(open_authentication.receive_proof(&impostor, None));
+	assert!(open_authentication.is_authenticated(&impostor));
+}
+
+/// An authenticator whose single key can be removed from the authorities
+#[derive(Default)]
+struct RevocableAuthenticator {
+	revoked: AtomicBool,
+}
+#[async_trait]
+impl GossipAuthenticator for RevocableAuthenticator {
+	async fn create_proof(&self, local_peer_id: &PeerId) -> Result<Option<AuthorityProof>, Error> {
+		MockAuthenticator.create_proof(local_peer_id).await
+	}
+
+	fn verify_proof(&self, peer_id: &PeerId, proof: &AuthorityProof) -> Result<(), Error> {
+		if self.revoked.load(Ordering::SeqCst) {
+			return Err(Error::UnknownSigner("handshake".to_string()))
+		}
+		MockAuthenticator.verify_proof(peer_id, proof)
+	}
+}
+
+/// test that messages of peers still handshaking are held until the handshake completes, and that
+/// peers are no longer authenticated once their keys are removed from the authorities
+#[tokio::test]
+async fn test_pending_messages_and_reverification() {
+	let authority = PeerId::random();
+	let sentry = PeerId::random();
+	let authenticator = Arc::new(RevocableAuthenticator::default());
+	let mut authentication =
+		PeerAuthentication::new(Some(authenticator.clone()), HashSet::from([sentry]));
+
+	let message = |index: u8| {
+		let message = GossipsubMessage {
+			source: None,
+			data: vec![index],
+			sequence_number: None,
+			topic: TopicHash::from_raw("test"),
+		};
+		(MessageId::new(&[index]), message)
+	};
+	for index in 0..64 {
+		let (message_id, message) = message(index);
+		assert!(authentication.hold_message(authority, message_id, message).is_ok());
+	}
+	let (message_id, overflow) = message(64);
+	assert_eq!(
+		authentication.hold_message(authority, message_id.clone(), overflow),
+		Err(message_id)
+	);
+
+	let proof = authentication.create_proof(&authority).await;
+	assert!(authentication.receive_proof(&authority, proof));
+	let pending = authentication.take_pending(&authority);
+	assert_eq!(pending.len(), 64);
+	assert_eq!(pending[0].1.data, vec![0]);
+	assert!(authentication.take_pending(&authority).is_empty());
+
+	let sentry_proof = authentication.create_proof(&sentry).await;
+	assert!(authentication.receive_proof(&sentry, sentry_proof));
+	assert!(authentication.reverify().is_empty());
+	authenticator.revoked.store(true, Ordering::SeqCst);
+	// Allowed peers stay connected even when their proofs are no longer valid
+	assert_eq!(authentication.reverify(), vec![authority]);
+	assert!(!authentication.is_authenticated(&authority));
+	assert!(authentication.is_authenticated(&sentry));
+
+	let (message_id, message) = message(0);
+	assert!(authentication.hold_message(authority, message_id, message).is_ok());
+	assert_eq!(authentication.remove(&authority).len(), 1);
+}
+
+/// test that the notification protocol name is prefixed by the genesis hash and fork id
+#[test]
+fn test_protocol_standard_name() {
+	let genesis_hash = sp_core::H256::repeat_byte(0xab);
+	assert_eq!(
+		super::network::protocol_standard_name(&genesis_hash, None).to_string(),
+		format!("/{}/validated-streams/1", "ab".repeat(32))
+	);
+	assert_eq!(
+		super::network::protocol_standard_name(&genesis_hash, Some("fork")).to_string(),
+		format!("/{}/fork/validated-streams/1", "ab".repeat(32))
+	);
+}
+
+/// test that gossip configurations with inconsistent mesh sizes are rejected
+#[test]
+fn test_gossip_config() {
+	let key = libp2p::identity::Keypair::generate_ed25519();
+	assert!(Gossip::create_with_config(key.clone(), GossipConfig::default()).is_ok());
+	let large_mesh =
+		GossipConfig { mesh_n: 20, mesh_n_low: 12, mesh_n_high: 40, ..Default::default() };
+	assert!(Gossip::create_with_config(key.clone(), large_mesh).is_ok());
+	let small_mesh =
+		GossipConfig { mesh_n: 2, mesh_n_low: 1, mesh_n_high: 3, ..Default::default() };
+	assert!(Gossip::create_with_config(key.clone(), small_mesh).is_ok());
+	let unordered_mesh = GossipConfig { mesh_n: 4, mesh_n_low: 5, ..Default::default() };
+	assert!(Gossip::create_with_config(key, unordered_mesh).is_err());
+}
+
+/// test that verdicts on batches are combined, and that peer scoring parameters are valid and
+/// graylist peers after a few invalid messages
+#[test]
+fn test_message_verdict() {
+	use MessageVerdict::*;
+	assert_eq!(Accept.combine(Reject), Reject);
+	assert_eq!(Ignore.combine(Reject), Reject);
+	assert_eq!(Ignore.combine(Accept), Accept);
+	assert_eq!(Ignore.combine(Ignore), Ignore);
+
+	let (params, thresholds) = peer_score_params(&[IdentTopic::new("WitnessedEvent")]);
+	assert!(params.validate().is_ok());
+	assert!(thresholds.validate().is_ok());
+	let topic_params = params.topics.values().next().unwrap();
+	let penalty = |invalid: f64| {
+		topic_params.topic_weight *
+			topic_params.invalid_message_deliveries_weight *
+			invalid * invalid
+	};
+	assert!(penalty(2.0) >= thresholds.graylist_threshold);
+	assert!(penalty(3.0) < thresholds.graylist_threshold);
+}
@@ -13,6 +13,7 @@ pub mod errors;
 pub mod events;
 pub mod gossip;
 pub mod node;
+pub mod payloads;
 pub mod proofs;
 pub mod server;
 pub mod traits;
@@ -22,6 +23,6 @@ pub use block_import::ValidatedStreamsBlockImport;
 
 pub use config::{ValidatedStreamsNetworkConfiguration, ValidatedStreamsNetworkParams};
 
-pub use node::{start, StartParams};
+pub use node::{start, StartParams, ValidatedStreamsServices};
 
 pub use events::BlockStateCache;
@@ -1,38 +1,52 @@
 //! A helper for starting all the components needed to run a full Validated Streams node
 
 use crate::{
-	config::ValidatedStreamsNetworkConfiguration,
-	events::{BlockStateCache, EventGossipHandler, EventValidator, EventWitnesser},
-	gossip::Gossip,
+	config::{GossipTransport, ValidatedStreamsNetworkConfiguration},
+	events::{
+		run_catch_up, send_heartbeats, watch_authority_changes, watch_chain_events,
+		AuthorityHandshake, BlockStateCache, ConflictSetVotes, EventGossipHandler,
+		EventStatusProvider, EventUpdates, EventValidator, EventWitnesser, MissingProofsReceiver,
+		PeerVersions, WitnessBatcher,
+	},
+	gossip::{self, Gossip},
+	payloads::EventPayloadsTrait,
 	proofs::EventProofsTrait,
-	server,
+	server::{self, AuthTokens},
+	traits::{EventStatusTrait, EventValidatorTrait, EventWitnesserTrait},
 };
 use codec::Codec;
 use futures::future;
 
 use pallet_validated_streams::ValidatedStreamsApi;
 use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
-use sc_network::config::NetworkConfiguration;
+use sc_network::{config::NetworkConfiguration, NetworkService, ProtocolName};
+use sc_network_sync::SyncingService;
 use sc_service::{error::Error as ServiceError, SpawnTaskHandle};
-use sc_transaction_pool_api::LocalTransactionPool;
+use sc_transaction_pool_api::{LocalTransactionPool, TransactionPool};
 use sp_api::{BlockT, HeaderT, ProvideRuntimeApi};
 use sp_blockchain::HeaderMetadata;
 use sp_consensus_aura::AuraApi;
 use sp_keystore::CryptoStore;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+
+/// How many [crate::events::EventUpdate]-s to buffer for slow subscribers.
+const EVENT_UPDATES_CAPACITY: usize = 1024;
 
 /// Parameters for the [start] function.
 pub struct StartParams<
 	Block: BlockT,
-	TxPool: LocalTransactionPool<Block = Block> + 'static,
+	TxPool: LocalTransactionPool<Block = Block> + TransactionPool<Block = Block> + 'static,
 	Client: Sync + Send + 'static,
 	EventProofs: EventProofsTrait + Sync + Send + 'static,
+	EventPayloads: EventPayloadsTrait + Sync + Send + 'static,
 > {
 	/// The spawn handle to launch services under.
 	pub spawn_handle: SpawnTaskHandle,
 	/// A reference to an [EventProofsTrait] instance for storing events proofs.
 	pub event_proofs: Arc<EventProofs>,
+	/// A reference to an [EventPayloadsTrait] instance for storing raw event payloads.
+	pub event_payloads: Arc<EventPayloads>,
 	/// The client.
 	pub client: Arc<Client>,
 	/// A keystore for signing witnessed events.
@@ -41,24 +55,47 @@ pub struct StartParams<
 	pub transaction_pool: Arc<TxPool>,
 	/// The substrate network configuration.
 	pub network_configuration: NetworkConfiguration,
+	/// The substrate network, used for gossiping with [GossipTransport::Substrate].
+	pub network: Arc<NetworkService<Block, <Block as BlockT>::Hash>>,
+	/// The syncing service of the substrate network, whose peers are gossiped with when using
+	/// [GossipTransport::Substrate].
+	pub sync_service: Arc<SyncingService<Block>>,
+	/// The name of the notification protocol used with [GossipTransport::Substrate], as
+	/// registered with the substrate network (see [gossip::network::peers_set_config]).
+	pub gossip_protocol_name: ProtocolName,
+	/// The events whose proofs are missing, to be requested from gossip peers.
+	pub missing_proofs: MissingProofsReceiver,
 	/// The validated streams -specific network configuration.
 	pub validated_streams_network_config: ValidatedStreamsNetworkConfiguration,
 	/// A cache for storing recently-accesed blocks.
 	pub block_state: BlockStateCache<Block>,
 }
 
+/// Handles to the services started by [start], for exposing them through other APIs (e.g. the
+/// node's JSON-RPC).
+#[derive(Clone)]
+pub struct ValidatedStreamsServices {
+	/// The [EventWitnesserTrait] instance used by the GRPC server.
+	pub event_witnesser: Arc<dyn EventWitnesserTrait + Send + Sync>,
+	/// The [EventValidatorTrait] instance used by the GRPC server.
+	pub event_validator: Arc<dyn EventValidatorTrait + Send + Sync>,
+	/// The [EventStatusTrait] instance used by the GRPC server.
+	pub event_status: Arc<dyn EventStatusTrait + Send + Sync>,
+}
+
 /// Start all the services of the Validated Streams node.
 /// This functions starts the gossip, event service, and the gRPC server for the current node, and
 /// configures their ports using the passed configuration.
 pub fn start<
 	Block: BlockT,
-	TxPool: LocalTransactionPool<Block = Block> + 'static,
+	TxPool: LocalTransactionPool<Block = Block> + TransactionPool<Block = Block> + 'static,
 	Client: Sync + Send + 'static,
 	EventProofs: EventProofsTrait + Sync + Send + 'static,
+	EventPayloads: EventPayloadsTrait + Sync + Send + 'static,
 	AuthorityId: Codec + Send + Sync + 'static,
 >(
-	params: StartParams<Block, TxPool, Client, EventProofs>,
-) -> Result<(), ServiceError>
+	params: StartParams<Block, TxPool, Client, EventProofs, EventPayloads>,
+) -> Result<ValidatedStreamsServices, ServiceError>
 where
 	CryptoTypePublicPair: for<'a> From<&'a AuthorityId>,
 	Client: HeaderMetadata<Block>
@@ -72,37 +109,209 @@ where
 	let StartParams {
 		spawn_handle,
 		event_proofs,
+		event_payloads,
 		client,
 		keystore,
 		transaction_pool: tx_pool,
 		validated_streams_network_config: vs_network_configuration,
 		network_configuration,
+		network,
+		sync_service,
+		gossip_protocol_name,
+		missing_proofs,
 		block_state,
 	} = params;
 
-	let (streams_gossip, streams_gossip_service) = Gossip::create();
+	// Without requests for missing proofs, a validator that was offline would never get the
+	// off-chain proofs of the events it missed
+	#[cfg(feature = "off-chain-proofs")]
+	if vs_network_configuration.gossip_transport == GossipTransport::Substrate {
+		return Err(ServiceError::Other(
+			"--gossip-transport substrate does not support catching up on missed proofs, so it \
+			 cannot be used with off-chain proofs"
+				.into(),
+		))
+	}
+	// The substrate transport does not authenticate peers, so it is only used when all are trusted
+	if vs_network_configuration.gossip_transport == GossipTransport::Substrate &&
+		!vs_network_configuration.gossip_trust_substrate_peers
+	{
+		return Err(ServiceError::Other(
+			"--gossip-transport substrate accepts gossip from peers that have not proven holding \
+			 an authority key; pass --gossip-trust-substrate-peers if all peers are trusted"
+				.into(),
+		))
+	}
+
+	// Unless a separate key is configured, the gossip shares its identity with the Substrate
+	// network, so that the peer IDs in --bootnodes are valid for gossiping too.
+	let shares_node_key = vs_network_configuration.gossip_key_file.is_none();
+	let (streams_gossip, streams_gossip_service) = match vs_network_configuration.gossip_transport {
+		GossipTransport::Libp2p => {
+			let gossip_key = match &vs_network_configuration.gossip_key_file {
+				Some(path) => gossip::load_or_generate_key(path),
+				None => network_configuration.node_key.clone().into_keypair(),
+			}
+			.map_err(|e| ServiceError::Other(format!("Failed loading the gossip key: {e}")))?;
+			let (streams_gossip, streams_gossip_service) =
+				Gossip::create_with_config(gossip_key, vs_network_configuration.gossip_config())
+					.map_err(|e| ServiceError::Other(e.to_string()))?;
+			// Only peers holding a current authority key (or explicitly allowed) may gossip with
+			// us
+			let streams_gossip_service = streams_gossip_service.with_authenticator(
+				Arc::new(AuthorityHandshake::<Block, Client, AuthorityId>::new(
+					client.clone(),
+					keystore.clone(),
+					block_state.clone(),
+				)),
+				vs_network_configuration.gossip_allowed_peers.iter().cloned().collect(),
+			);
+			(streams_gossip, streams_gossip_service)
+		},
+		// The Substrate network has an identity and a configuration of its own
+		GossipTransport::Substrate => Gossip::create_with_config(
+			libp2p::identity::Keypair::generate_ed25519(),
+			vs_network_configuration.gossip_config(),
+		)
+		.map_err(|e| ServiceError::Other(e.to_string()))?,
+	};
+
+	let event_updates = EventUpdates::new(EVENT_UPDATES_CAPACITY);
+	let conflict_votes = Arc::new(ConflictSetVotes::default());
+	let peer_versions = Arc::new(PeerVersions::default());
 
 	let event_gossip_handler = Arc::new(EventGossipHandler::new(
 		client.clone(),
-		event_proofs,
+		event_proofs.clone(),
+		tx_pool.clone(),
+		block_state.clone(),
+		event_updates.clone(),
+		conflict_votes.clone(),
+		peer_versions.clone(),
+	));
+
+	let event_status = Arc::new(EventStatusProvider::new(
+		client.clone(),
+		event_proofs.clone(),
 		tx_pool,
 		block_state.clone(),
 	));
 
-	let event_witnesser = Arc::new(EventWitnesser::new(
+	let mut event_witnesser = EventWitnesser::new(
 		client.clone(),
 		streams_gossip.clone(),
 		keystore,
 		block_state.clone(),
-	));
-	let event_validator = Arc::new(EventValidator::new(client));
+		conflict_votes,
+		peer_versions.clone(),
+	)
+	.with_max_batch_size(vs_network_configuration.gossip_batch_size);
+	if vs_network_configuration.gossip_batch_window > 0 {
+		let (batcher, batcher_service) = WitnessBatcher::create(
+			streams_gossip.clone(),
+			peer_versions,
+			Duration::from_millis(vs_network_configuration.gossip_batch_window),
+			vs_network_configuration.gossip_batch_size.max(1),
+		);
+		spawn_handle.spawn("Validated Streams witness batcher", None, batcher_service.run());
+		event_witnesser = event_witnesser.with_batcher(batcher);
+	}
+	let event_witnesser = Arc::new(event_witnesser);
+	let event_validator =
+		Arc::new(EventValidator::new(client.clone(), event_proofs.clone(), block_state.clone()));
+
+	// Requests for missing proofs and the handshake are only supported by the libp2p gossip
+	if vs_network_configuration.gossip_transport == GossipTransport::Libp2p {
+		spawn_handle.spawn(
+			"Validated Streams gossip authentication",
+			None,
+			watch_authority_changes::<Block, Client, AuthorityId>(
+				client.clone(),
+				block_state.clone(),
+				streams_gossip.clone(),
+			),
+		);
+		spawn_handle.spawn(
+			"Validated Streams catch-up",
+			None,
+			run_catch_up::<Block, Client, EventProofs, AuthorityId>(
+				client.clone(),
+				event_proofs,
+				block_state.clone(),
+				streams_gossip.clone(),
+				missing_proofs,
+			),
+		);
+	}
+
+	spawn_handle.spawn(
+		"Validated Streams gossip heartbeat",
+		None,
+		send_heartbeats(streams_gossip.clone()),
+	);
+
+	spawn_handle.spawn(
+		"Validated Streams event watcher",
+		None,
+		watch_chain_events(client, event_updates.clone()),
+	);
+
+	let grpc_tls_config =
+		match (&vs_network_configuration.grpc_tls_cert, &vs_network_configuration.grpc_tls_key) {
+			(Some(cert), Some(key)) => Some(
+				server::auth::load_tls_config(
+					cert,
+					key,
+					vs_network_configuration.grpc_tls_client_ca.as_deref(),
+				)
+				.map_err(|e| ServiceError::Other(e.to_string()))?,
+			),
+			_ => None,
+		};
+	let grpc_auth_tokens = vs_network_configuration
+		.grpc_auth_tokens
+		.as_deref()
+		.map(AuthTokens::load)
+		.transpose()
+		.map_err(|e| ServiceError::Other(e.to_string()))?;
+
+	let services = ValidatedStreamsServices {
+		event_witnesser: event_witnesser.clone(),
+		event_validator: event_validator.clone(),
+		event_status: event_status.clone(),
+	};
 
 	spawn_handle.spawn_blocking("Validated Streams gRPC server", None, async move {
-		server::run(event_witnesser, event_validator, vs_network_configuration.grpc_addr)
-			.await
-			.unwrap()
+		server::run(
+			event_witnesser,
+			event_validator,
+			event_status,
+			Arc::new(event_updates),
+			event_payloads,
+			vs_network_configuration.max_event_payload_size,
+			vs_network_configuration.grpc_addr,
+			vs_network_configuration.grpc_socket_mode,
+			grpc_tls_config,
+			grpc_auth_tokens,
+		)
+		.await
+		.unwrap()
 	});
 
+	if vs_network_configuration.gossip_transport == GossipTransport::Substrate {
+		spawn_handle.spawn_blocking(
+			"Validated Streams gossip",
+			None,
+			streams_gossip_service.run_on_network(
+				network,
+				sync_service,
+				gossip_protocol_name,
+				event_gossip_handler,
+			),
+		);
+		return Ok(services)
+	}
+
 	let gossip_listen_addresses = network_configuration
 		.listen_addresses
 		.iter()
@@ -116,15 +325,9 @@ where
 			.boot_nodes
 			.iter()
 			.map(|addr| {
-				let mut addr =
-					vs_network_configuration.gossip_port.adjust_multiaddr(addr.multiaddr.clone());
-				// Remove the final /p2p/.. part as we are using different keys for gossip
-				match addr.pop() {
-					Some(libp2p::core::multiaddr::Protocol::P2p(_)) => {},
-					Some(x) => addr.push(x),
-					None => {},
-				}
-				addr
+				// Peer IDs only carry over when the gossip uses the same key as the network
+				let addr = if shares_node_key { addr.concat() } else { addr.multiaddr.clone() };
+				vs_network_configuration.gossip_port.adjust_multiaddr(addr)
 			})
 			.collect()
 	};
@@ -144,5 +347,5 @@ where
 		streams_gossip_service.run(event_gossip_handler).await;
 	});
 
-	Ok(())
+	Ok(services)
 }
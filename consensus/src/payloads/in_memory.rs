@@ -0,0 +1,39 @@
+//! Validated streams event payload storage
+
+use super::{payload_event_id, EventPayloadsTrait};
+use crate::errors::Error;
+
+use sp_core::H256;
+use std::{collections::HashMap, sync::Mutex};
+
+/// An in-memory store of event payloads.
+pub struct InMemoryEventPayloads {
+	payloads: Mutex<HashMap<H256, Vec<u8>>>,
+}
+impl InMemoryEventPayloads {
+	/// Create an empty [InMemoryEventPayloads] instance.
+	pub fn new() -> InMemoryEventPayloads {
+		InMemoryEventPayloads { payloads: Mutex::new(HashMap::new()) }
+	}
+}
+impl Default for InMemoryEventPayloads {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl EventPayloadsTrait for InMemoryEventPayloads {
+	fn add_event_payload(&self, payload: &[u8]) -> Result<H256, Error> {
+		let event_id = payload_event_id(payload);
+		self.payloads
+			.lock()
+			.or(Err(Error::LockFail("InMemoryPayloads".to_string())))?
+			.insert(event_id, payload.to_vec());
+		Ok(event_id)
+	}
+
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error> {
+		let payloads =
+			self.payloads.lock().or(Err(Error::LockFail("InMemoryPayloads".to_string())))?;
+		Ok(payloads.get(event_id).cloned())
+	}
+}
@@ -0,0 +1,27 @@
+//! Validated streams event payloads and their content-addressed storage
+
+use crate::errors::Error;
+use sp_core::{hashing::blake2_256, H256};
+
+#[cfg(test)]
+pub mod tests;
+
+pub mod in_memory;
+pub use in_memory::InMemoryEventPayloads;
+
+pub mod offchain;
+pub use offchain::OffchainStorageEventPayloads;
+
+/// Computes the event id of a raw event payload: its BLAKE2b-256 hash.
+pub fn payload_event_id(payload: &[u8]) -> H256 {
+	H256::from(blake2_256(payload))
+}
+
+/// Content-addressed storage for raw event payloads, keyed by [payload_event_id].
+pub trait EventPayloadsTrait {
+	/// Stores the provided payload, returning its event id.
+	fn add_event_payload(&self, payload: &[u8]) -> Result<H256, Error>;
+
+	/// Returns the payload with the given event id, if it is stored.
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error>;
+}
@@ -0,0 +1,34 @@
+//! Validated streams event payload storage
+
+use super::{payload_event_id, EventPayloadsTrait};
+use crate::errors::Error;
+
+use sp_core::{offchain::OffchainStorage, H256};
+
+///  A persistent content store for event payloads in [OffchainStorage]
+pub struct OffchainStorageEventPayloads<Storage: OffchainStorage> {
+	storage: Storage,
+}
+
+impl<Storage: OffchainStorage> OffchainStorageEventPayloads<Storage> {
+	/// The prefix under which data is persisted in the OffchainStorage
+	pub const OFFCHAIN_PREFIX: &[u8] = b"EventPayloads";
+
+	/// Returns a OffchainStorageEventPayloads instance that persists data in the provided
+	/// [OffchainStorage]
+	pub fn new(storage: Storage) -> Self {
+		Self { storage }
+	}
+}
+
+impl<Storage: OffchainStorage> EventPayloadsTrait for OffchainStorageEventPayloads<Storage> {
+	fn add_event_payload(&self, payload: &[u8]) -> Result<H256, Error> {
+		let event_id = payload_event_id(payload);
+		self.storage.clone().set(Self::OFFCHAIN_PREFIX, event_id.as_ref(), payload);
+		Ok(event_id)
+	}
+
+	fn get_event_payload(&self, event_id: &H256) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self.storage.get(Self::OFFCHAIN_PREFIX, event_id.as_ref()))
+	}
+}
@@ -0,0 +1,35 @@
+use super::{
+	payload_event_id, EventPayloadsTrait, InMemoryEventPayloads, OffchainStorageEventPayloads,
+};
+use rstest::rstest;
+use sp_core::{hashing::blake2_256, H256};
+use sp_runtime::offchain::testing::TestPersistentOffchainDB;
+
+fn in_memory_payloads() -> impl EventPayloadsTrait {
+	InMemoryEventPayloads::new()
+}
+
+fn offchain_payloads() -> impl EventPayloadsTrait {
+	OffchainStorageEventPayloads::new(TestPersistentOffchainDB::new())
+}
+
+#[rstest]
+fn test_payload_event_id() {
+	assert_eq!(payload_event_id(b"hello"), H256::from(blake2_256(b"hello")));
+	assert_ne!(payload_event_id(b"hello"), payload_event_id(b"hello!"));
+}
+
+#[rstest]
+#[case(in_memory_payloads())]
+#[case(offchain_payloads())]
+fn test_add_get_event_payload(#[case] payloads: impl EventPayloadsTrait) {
+	let payload = b"some event contents".to_vec();
+	let event_id = payload_event_id(&payload);
+
+	assert_eq!(payloads.get_event_payload(&event_id), Ok(None));
+	assert_eq!(payloads.add_event_payload(&payload), Ok(event_id));
+	// adding again is a no-op
+	assert_eq!(payloads.add_event_payload(&payload), Ok(event_id));
+	assert_eq!(payloads.get_event_payload(&event_id), Ok(Some(payload)));
+	assert_eq!(payloads.get_event_payload(&H256::repeat_byte(1)), Ok(None));
+}
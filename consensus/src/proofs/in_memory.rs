@@ -1,6 +1,6 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
+use super::{ensure_same_conflict_set, EventProofsTrait, WitnessProof};
 use crate::errors::Error;
 
 use sp_core::H256;
@@ -12,7 +12,7 @@ use std::{
 
 /// An in-memory store of event proofs.
 pub struct InMemoryEventProofs {
-	proofs: Mutex<HashMap<H256, HashMap<CryptoTypePublicPair, Vec<u8>>>>,
+	proofs: Mutex<HashMap<(H256, H256), HashMap<CryptoTypePublicPair, WitnessProof>>>,
 }
 impl InMemoryEventProofs {
 	/// Create an empty [InMemoryEventProofs] instances.
@@ -26,15 +26,18 @@ impl Default for InMemoryEventProofs {
 	}
 }
 impl EventProofsTrait for InMemoryEventProofs {
-	fn add_event_proof(&self, witnessed_event: &WitnessedEvent) -> Result<(), Error> {
-		let event_id = witnessed_event.event_id;
+	fn add_event_proof(&self, proof: &WitnessProof) -> Result<(), Error> {
+		let event_id = proof.event_id;
 		let mut proofs =
 			self.proofs.lock().or(Err(Error::LockFail("InMemoryProofs".to_string())))?;
 
-		let event_witnesses = proofs.entry(event_id).or_default();
-		match event_witnesses.entry(witnessed_event.pub_key.clone()) {
+		let event_witnesses = proofs.entry((proof.stream_id, event_id)).or_default();
+		if let Some(stored) = event_witnesses.values().next() {
+			ensure_same_conflict_set(stored, proof)?;
+		}
+		match event_witnesses.entry(CryptoTypePublicPair::from(proof.signer)) {
 			Entry::Vacant(e) => {
-				e.insert(witnessed_event.signature.clone());
+				e.insert(proof.clone());
 				Ok(())
 			},
 			witness_entry => {
@@ -50,12 +53,13 @@ impl EventProofsTrait for InMemoryEventProofs {
 
 	fn get_event_proofs(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
+	) -> Result<HashMap<CryptoTypePublicPair, WitnessProof>, Error> {
 		let proofs = self.proofs.lock().or(Err(Error::LockFail("InMemoryProofs".to_string())))?;
 		Ok(proofs
-			.get(event_id)
+			.get(&(*stream_id, *event_id))
 			.map(|event_proofs| {
 				let mut event_proofs = event_proofs.clone();
 				event_proofs.retain(|k, _| validators.contains(k));
@@ -66,12 +70,13 @@ impl EventProofsTrait for InMemoryEventProofs {
 
 	fn purge_event_stale_signatures(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<(), Error> {
 		let mut proofs =
 			self.proofs.lock().or(Err(Error::LockFail("InMemoryProofs".to_string())))?;
-		if let Some(event_proofs) = proofs.get_mut(event_id) {
+		if let Some(event_proofs) = proofs.get_mut(&(*stream_id, *event_id)) {
 			event_proofs.retain(|k, _| validators.contains(k));
 		}
 		Ok(())
@@ -1,11 +1,17 @@
 //! Validated streams event proof types and storage
 
 use crate::errors::Error;
+use codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
-use sp_core::H256;
+use sp_core::{
+	sr25519::{Public, Signature},
+	ByteArray, H256,
+};
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::collections::HashMap;
 
+pub use pallet_validated_streams::WitnessProof;
+
 #[cfg(test)]
 pub mod tests;
 
@@ -20,47 +26,120 @@ pub mod rocksdb;
 #[cfg(feature = "rocksdb")]
 pub use self::rocksdb::RocksDbEventProofs;
 
-/// Proof of event that has been witnessed; an event id and a signature
-/// Signatures do not have a defined cryptosystem, but are assumed to be sr25519 signatures by
-/// [super::services::events].
+/// Proof of event that has been witnessed; a stream id, an event id and a signature
+/// Signatures do not have a defined cryptosystem, but are assumed to be sr25519 signatures (of
+/// [pallet_validated_streams::witnessed_event_message]) by [super::services::events].
+/// On the wire, it is encoded as the [WitnessProof] shared with the pallet; see
+/// [WitnessedEvent::encode_proofs].
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WitnessedEvent {
 	/// The signature of the event
 	pub signature: Vec<u8>,
 	/// The public key which was used to produce the signature
 	pub pub_key: CryptoTypePublicPair,
+	/// The id of the stream (namespace) the event belongs to
+	pub stream_id: H256,
 	/// The id/hash of the event
 	pub event_id: H256,
+	/// The conflict set the event was witnessed as a member of, if any
+	pub conflict_set: Option<H256>,
+}
+
+impl WitnessedEvent {
+	/// Converts the event to the [WitnessProof] shared with the pallet. Fails if the key or the
+	/// signature are not sr25519 ones.
+	pub fn to_proof(&self) -> Result<WitnessProof, Error> {
+		if self.pub_key.0 != sp_core::sr25519::CRYPTO_ID {
+			return Err(Error::SerilizationFailure("Proof key is not an sr25519 key".to_string()))
+		}
+		let signer = Public::from_slice(self.pub_key.1.as_slice()).map_err(|_| {
+			Error::SerilizationFailure("Proof key is not an sr25519 key".to_string())
+		})?;
+		let signature = Signature::from_slice(self.signature.as_slice()).ok_or_else(|| {
+			Error::SerilizationFailure("Proof signature is not an sr25519 signature".to_string())
+		})?;
+		Ok(WitnessProof {
+			stream_id: self.stream_id,
+			event_id: self.event_id,
+			conflict_set: self.conflict_set,
+			signer,
+			signature,
+		})
+	}
+
+	/// SCALE-encodes the events as a list of [WitnessProof]-s.
+	pub fn encode_proofs(events: &[WitnessedEvent]) -> Result<Vec<u8>, Error> {
+		Ok(events
+			.iter()
+			.map(|event| event.to_proof())
+			.collect::<Result<Vec<_>, _>>()?
+			.encode())
+	}
+
+	/// Decodes a list of [WitnessProof]-s encoded with [WitnessedEvent::encode_proofs].
+	pub fn decode_proofs(bytes: &[u8]) -> Result<Vec<WitnessedEvent>, Error> {
+		let proofs = Vec::<WitnessProof>::decode(&mut &*bytes)?;
+		Ok(proofs.into_iter().map(WitnessedEvent::from).collect())
+	}
+}
+
+impl From<WitnessProof> for WitnessedEvent {
+	fn from(proof: WitnessProof) -> Self {
+		Self {
+			signature: proof.signature.as_ref().to_vec(),
+			pub_key: CryptoTypePublicPair::from(proof.signer),
+			stream_id: proof.stream_id,
+			event_id: proof.event_id,
+			conflict_set: proof.conflict_set,
+		}
+	}
 }
 
 /// Storage for event proofs (for [WitnessedEvent]-s)
 pub trait EventProofsTrait {
-	/// Stores the provided event proof.
-	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error>;
+	/// Stores the provided event proof, along with the conflict set it was signed under. Fails
+	/// with [Error::ConflictingVote] if proofs of the same event were already stored under a
+	/// different conflict set, as those could not be submitted together.
+	fn add_event_proof(&self, proof: &WitnessProof) -> Result<(), Error>;
 
-	/// Returns a [HashMap] containing the public keys and their corresponding signatures for the
-	/// given event id and list of validators
+	/// Returns a [HashMap] containing the public keys and their corresponding proofs for the given
+	/// event id in the given stream and list of validators
 	fn get_event_proofs(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error>;
+	) -> Result<HashMap<CryptoTypePublicPair, WitnessProof>, Error>;
 
 	/// Retrieve count of proof for the given event id. Equivalent to
-	/// `self.get_event_proofs(event_id, validators)?.len()`, but possibly more optimal.
+	/// `self.get_event_proofs(stream_id, event_id, validators)?.len()`, but possibly more optimal.
 	fn get_event_proof_count(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<u16, Error> {
-		Ok(self.get_event_proofs(event_id, validators)?.len() as u16)
+		Ok(self.get_event_proofs(stream_id, event_id, validators)?.len() as u16)
 	}
 
 	/// Remove proofs of the given event observed by validators not in the list of validators passed
 	/// in. Useful for maintaining the pool of event proofs whenever the validator set changes.
 	fn purge_event_stale_signatures(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<(), Error>;
 }
+
+/// Checks that a new proof of an event was signed under the same conflict set as an already
+/// stored one.
+fn ensure_same_conflict_set(stored: &WitnessProof, proof: &WitnessProof) -> Result<(), Error> {
+	if stored.conflict_set != proof.conflict_set {
+		return Err(Error::ConflictingVote(format!(
+			"event {} was already witnessed in conflict set {:?}, not {:?}",
+			proof.event_id, stored.conflict_set, proof.conflict_set
+		)))
+	}
+	Ok(())
+}
@@ -1,8 +1,9 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
+use super::{ensure_same_conflict_set, EventProofsTrait, WitnessProof};
 use crate::errors::Error;
 
+use codec::{Decode, Encode};
 use sp_core::{offchain::OffchainStorage, H256};
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::collections::HashMap;
@@ -13,8 +14,26 @@ pub struct OffchainStorageEventProofs<Storage: OffchainStorage> {
 }
 
 impl<Storage: OffchainStorage> OffchainStorageEventProofs<Storage> {
-	/// The prefix under which data is persisted in the OffchainStorage
-	pub const OFFCHAIN_PREFIX: &[u8] = b"EventProofs";
+	/// The prefix under which data is persisted in the OffchainStorage. Proofs stored by earlier
+	/// versions, with bincode-encoded keys, are left under `b"EventProofs"`; the ones still needed
+	/// are requested from peers again.
+	pub const OFFCHAIN_PREFIX: &[u8] = b"EventProofsV2";
+
+	// key value format:
+	// <stream id> <event id> -> <SCALE list of public keys>
+	// <stream id> <event id> <public key (SCALE-encoded CryptoTypePublicPair)> -> <SCALE
+	// WitnessProof>
+	fn event_key(stream_id: &H256, event_id: &H256) -> Vec<u8> {
+		[stream_id.as_ref(), event_id.as_ref()].concat()
+	}
+
+	fn proof_key(
+		stream_id: &H256,
+		event_id: &H256,
+		pub_key: &CryptoTypePublicPair,
+	) -> Result<Vec<u8>, Error> {
+		Ok([stream_id.as_ref(), event_id.as_ref(), &pub_key.encode()].concat())
+	}
 
 	/// Returns a OffchainStorageEventProofs instance that persists data in the provided
 	/// [OffchainStorage]
@@ -24,25 +43,43 @@ impl<Storage: OffchainStorage> OffchainStorageEventProofs<Storage> {
 }
 
 impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<Storage> {
-	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error> {
+	fn add_event_proof(&self, proof: &WitnessProof) -> Result<(), Error> {
+		let event_key = Self::event_key(&proof.stream_id, &proof.event_id);
+		let first_signer = self
+			.storage
+			.get(Self::OFFCHAIN_PREFIX, &event_key)
+			.map(|b| Vec::<CryptoTypePublicPair>::decode(&mut b.as_slice()))
+			.transpose()?
+			.and_then(|signers| signers.into_iter().next());
+		if let Some(signer) = first_signer {
+			let stored = self.storage.get(
+				Self::OFFCHAIN_PREFIX,
+				&Self::proof_key(&proof.stream_id, &proof.event_id, &signer)?,
+			);
+			if let Some(stored) = stored {
+				ensure_same_conflict_set(&WitnessProof::decode(&mut stored.as_slice())?, proof)?;
+			}
+		}
+
+		let pub_key = CryptoTypePublicPair::from(proof.signer);
 		self.storage.clone().set(
 			Self::OFFCHAIN_PREFIX,
-			&[event.event_id.as_ref(), &bincode::serialize(&event.pub_key)?].concat(),
-			&event.signature,
+			&Self::proof_key(&proof.stream_id, &proof.event_id, &pub_key)?,
+			&proof.encode(),
 		);
 
 		loop {
-			let existing_bytes = self.storage.get(Self::OFFCHAIN_PREFIX, event.event_id.as_ref());
+			let existing_bytes = self.storage.get(Self::OFFCHAIN_PREFIX, &event_key);
 			let mut signers_list = existing_bytes
 				.as_ref()
-				.map(|b| bincode::deserialize::<Vec<CryptoTypePublicPair>>(b.as_ref()))
+				.map(|b| Vec::<CryptoTypePublicPair>::decode(&mut b.as_slice()))
 				.unwrap_or_else(|| Ok(vec![]))?;
-			signers_list.push(event.pub_key.clone());
+			signers_list.push(pub_key.clone());
 			if self.storage.clone().compare_and_set(
 				Self::OFFCHAIN_PREFIX,
-				event.event_id.as_ref(),
+				&event_key,
 				existing_bytes.as_ref().map(|x| x.as_ref()),
-				&bincode::serialize(&signers_list)?,
+				&signers_list.encode(),
 			) {
 				break
 			}
@@ -52,24 +89,30 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 
 	fn get_event_proofs(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
-		Ok(validators
+	) -> Result<HashMap<CryptoTypePublicPair, WitnessProof>, Error> {
+		validators
 			.iter()
 			.flat_map(|pub_key| {
 				self.storage
 					.get(
 						Self::OFFCHAIN_PREFIX,
-						&[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat(),
+						&Self::proof_key(stream_id, event_id, pub_key).unwrap(),
 					)
-					.map(|signature| (pub_key.clone(), signature))
+					.map(|bytes| {
+						WitnessProof::decode(&mut bytes.as_slice())
+							.map(|proof| (pub_key.clone(), proof))
+							.map_err(Error::from)
+					})
 			})
-			.collect())
+			.collect()
 	}
 
 	fn get_event_proof_count(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<u16, Error> {
@@ -78,7 +121,7 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 			.map(|pub_key| {
 				self.storage.get(
 					Self::OFFCHAIN_PREFIX,
-					&[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat(),
+					&Self::proof_key(stream_id, event_id, pub_key).unwrap(),
 				)
 			})
 			.filter(|r| matches!(r, Some(_)))
@@ -87,21 +130,23 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 
 	fn purge_event_stale_signatures(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<(), Error> {
+		let event_key = Self::event_key(stream_id, event_id);
 		loop {
-			let existing_bytes = self.storage.get(Self::OFFCHAIN_PREFIX, event_id.as_ref());
+			let existing_bytes = self.storage.get(Self::OFFCHAIN_PREFIX, &event_key);
 			let mut signers_list = existing_bytes
 				.as_ref()
-				.map(|b| bincode::deserialize::<Vec<CryptoTypePublicPair>>(b.as_ref()))
+				.map(|b| Vec::<CryptoTypePublicPair>::decode(&mut b.as_slice()))
 				.unwrap_or_else(|| Ok(vec![]))?;
 
 			signers_list.retain(|pub_key| {
 				if !validators.contains(pub_key) {
 					self.storage.clone().remove(
 						Self::OFFCHAIN_PREFIX,
-						&[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat(),
+						&Self::proof_key(stream_id, event_id, pub_key).unwrap(),
 					);
 					false
 				} else {
@@ -111,9 +156,9 @@ impl<Storage: OffchainStorage> EventProofsTrait for OffchainStorageEventProofs<S
 
 			if self.storage.clone().compare_and_set(
 				Self::OFFCHAIN_PREFIX,
-				event_id.as_ref(),
+				&event_key,
 				existing_bytes.as_ref().map(|x| x.as_ref()),
-				&bincode::serialize(&signers_list)?,
+				&signers_list.encode(),
 			) {
 				break
 			}
@@ -1,8 +1,9 @@
 //! Validated streams event proof types and storage
 
-use super::{EventProofsTrait, WitnessedEvent};
+use super::{ensure_same_conflict_set, EventProofsTrait, WitnessProof};
 use crate::errors::Error;
 
+use codec::{Decode, DecodeAll, Encode};
 use sp_core::H256;
 use sp_runtime::app_crypto::CryptoTypePublicPair;
 use std::collections::HashMap;
@@ -10,14 +11,52 @@ use std::collections::HashMap;
 /// A persistent database for storing event proofs.
 pub struct RocksDbEventProofs {
 	// key value format:
-	// <event id (32 bytes)> <public key (serialized CryptoTypePublicPair)> -> <signature bytes>
+	// <stream id (32 bytes)> <event id (32 bytes)> <public key (SCALE-encoded
+	// CryptoTypePublicPair)>   -> <SCALE WitnessProof>
 	db: rocksdb::DB,
 }
 
+/// The key under which the version of the key value format is stored; it is shorter than the key
+/// of any proof.
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+/// The current version of the key value format. Databases without a version were written by
+/// earlier releases, whose keys had no stream id or bincode-encoded public keys, and whose values
+/// were bare signatures.
+const FORMAT_VERSION: u8 = 1;
+
 impl RocksDbEventProofs {
 	/// Returns a RocksDbEventProofs instance which persists data in the provided path
 	pub fn create(path: &str) -> Self {
-		Self { db: rocksdb::DB::open_default(path).expect("open") }
+		let proofs = Self { db: rocksdb::DB::open_default(path).expect("open") };
+		proofs.purge_legacy_entries().expect("purge legacy proofs");
+		proofs
+	}
+
+	/// Deletes the entries written by earlier releases, unless the database is already in the
+	/// current format. They cannot be converted, as they lack the conflict set of the events, so
+	/// the proofs are left to be gossiped again.
+	fn purge_legacy_entries(&self) -> Result<(), Error> {
+		if self.db.get(FORMAT_VERSION_KEY)?.is_some() {
+			return Ok(())
+		}
+		let mut purged = 0;
+		for r in self.db.iterator(rocksdb::IteratorMode::Start) {
+			let (key, stored) = r?;
+			let is_current = WitnessProof::decode_all(&mut &stored[..]).map_or(false, |proof| {
+				let pub_key = CryptoTypePublicPair::from(proof.signer);
+				*key == [proof.stream_id.as_ref(), proof.event_id.as_ref(), &pub_key.encode()]
+					.concat()
+			});
+			if !is_current {
+				self.db.delete(key)?;
+				purged += 1;
+			}
+		}
+		if purged > 0 {
+			log::info!("Purged {purged} event proofs stored by an earlier version");
+		}
+		self.db.put(FORMAT_VERSION_KEY, [FORMAT_VERSION])?;
+		Ok(())
 	}
 
 	/// Clears ALL the data stored at the given path.
@@ -28,33 +67,47 @@ impl RocksDbEventProofs {
 }
 
 impl EventProofsTrait for RocksDbEventProofs {
-	fn add_event_proof(&self, event: &WitnessedEvent) -> Result<(), Error> {
+	fn add_event_proof(&self, proof: &WitnessProof) -> Result<(), Error> {
+		let prefix = [proof.stream_id.as_ref(), proof.event_id.as_ref()].concat();
+		if let Some(r) = self.db.prefix_iterator(&prefix).next() {
+			let (key, stored) = r?;
+			if key.starts_with(&prefix) {
+				ensure_same_conflict_set(&WitnessProof::decode(&mut &stored[..])?, proof)?;
+			}
+		}
+
+		let pub_key = CryptoTypePublicPair::from(proof.signer);
 		self.db.put(
-			[event.event_id.as_ref(), &bincode::serialize(&event.pub_key)?].concat(),
-			event.signature.clone(),
+			[proof.stream_id.as_ref(), proof.event_id.as_ref(), &pub_key.encode()].concat(),
+			proof.encode(),
 		)?;
 		Ok(())
 	}
 
 	fn get_event_proofs(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
-	) -> Result<HashMap<CryptoTypePublicPair, Vec<u8>>, Error> {
+	) -> Result<HashMap<CryptoTypePublicPair, WitnessProof>, Error> {
 		// NOTE: to get all proofs, no matter who signed them:
-		// self.db.prefix_iterator(event_id).map(|r| { r.map(|(key, signature)| { let pub_key =
-		// bincode::deserialize(&key[H256::len_bytes()..]).unwrap(); (pub_key, signature.into())
-		// }).map_err(|e| e.into())}).collect()
+		// self.db.prefix_iterator([stream_id, event_id].concat()).map(|r| { r.map(|(key,
+		// proof)| { let pub_key = Decode::decode(&mut &key[2 * H256::len_bytes()..]).unwrap();
+		// (pub_key, Decode::decode(&mut &proof[..]).unwrap()) }).map_err(|e| e.into())}).collect()
 
 		let values =
 			self.db.multi_get(validators.iter().map(|pub_key| {
-				[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat()
+				[stream_id.as_ref(), event_id.as_ref(), &pub_key.encode()].concat()
 			}));
 		validators
 			.iter()
 			.zip(values)
-			.flat_map(|(pub_key, signature_r)| match signature_r {
-				Ok(Some(signature)) => Some(Ok((pub_key.clone(), signature))),
+			.flat_map(|(pub_key, proof_r)| match proof_r {
+				Ok(Some(proof)) => Some(
+					WitnessProof::decode(&mut proof.as_slice())
+						.map(|proof| (pub_key.clone(), proof))
+						.map_err(Error::from),
+				),
 				Ok(None) => None,
 				Err(e) => Some(Err(e.into())),
 			})
@@ -63,6 +116,7 @@ impl EventProofsTrait for RocksDbEventProofs {
 
 	fn get_event_proof_count(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<u16, Error> {
@@ -70,7 +124,7 @@ impl EventProofsTrait for RocksDbEventProofs {
 			.db
 			.multi_get(
 				validators.iter().map(|pub_key| {
-					[event_id.as_ref(), &bincode::serialize(pub_key).unwrap()].concat()
+					[stream_id.as_ref(), event_id.as_ref(), &pub_key.encode()].concat()
 				}),
 			)
 			.into_iter()
@@ -80,13 +134,19 @@ impl EventProofsTrait for RocksDbEventProofs {
 
 	fn purge_event_stale_signatures(
 		&self,
+		stream_id: &H256,
 		event_id: &H256,
 		validators: &[CryptoTypePublicPair],
 	) -> Result<(), Error> {
-		for r in self.db.prefix_iterator(event_id) {
-			let (key, _signature) = r?;
-			let pub_key = bincode::deserialize(&key[H256::len_bytes()..])?;
-			if !validators.contains(&pub_key) {
+		let prefix = [stream_id.as_ref(), event_id.as_ref()].concat();
+		for r in self.db.prefix_iterator(&prefix) {
+			let (key, _proof) = r?;
+			// prefix_iterator seeks to the prefix, but does not stop at its end
+			if !key.starts_with(&prefix) {
+				break
+			}
+			let pub_key = CryptoTypePublicPair::decode_all(&mut &key[prefix.len()..]);
+			if !pub_key.map_or(false, |pub_key| validators.contains(&pub_key)) {
 				self.db.delete(key)?;
 			}
 		}
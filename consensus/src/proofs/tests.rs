@@ -1,11 +1,12 @@
-use super::{
-	EventProofsTrait, InMemoryEventProofs, OffchainStorageEventProofs,
-	WitnessedEvent,
-};
 #[cfg(feature = "rocksdb")]
 use super::RocksDbEventProofs;
+use super::{EventProofsTrait, InMemoryEventProofs, OffchainStorageEventProofs, WitnessProof};
+use crate::errors::Error;
 use rstest::rstest;
-use sp_core::{sr25519::Public, H256};
+use sp_core::{
+	sr25519::{Public, Signature},
+	H256,
+};
 use sp_runtime::{app_crypto::CryptoTypePublicPair, offchain::testing::TestPersistentOffchainDB};
 use std::{
 	collections::HashMap,
@@ -36,12 +37,13 @@ fn offchain_proofs() -> impl EventProofsTrait {
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
 fn test_add_event_proof(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
 	let event_id = H256::repeat_byte(1);
-	let witnessed_event = create_witnessed_event(event_id);
+	let proof = create_proof(stream_id, event_id);
 
-	assert!(proofs.add_event_proof(&witnessed_event).is_ok());
+	assert!(proofs.add_event_proof(&proof).is_ok());
 	// add again the same event
-	assert!(proofs.add_event_proof(&witnessed_event).is_ok());
+	assert!(proofs.add_event_proof(&proof).is_ok());
 }
 
 #[rstest]
@@ -50,16 +52,17 @@ fn test_add_event_proof(#[case] proofs: impl EventProofsTrait) {
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
 fn test_get_proof_count(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
 	let event_id = H256::repeat_byte(1);
 	let validator_list = get_validator_list();
 	let new_validator_list = get_new_validator_list();
 
-	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(0));
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(0));
 
-	let witnessed_event = create_witnessed_event(event_id);
-	let _ = proofs.add_event_proof(&witnessed_event);
-	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(1));
-	assert_eq!(proofs.get_event_proof_count(&event_id, &new_validator_list), Ok(0));
+	let proof = create_proof(stream_id, event_id);
+	let _ = proofs.add_event_proof(&proof);
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(1));
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &new_validator_list), Ok(0));
 }
 
 #[rstest]
@@ -68,19 +71,82 @@ fn test_get_proof_count(#[case] proofs: impl EventProofsTrait) {
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
 fn test_get_proof_proofs(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
 	let event_id = H256::repeat_byte(1);
 	let validator_list = get_validator_list();
 	let new_validator_list = get_new_validator_list();
 
-	assert_eq!(proofs.get_event_proofs(&event_id, &validator_list), Ok(HashMap::new()));
+	assert_eq!(proofs.get_event_proofs(&stream_id, &event_id, &validator_list), Ok(HashMap::new()));
 
-	let witnessed_event = create_witnessed_event(event_id);
-	let _ = proofs.add_event_proof(&witnessed_event);
-	let proofmap = proofs.get_event_proofs(&event_id, &validator_list).unwrap();
+	let proof = create_proof(stream_id, event_id);
+	let _ = proofs.add_event_proof(&proof);
+	let proofmap = proofs.get_event_proofs(&stream_id, &event_id, &validator_list).unwrap();
 	assert_eq!(proofmap.len(), 1);
-	assert_eq!(proofmap.get(&validator_list[0]), Some(&witnessed_event.signature));
+	assert_eq!(proofmap.get(&validator_list[0]), Some(&proof));
+
+	assert_eq!(
+		proofs.get_event_proofs(&stream_id, &event_id, &new_validator_list),
+		Ok(HashMap::new())
+	);
+}
+
+#[rstest]
+#[case(in_memory_proofs())]
+#[cfg(feature = "rocksdb")]
+#[case(rocksdb_proofs())]
+#[case(offchain_proofs())]
+fn test_proofs_keep_conflict_set(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
+	let event_id = H256::repeat_byte(1);
+	let validator_list = get_validator_list();
+	let proof = WitnessProof {
+		conflict_set: Some(H256::repeat_byte(3)),
+		..create_proof(stream_id, event_id)
+	};
+
+	let _ = proofs.add_event_proof(&proof);
+	let proofmap = proofs.get_event_proofs(&stream_id, &event_id, &validator_list).unwrap();
+	assert_eq!(proofmap.get(&validator_list[0]).and_then(|p| p.conflict_set), proof.conflict_set);
+}
+
+#[rstest]
+#[case(in_memory_proofs())]
+#[cfg(feature = "rocksdb")]
+#[case(rocksdb_proofs())]
+#[case(offchain_proofs())]
+fn test_conflicting_conflict_sets_are_rejected(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
+	let event_id = H256::repeat_byte(1);
+	let validator_list = get_validator_list();
+
+	assert!(proofs.add_event_proof(&create_proof(stream_id, event_id)).is_ok());
+	let other_signer = WitnessProof {
+		conflict_set: Some(H256::repeat_byte(3)),
+		signer: Public::from_h256(H256::repeat_byte(2)),
+		..create_proof(stream_id, event_id)
+	};
+	assert!(matches!(proofs.add_event_proof(&other_signer), Err(Error::ConflictingVote(_))));
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(1));
+}
 
-	assert_eq!(proofs.get_event_proofs(&event_id, &new_validator_list), Ok(HashMap::new()));
+#[rstest]
+#[case(in_memory_proofs())]
+#[cfg(feature = "rocksdb")]
+#[case(rocksdb_proofs())]
+#[case(offchain_proofs())]
+fn test_streams_are_separate(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
+	let other_stream_id = H256::repeat_byte(8);
+	let event_id = H256::repeat_byte(1);
+	let validator_list = get_validator_list();
+
+	let _ = proofs.add_event_proof(&create_proof(stream_id, event_id));
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(1));
+	assert_eq!(proofs.get_event_proof_count(&other_stream_id, &event_id, &validator_list), Ok(0));
+	assert_eq!(
+		proofs.get_event_proofs(&other_stream_id, &event_id, &validator_list),
+		Ok(HashMap::new())
+	);
 }
 
 #[rstest]
@@ -89,18 +155,58 @@ fn test_get_proof_proofs(#[case] proofs: impl EventProofsTrait) {
 #[case(rocksdb_proofs())]
 #[case(offchain_proofs())]
 fn test_remove_stale_events(#[case] proofs: impl EventProofsTrait) {
+	let stream_id = H256::repeat_byte(7);
 	let event_id = H256::repeat_byte(1);
-	let witnessed_event = create_witnessed_event(event_id);
+	let proof = create_proof(stream_id, event_id);
 	let validator_list = get_validator_list();
 	let new_validator_list = get_new_validator_list();
 
-	let _ = proofs.add_event_proof(&witnessed_event);
+	let _ = proofs.add_event_proof(&proof);
+
+	assert!(proofs
+		.purge_event_stale_signatures(&stream_id, &event_id, &validator_list)
+		.is_ok());
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(1));
+
+	assert!(proofs
+		.purge_event_stale_signatures(&stream_id, &event_id, &new_validator_list)
+		.is_ok());
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(0));
+}
 
-	assert!(proofs.purge_event_stale_signatures(&event_id, &validator_list).is_ok());
-	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(1));
+/// test that the proofs stored by earlier versions, which cannot be decoded, are purged once
+#[cfg(feature = "rocksdb")]
+#[rstest]
+fn test_rocksdb_legacy_proofs_are_purged() {
+	use codec::Encode;
+	let path =
+		format!("/tmp/testvstreamsrocksdb{}", ROCKSDB_INSTANCE.fetch_add(1, Ordering::SeqCst));
+	let _ = RocksDbEventProofs::destroy(&path);
+	let stream_id = H256::repeat_byte(7);
+	let event_id = H256::repeat_byte(1);
+	let validator_list = get_validator_list();
+	{
+		let db = rocksdb::DB::open_default(&path).unwrap();
+		// Keys without a stream id, and keys with bincode-encoded public keys, storing signatures
+		db.put([event_id.as_ref(), &[1u8; 40]].concat(), [0; 64]).unwrap();
+		db.put([stream_id.as_ref(), event_id.as_ref(), &[1u8; 40]].concat(), [0; 64])
+			.unwrap();
+		db.put(
+			[stream_id.as_ref(), event_id.as_ref(), &validator_list[0].encode()].concat(),
+			[0; 64],
+		)
+		.unwrap();
+	}
 
-	assert!(proofs.purge_event_stale_signatures(&event_id, &new_validator_list).is_ok());
-	assert_eq!(proofs.get_event_proof_count(&event_id, &validator_list), Ok(0));
+	let proofs = RocksDbEventProofs::create(&path);
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(0));
+	assert_eq!(proofs.add_event_proof(&create_proof(stream_id, event_id)), Ok(()));
+	drop(proofs);
+
+	let proofs = RocksDbEventProofs::create(&path);
+	assert_eq!(proofs.get_event_proof_count(&stream_id, &event_id, &validator_list), Ok(1));
+	drop(proofs);
+	let _ = RocksDbEventProofs::destroy(&path);
 }
 
 fn get_validator_list() -> [CryptoTypePublicPair; 1] {
@@ -109,10 +215,12 @@ fn get_validator_list() -> [CryptoTypePublicPair; 1] {
 fn get_new_validator_list() -> [CryptoTypePublicPair; 1] {
 	[CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(2)))]
 }
-fn create_witnessed_event(event_id: H256) -> WitnessedEvent {
-	WitnessedEvent {
+fn create_proof(stream_id: H256, event_id: H256) -> WitnessProof {
+	WitnessProof {
+		stream_id,
 		event_id,
-		pub_key: CryptoTypePublicPair::from(Public::from_h256(H256::repeat_byte(1))),
-		signature: vec![],
+		conflict_set: None,
+		signer: Public::from_h256(H256::repeat_byte(1)),
+		signature: Signature::from_raw([0; 64]),
 	}
 }
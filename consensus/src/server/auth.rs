@@ -0,0 +1,172 @@
+//! Access control for the GRPC server: TLS with optional client certificate verification, and
+//! bearer tokens identifying the trusted clients.
+
+use crate::errors::Error;
+use sp_core::hashing::blake2_256;
+use std::{collections::HashMap, fmt, fs, path::Path, sync::Arc};
+use tonic::{
+	service::Interceptor,
+	transport::{server::UdsConnectInfo, Certificate, Identity, ServerTlsConfig},
+	Request, Status,
+};
+
+/// The identity of the client which made a GRPC call, as established by [ClientAuthenticator].
+/// Stored in the extensions of every request that passes through the authenticator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientIdentity {
+	/// The name associated with the bearer token the client presented, if any.
+	pub token_name: Option<String>,
+	/// A fingerprint of the TLS certificate the client presented, if any.
+	pub certificate: Option<String>,
+	/// The address the client connected from, if known.
+	pub remote_addr: Option<String>,
+	/// The user id of the local process which connected over a Unix domain socket, if any.
+	pub peer_uid: Option<u32>,
+}
+
+impl ClientIdentity {
+	/// Reads the identity of the client which made a request.
+	pub fn of<T>(request: &Request<T>) -> Option<&ClientIdentity> {
+		request.extensions().get::<ClientIdentity>()
+	}
+}
+
+impl fmt::Display for ClientIdentity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut parts = vec![];
+		if let Some(token_name) = &self.token_name {
+			parts.push(format!("token {token_name}"));
+		}
+		if let Some(certificate) = &self.certificate {
+			parts.push(format!("certificate {certificate}"));
+		}
+		if let Some(remote_addr) = &self.remote_addr {
+			parts.push(format!("address {remote_addr}"));
+		}
+		if let Some(peer_uid) = &self.peer_uid {
+			parts.push(format!("uid {peer_uid}"));
+		}
+		if parts.is_empty() {
+			write!(f, "unknown client")
+		} else {
+			write!(f, "{}", parts.join(", "))
+		}
+	}
+}
+
+/// A list of bearer tokens accepted by the GRPC server, each with a name identifying the client
+/// it was given to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuthTokens {
+	// token -> name
+	tokens: HashMap<String, String>,
+}
+
+impl AuthTokens {
+	/// Reads a list of tokens from a file. See [AuthTokens::parse] for the format.
+	pub fn load(path: &Path) -> Result<Self, Error> {
+		let contents = fs::read_to_string(path).map_err(|e| {
+			Error::Other(format!("Failed reading GRPC tokens file {}: {e}", path.display()))
+		})?;
+		Self::parse(&contents)
+	}
+
+	/// Parses a list of tokens, one `<client name>:<token>` pair per line. Empty lines and lines
+	/// starting with `#` are ignored.
+	pub fn parse(contents: &str) -> Result<Self, Error> {
+		let mut tokens = HashMap::new();
+		for (line_number, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue
+			}
+			match line.split_once(':') {
+				Some((name, token)) if !name.trim().is_empty() && !token.trim().is_empty() => {
+					tokens.insert(token.trim().to_string(), name.trim().to_string());
+				},
+				_ =>
+					return Err(Error::Other(format!(
+						"Malformed GRPC token on line {} (expected <client name>:<token>)",
+						line_number + 1
+					))),
+			}
+		}
+		Ok(Self { tokens })
+	}
+
+	/// Returns the name of the client holding the given token, if the token is valid.
+	pub fn client_name(&self, token: &str) -> Option<&String> {
+		self.tokens.get(token)
+	}
+}
+
+/// Builds the TLS configuration of the GRPC server from PEM files. When `client_ca` is given,
+/// clients are required to present a certificate signed by it.
+pub fn load_tls_config(
+	cert: &Path,
+	key: &Path,
+	client_ca: Option<&Path>,
+) -> Result<ServerTlsConfig, Error> {
+	let read = |path: &Path| {
+		fs::read(path).map_err(|e| Error::Other(format!("Failed reading {}: {e}", path.display())))
+	};
+	let mut tls_config =
+		ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+	if let Some(client_ca) = client_ca {
+		tls_config = tls_config.client_ca_root(Certificate::from_pem(read(client_ca)?));
+	}
+	Ok(tls_config)
+}
+
+/// An [Interceptor] which checks the bearer token of every call (if tokens are configured) and
+/// attaches a [ClientIdentity] to the request.
+#[derive(Clone, Default)]
+pub struct ClientAuthenticator {
+	tokens: Option<Arc<AuthTokens>>,
+}
+
+impl ClientAuthenticator {
+	/// Creates a new [ClientAuthenticator]. If `tokens` is [None], all calls are allowed through.
+	pub fn new(tokens: Option<AuthTokens>) -> Self {
+		Self { tokens: tokens.map(Arc::new) }
+	}
+}
+
+impl Interceptor for ClientAuthenticator {
+	fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+		let token_name = match &self.tokens {
+			Some(tokens) => {
+				let token = request
+					.metadata()
+					.get("authorization")
+					.and_then(|value| value.to_str().ok())
+					.and_then(|value| value.strip_prefix("Bearer "))
+					.ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+				let name = tokens
+					.client_name(token.trim())
+					.ok_or_else(|| Status::unauthenticated("invalid bearer token"))?;
+				Some(name.clone())
+			},
+			None => None,
+		};
+
+		// Verification of the certificate itself is done by the TLS layer
+		let certificate = request.peer_certs().and_then(|certs| {
+			certs.first().map(|cert| hex::encode(&blake2_256(cert.get_ref())[..8]))
+		});
+
+		let identity = ClientIdentity {
+			token_name,
+			certificate,
+			remote_addr: request.remote_addr().map(|addr| addr.to_string()),
+			peer_uid: request
+				.extensions()
+				.get::<UdsConnectInfo>()
+				.and_then(|info| info.peer_cred)
+				.map(|cred| cred.uid()),
+		};
+		request.extensions_mut().insert(identity);
+
+		Ok(request)
+	}
+}
@@ -0,0 +1,45 @@
+//! Opaque cursors for resuming [super::validated_streams_proto::ValidatedEventsRequest] streams.
+
+use sp_core::H256;
+
+/// A position in the stream of validated events: the last block that was streamed, together with
+/// enough information to check that a resumed stream continues on the same chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventsCursor {
+	/// The number of the last streamed block.
+	pub block_number: u32,
+	/// The hash of the last streamed block.
+	pub block_hash: H256,
+	/// The hash of the genesis block of the chain the block belongs to.
+	pub genesis_hash: H256,
+}
+
+impl EventsCursor {
+	/// The version of the encoding produced by [EventsCursor::encode].
+	const VERSION: u8 = 1;
+	/// The length of an encoded cursor.
+	const ENCODED_LENGTH: usize = 1 + 4 + 32 + 32;
+
+	/// Encodes the cursor as an opaque byte string.
+	pub fn encode(&self) -> Vec<u8> {
+		[
+			&[Self::VERSION][..],
+			&self.block_number.to_be_bytes(),
+			self.block_hash.as_ref(),
+			self.genesis_hash.as_ref(),
+		]
+		.concat()
+	}
+
+	/// Decodes a cursor produced by [EventsCursor::encode], returning [None] if it is malformed.
+	pub fn decode(bytes: &[u8]) -> Option<Self> {
+		if bytes.len() != Self::ENCODED_LENGTH || bytes[0] != Self::VERSION {
+			return None
+		}
+		Some(Self {
+			block_number: u32::from_be_bytes(bytes[1..5].try_into().ok()?),
+			block_hash: H256::from_slice(&bytes[5..37]),
+			genesis_hash: H256::from_slice(&bytes[37..69]),
+		})
+	}
+}
@@ -0,0 +1,787 @@
+//! A GRPC server for easier use of a validated streams node by external trusted clients.
+/// See <https://github.com/comrade-coop/validated-streams/blob/master/client/proto/streams.proto> for the protobuf file and associated documentation. (or check [self::validated_streams_proto] out)
+use crate::{
+	config::GrpcAddr,
+	errors::Error,
+	events::EventUpdate,
+	payloads::{payload_event_id, EventPayloadsTrait},
+	proofs::WitnessedEvent,
+	traits::{
+		EventStatusTrait, EventValidatorTrait, EventWatcherTrait, EventWitnesserTrait,
+		FinalizedBlockEvents,
+	},
+};
+use futures::{future, stream, FutureExt, Stream, StreamExt};
+use sp_core::{sr25519::Public, ByteArray, H256};
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::{
+	collections::HashSet,
+	fs,
+	os::unix::fs::{FileTypeExt, PermissionsExt},
+	path::Path,
+	pin::Pin,
+	sync::Arc,
+};
+use tokio::{
+	net::{UnixListener, UnixStream},
+	time::{timeout_at, Duration, Instant},
+};
+use tonic::{
+	transport::{Server, ServerTlsConfig},
+	Request, Response, Status,
+};
+use validated_streams_proto::{
+	streams_server::{Streams, StreamsServer},
+	EventPayloadRequest, EventPayloadResponse, EventState, EventStatusRequest, EventStatusResponse,
+	FeedValueRequest, FeedValueResponse, ReportFeedValueRequest, ReportFeedValueResponse,
+	ValidatedEvent, ValidatedEventsRequest, ValidatedEventsResponse, WatchEventRequest,
+	WatchEventResponse, WitnessEventRequest, WitnessEventResponse, WitnessEventStatus,
+	WitnessEventsRequest, WitnessEventsResponse, WitnessedEventSignature,
+};
+
+pub mod auth;
+pub mod cursor;
+#[cfg(test)]
+pub mod tests;
+
+pub use auth::{AuthTokens, ClientAuthenticator, ClientIdentity};
+pub use cursor::EventsCursor;
+
+/// The protobuf module implemented by this server.
+pub mod validated_streams_proto {
+	#![allow(missing_docs)]
+	tonic::include_proto!("validated_streams");
+}
+
+/// Run a GRPC server with the ValidatedStreamsGrpc service on the specified listen addresses.
+/// When `tls_config` is given, the server only accepts TLS connections; when `auth_tokens` is
+/// given, every call must carry one of the tokens as an `authorization: Bearer <token>` header.
+pub async fn run<
+	EventWitnesser: EventWitnesserTrait + Sync + Send + 'static,
+	EventValidator: EventValidatorTrait + Sync + Send + 'static,
+	EventStatusProvider: EventStatusTrait + Sync + Send + 'static,
+	EventWatcher: EventWatcherTrait + Sync + Send + 'static,
+	EventPayloads: EventPayloadsTrait + Sync + Send + 'static,
+>(
+	event_witnesser: Arc<EventWitnesser>,
+	event_validator: Arc<EventValidator>,
+	event_status: Arc<EventStatusProvider>,
+	event_watcher: Arc<EventWatcher>,
+	event_payloads: Arc<EventPayloads>,
+	max_payload_size: usize,
+	grpc_addrs: Vec<GrpcAddr>,
+	socket_mode: u32,
+	tls_config: Option<ServerTlsConfig>,
+	auth_tokens: Option<AuthTokens>,
+) -> Result<(), Error> {
+	if tls_config.is_none() && auth_tokens.is_none() {
+		log::warn!("GRPC server is running without TLS or client tokens");
+	}
+	let authenticator = ClientAuthenticator::new(auth_tokens);
+
+	log::info!(
+		"GRPC server can be reached at {}",
+		grpc_addrs.iter().fold(String::new(), |acc, arg| format!("{acc}, {arg}"))
+	);
+
+	let servers = grpc_addrs
+		.into_iter()
+		.map(|a| {
+			let mut builder = Server::builder();
+			if let Some(tls_config) = tls_config.clone() {
+				builder =
+					builder.tls_config(tls_config).map_err(|e| Error::Other(e.to_string()))?;
+			}
+			let router = builder.add_service(StreamsServer::with_interceptor(
+				ValidatedStreamsGrpc {
+					event_witnesser: event_witnesser.clone(),
+					event_validator: event_validator.clone(),
+					event_status: event_status.clone(),
+					event_watcher: event_watcher.clone(),
+					event_payloads: event_payloads.clone(),
+					max_payload_size,
+				},
+				authenticator.clone(),
+			));
+			Ok(match a {
+				GrpcAddr::Tcp(addr) => router.serve(addr).boxed(),
+				GrpcAddr::Unix(path) =>
+					router.serve_with_incoming(bind_unix(&path, socket_mode)?).boxed(),
+			})
+		})
+		.collect::<Result<Vec<_>, Error>>()?;
+
+	future::try_join_all(servers).await.map_err(|e| Error::Other(e.to_string()))?;
+
+	Ok(())
+}
+
+/// Binds a Unix domain socket at the given path, replacing any stale socket left over from a
+/// previous run, and restricts its permissions to the given mode.
+fn bind_unix(
+	path: &Path,
+	mode: u32,
+) -> Result<impl Stream<Item = Result<UnixStream, std::io::Error>>, Error> {
+	if fs::symlink_metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false) {
+		fs::remove_file(path).map_err(|e| Error::Other(e.to_string()))?;
+	}
+	let listener = UnixListener::bind(path).map_err(|e| Error::Other(e.to_string()))?;
+	fs::set_permissions(path, fs::Permissions::from_mode(mode))
+		.map_err(|e| Error::Other(e.to_string()))?;
+
+	Ok(stream::unfold(listener, async move |listener| {
+		let result = listener.accept().await.map(|(stream, _)| stream);
+		Some((result, listener))
+	}))
+}
+
+/// How many already-finalized blocks to fetch concurrently when replaying past events.
+const REPLAY_CONCURRENCY: usize = 16;
+
+/// A stream of the events of finalized blocks, paired with their block numbers.
+type FinalizedBlocks =
+	Pin<Box<dyn Stream<Item = Result<(u32, FinalizedBlockEvents), Error>> + Send>>;
+
+/// Streams the events of the finalized blocks between `from_block` and `to_block` (inclusive).
+/// Blocks which are already finalized are fetched concurrently, while the rest are waited for one
+/// by one.
+async fn finalized_blocks<EventValidator: EventValidatorTrait + Sync + Send + 'static>(
+	event_validator: Arc<EventValidator>,
+	from_block: u32,
+	to_block: u32,
+) -> Result<FinalizedBlocks, Error> {
+	let latest_finalized = event_validator.get_latest_finalized_block().await?;
+	let replay_end = latest_finalized.min(to_block);
+
+	let fetch = move |event_validator: Arc<EventValidator>, block_num: u32| async move {
+		event_validator
+			.get_finalized_block_events(block_num)
+			.await
+			.map(|block| (block_num, block))
+	};
+
+	let replay = stream::iter(from_block..=replay_end)
+		.map({
+			let event_validator = event_validator.clone();
+			move |block_num| fetch(event_validator.clone(), block_num)
+		})
+		.buffered(REPLAY_CONCURRENCY);
+
+	let live = stream::unfold(
+		(event_validator, Some(from_block.max(replay_end.saturating_add(1)))),
+		async move |(event_validator, block_num)| {
+			let block_num = block_num.filter(|block_num| *block_num <= to_block)?;
+			let result = fetch(event_validator.clone(), block_num).await;
+			Some((result, (event_validator, block_num.checked_add(1))))
+		},
+	);
+
+	Ok(Box::pin(replay.chain(live)))
+}
+
+/// The state of a [Streams::validated_events] stream.
+struct ValidatedEventsState<EventValidator> {
+	event_validator: Arc<EventValidator>,
+	blocks: FinalizedBlocks,
+	next_block: u32,
+	cursor: Option<EventsCursor>,
+	last_sent: Instant,
+	/// The streams whose events are sent; empty for all streams.
+	stream_ids: HashSet<H256>,
+}
+
+/// Implements a GRPC service which allows submitting event hashes from the trusted client and
+/// streaming the finalized events out to the same.
+pub struct ValidatedStreamsGrpc<
+	EventWitnesser,
+	EventValidator,
+	EventStatusProvider,
+	EventWatcher,
+	EventPayloads,
+> {
+	/// A [EventWitnesserTrait] instance.
+	pub event_witnesser: Arc<EventWitnesser>,
+	/// A [EventValidatorTrait] instance.
+	pub event_validator: Arc<EventValidator>,
+	/// A [EventStatusTrait] instance.
+	pub event_status: Arc<EventStatusProvider>,
+	/// A [EventWatcherTrait] instance.
+	pub event_watcher: Arc<EventWatcher>,
+	/// A [EventPayloadsTrait] instance.
+	pub event_payloads: Arc<EventPayloads>,
+	/// The maximum size of payloads accepted by [Streams::witness_event].
+	pub max_payload_size: usize,
+}
+
+#[tonic::async_trait]
+impl<
+		EventWitnesser: EventWitnesserTrait + Sync + Send + 'static,
+		EventValidator: EventValidatorTrait + Sync + Send + 'static,
+		EventStatusProvider: EventStatusTrait + Sync + Send + 'static,
+		EventWatcher: EventWatcherTrait + Sync + Send + 'static,
+		EventPayloads: EventPayloadsTrait + Sync + Send + 'static,
+	> Streams
+	for ValidatedStreamsGrpc<
+		EventWitnesser,
+		EventValidator,
+		EventStatusProvider,
+		EventWatcher,
+		EventPayloads,
+	>
+{
+	async fn witness_event(
+		&self,
+		request: Request<WitnessEventRequest>,
+	) -> Result<Response<WitnessEventResponse>, Status> {
+		let client = client_identity(&request);
+		let event = request.into_inner();
+		let stream_id = parse_stream_id(&event.stream_id)?;
+		let conflict_set = parse_conflict_set(&event.conflict_set)?;
+		let event_id = if event.payload.is_empty() {
+			if event.event_id.len() == 32 {
+				Ok(H256::from_slice(event.event_id.as_slice()))
+			} else {
+				Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
+			}?
+		} else {
+			if event.payload.len() > self.max_payload_size {
+				return Err(Status::invalid_argument(format!(
+					"payload too large (expected at most {} bytes)",
+					self.max_payload_size
+				)))
+			}
+			let event_id = payload_event_id(&event.payload);
+			if !event.event_id.is_empty() && event.event_id != event_id.as_ref() {
+				return Err(Status::invalid_argument("event_id does not match the payload hash"))
+			}
+			event_id
+		};
+
+		if let Some(signature) = event.signature {
+			let witnessed_event = pre_signed_event(stream_id, event_id, conflict_set, signature)?;
+			self.event_witnesser
+				.witness_signed_event(witnessed_event)
+				.await
+				.map_err(pre_signed_event_status)?;
+			log::info!(
+				"Witnessed pre-signed event {event_id:?} in stream {stream_id:?} from {client}"
+			);
+		} else {
+			self.event_witnesser
+				.witness_event(stream_id, event_id, conflict_set)
+				.await
+				.map_err(|e| match e {
+					Error::AlreadyValidated |
+					Error::ConflictSetResolved |
+					Error::ConflictingVote(_) => Status::failed_precondition(e.to_string()),
+					_ => Status::aborted(e.to_string()),
+				})?;
+			log::info!("Witnessed event {event_id:?} in stream {stream_id:?} from {client}");
+		}
+
+		// Payloads are only kept for events that were witnessed, so that rejected requests do not
+		// fill up the store
+		if !event.payload.is_empty() {
+			self.event_payloads
+				.add_event_payload(&event.payload)
+				.map_err(|e| Status::aborted(e.to_string()))?;
+		}
+
+		Ok(Response::new(WitnessEventResponse {}))
+	}
+
+	async fn witness_events(
+		&self,
+		request: Request<WitnessEventsRequest>,
+	) -> Result<Response<WitnessEventsResponse>, Status> {
+		let client = client_identity(&request);
+		let request = request.into_inner();
+		let stream_id = parse_stream_id(&request.stream_id)?;
+
+		let mut statuses = vec![WitnessEventStatus::Accepted; request.event_ids.len()];
+		let mut event_ids = Vec::with_capacity(request.event_ids.len());
+		let mut event_indices = Vec::with_capacity(request.event_ids.len());
+		for (index, event_id) in request.event_ids.iter().enumerate() {
+			if event_id.len() == 32 {
+				event_ids.push(H256::from_slice(event_id.as_slice()));
+				event_indices.push(index);
+			} else {
+				statuses[index] = WitnessEventStatus::InvalidLength;
+			}
+		}
+
+		match self.event_witnesser.witness_events(stream_id, event_ids).await {
+			Ok(results) =>
+				for (index, result) in event_indices.into_iter().zip(results) {
+					statuses[index] = match result {
+						Ok(()) => WitnessEventStatus::Accepted,
+						Err(Error::AlreadyValidated) => WitnessEventStatus::AlreadyValidated,
+						Err(Error::NotAValidator) => WitnessEventStatus::NotAValidator,
+						Err(e) => {
+							log::debug!("Failed witnessing event: {}", e);
+							WitnessEventStatus::Failed
+						},
+					}
+				},
+			Err(Error::NotAValidator) =>
+				for index in event_indices {
+					statuses[index] = WitnessEventStatus::NotAValidator;
+				},
+			Err(e) => return Err(Status::aborted(e.to_string())),
+		}
+
+		for (event_id, status) in request.event_ids.iter().zip(&statuses) {
+			if *status == WitnessEventStatus::Accepted {
+				log::info!(
+					"Witnessed event 0x{} in stream {stream_id:?} from {client}",
+					hex::encode(event_id)
+				);
+			}
+		}
+
+		Ok(Response::new(WitnessEventsResponse {
+			statuses: statuses.into_iter().map(|status| status as i32).collect(),
+		}))
+	}
+
+	// This type looks terrifying, but I'm blaming tonic; even their examples have that!
+	type ValidatedEventsStream =
+		Pin<Box<dyn Stream<Item = Result<ValidatedEventsResponse, Status>> + Send>>;
+
+	async fn validated_events(
+		&self,
+		request: Request<ValidatedEventsRequest>,
+	) -> Result<Response<Self::ValidatedEventsStream>, Status> {
+		let request = request.into_inner();
+
+		let genesis_hash = self
+			.event_validator
+			.get_finalized_block_hash(0)
+			.await
+			.map_err(|e| Status::aborted(e.to_string()))?
+			.unwrap_or_default();
+
+		let (from_block, cursor) = if !request.cursor.is_empty() {
+			let cursor = EventsCursor::decode(&request.cursor)
+				.ok_or_else(|| Status::invalid_argument("malformed cursor"))?;
+			if cursor.genesis_hash != genesis_hash {
+				return Err(Status::failed_precondition("cursor is from a different chain"))
+			}
+			match self.event_validator.get_finalized_block_hash(cursor.block_number).await {
+				Ok(Some(block_hash)) if block_hash == cursor.block_hash => {},
+				Ok(_) =>
+					return Err(Status::failed_precondition(format!(
+						"cursor block #{} is not part of the finalized chain",
+						cursor.block_number
+					))),
+				Err(e) => return Err(Status::not_found(e.to_string())),
+			}
+			let from_block = cursor
+				.block_number
+				.checked_add(1)
+				.ok_or_else(|| Status::invalid_argument("cursor is at the last possible block"))?;
+			(from_block, Some(cursor))
+		} else if request.from_block == 0 && request.from_latest {
+			(self.event_validator.get_latest_finalized_block().await.unwrap_or_default(), None)
+		} else {
+			(request.from_block, None)
+		};
+		let to_block = request.to_block.unwrap_or(u32::MAX);
+		let heartbeat_interval = (request.heartbeat_interval > 0)
+			.then(|| Duration::from_secs(request.heartbeat_interval.into()));
+		let skip_empty_blocks = request.skip_empty_blocks;
+		let stream_ids = request
+			.stream_ids
+			.iter()
+			.map(|stream_id| parse_stream_id(stream_id))
+			.collect::<Result<HashSet<_>, _>>()?;
+
+		let blocks = finalized_blocks(self.event_validator.clone(), from_block, to_block)
+			.await
+			.map_err(|e| Status::aborted(e.to_string()))?;
+
+		Ok(Response::new(Box::pin(stream::unfold(
+			// The state becomes None once the stream should end.
+			Some(ValidatedEventsState {
+				event_validator: self.event_validator.clone(),
+				blocks,
+				next_block: from_block,
+				cursor,
+				last_sent: Instant::now(),
+				stream_ids,
+			}),
+			async move |state| {
+				let mut state = state?;
+				loop {
+					let next = match heartbeat_interval {
+						Some(interval) => {
+							let deadline = state.last_sent + interval;
+							match timeout_at(deadline, state.blocks.next()).await {
+								Ok(next) => next,
+								Err(_) => {
+									state.last_sent = Instant::now();
+									let latest_finalized_block = state
+										.event_validator
+										.get_latest_finalized_block()
+										.await
+										.unwrap_or_default();
+									let response = ValidatedEventsResponse {
+										next_block: state.next_block,
+										heartbeat: true,
+										latest_finalized_block,
+										cursor: state
+											.cursor
+											.map(|cursor| cursor.encode())
+											.unwrap_or_default(),
+										..Default::default()
+									};
+									return Some((Ok(response), Some(state)))
+								},
+							}
+						},
+						None => state.blocks.next().await,
+					};
+
+					let (block_num, block) = match next {
+						None => return None,
+						// Stop at the first error, so that clients never miss a block silently
+						Some(Err(e @ Error::BlockUnavailable(_))) =>
+							return Some((Err(Status::not_found(e.to_string())), None)),
+						Some(Err(e)) => return Some((Err(Status::aborted(e.to_string())), None)),
+						Some(Ok(block)) => block,
+					};
+
+					let cursor = EventsCursor {
+						block_number: block_num,
+						block_hash: block.block_hash,
+						genesis_hash,
+					};
+					// There is no block after the last possible one, so the stream ends with it
+					let has_next_block = match block_num.checked_add(1) {
+						Some(next_block) => {
+							state.next_block = next_block;
+							true
+						},
+						None => false,
+					};
+					state.cursor = Some(cursor);
+
+					let events = block
+						.events
+						.into_iter()
+						.filter(|event| {
+							state.stream_ids.is_empty() ||
+								state.stream_ids.contains(&event.stream_id)
+						})
+						.map(|event| ValidatedEvent {
+							stream_id: event.stream_id.as_ref().to_vec(),
+							event_id: event.event_id.as_ref().to_vec(),
+							conflict_set: event
+								.conflict_set
+								.map(|conflict_set| conflict_set.as_ref().to_vec())
+								.unwrap_or_default(),
+							extrinsic_index: event.extrinsic_index,
+							signers: event.signers.into_iter().map(|pub_key| pub_key.1).collect(),
+						})
+						.collect::<Vec<_>>();
+
+					if skip_empty_blocks && events.is_empty() {
+						if !has_next_block {
+							return None
+						}
+						continue
+					}
+
+					let latest_finalized_block = state
+						.event_validator
+						.get_latest_finalized_block()
+						.await
+						.unwrap_or_default();
+
+					state.last_sent = Instant::now();
+					let response = ValidatedEventsResponse {
+						next_block: state.next_block,
+						events,
+						block_hash: block.block_hash.as_ref().to_vec(),
+						cursor: cursor.encode(),
+						heartbeat: false,
+						latest_finalized_block,
+					};
+					return Some((Ok(response), has_next_block.then_some(state)))
+				}
+			},
+		))))
+	}
+
+	async fn get_event_status(
+		&self,
+		request: Request<EventStatusRequest>,
+	) -> Result<Response<EventStatusResponse>, Status> {
+		let request = request.into_inner();
+		let stream_id = parse_stream_id(&request.stream_id)?;
+		let event_id = if request.event_id.len() == 32 {
+			Ok(H256::from_slice(request.event_id.as_slice()))
+		} else {
+			Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
+		}?;
+
+		let status = self
+			.event_status
+			.get_event_status(stream_id, event_id)
+			.await
+			.map_err(|e| Status::aborted(e.to_string()))?;
+
+		let (block_number, block_hash) = status
+			.included_in
+			.map(|(block_number, block_hash)| (block_number, block_hash.as_ref().to_vec()))
+			.unwrap_or_default();
+
+		Ok(Response::new(EventStatusResponse {
+			proof_count: status.proof_count.into(),
+			target: status.target.into(),
+			signers: status.signers.into_iter().map(|pub_key| pub_key.1).collect(),
+			in_transaction_pool: status.in_transaction_pool,
+			included: status.included_in.is_some(),
+			block_number,
+			block_hash,
+			finalized: status.finalized,
+		}))
+	}
+
+	async fn get_event_payload(
+		&self,
+		request: Request<EventPayloadRequest>,
+	) -> Result<Response<EventPayloadResponse>, Status> {
+		let request = request.into_inner();
+		let event_id = if request.event_id.len() == 32 {
+			Ok(H256::from_slice(request.event_id.as_slice()))
+		} else {
+			Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
+		}?;
+
+		let payload = self
+			.event_payloads
+			.get_event_payload(&event_id)
+			.map_err(|e| Status::aborted(e.to_string()))?
+			.ok_or_else(|| Status::not_found("no payload stored for event"))?;
+
+		Ok(Response::new(EventPayloadResponse { payload }))
+	}
+
+	async fn report_feed_value(
+		&self,
+		request: Request<ReportFeedValueRequest>,
+	) -> Result<Response<ReportFeedValueResponse>, Status> {
+		let client = client_identity(&request);
+		let request = request.into_inner();
+		let feed_id = parse_feed_id(&request.feed_id)?;
+
+		self.event_witnesser
+			.report_feed_value(feed_id, request.round, request.value)
+			.await
+			.map_err(|e| match e {
+				Error::AlreadyValidated | Error::ConflictingVote(_) =>
+					Status::failed_precondition(e.to_string()),
+				_ => Status::aborted(e.to_string()),
+			})?;
+		log::info!(
+			"Reported value {} for round {} of feed {feed_id:?} from {client}",
+			request.value,
+			request.round
+		);
+
+		Ok(Response::new(ReportFeedValueResponse {}))
+	}
+
+	async fn get_feed_value(
+		&self,
+		request: Request<FeedValueRequest>,
+	) -> Result<Response<FeedValueResponse>, Status> {
+		let request = request.into_inner();
+		let feed_id = parse_feed_id(&request.feed_id)?;
+		let round = if request.latest { None } else { Some(request.round) };
+
+		let value = self
+			.event_status
+			.get_feed_value(feed_id, round)
+			.await
+			.map_err(|e| Status::aborted(e.to_string()))?
+			.ok_or_else(|| Status::not_found("no value stored for the feed round"))?;
+
+		Ok(Response::new(FeedValueResponse {
+			round: value.round,
+			median: value.median,
+			signers: value.signers.into_iter().map(|pub_key| pub_key.1).collect(),
+		}))
+	}
+
+	type WatchEventStream = Pin<Box<dyn Stream<Item = Result<WatchEventResponse, Status>> + Send>>;
+
+	async fn watch_event(
+		&self,
+		request: Request<WatchEventRequest>,
+	) -> Result<Response<Self::WatchEventStream>, Status> {
+		let request = request.into_inner();
+		let stream_id = parse_stream_id(&request.stream_id)?;
+		let event_ids = request
+			.event_ids
+			.iter()
+			.map(|event_id| {
+				if event_id.len() == 32 {
+					Ok(H256::from_slice(event_id.as_slice()))
+				} else {
+					Err(Status::invalid_argument("invalid event_id length (expected 32 bytes)"))
+				}
+			})
+			.collect::<Result<HashSet<_>, _>>()?;
+
+		// Subscribe before checking the current status, so that no update falls in between
+		let updates = self.event_watcher.subscribe();
+
+		let mut pending = event_ids;
+		let mut already_finalized = Vec::new();
+		for event_id in pending.clone() {
+			let status = self
+				.event_status
+				.get_event_status(stream_id, event_id)
+				.await
+				.map_err(|e| Status::aborted(e.to_string()))?;
+			if let (true, Some((block_number, block_hash))) = (status.finalized, status.included_in)
+			{
+				already_finalized.push(EventUpdate::Finalized {
+					stream_id,
+					event_id,
+					block_number,
+					block_hash,
+				});
+				pending.remove(&event_id);
+			}
+		}
+
+		let pending_updates =
+			stream::unfold((updates, pending), |(mut updates, mut pending)| async move {
+				while !pending.is_empty() {
+					let update = updates.next().await?;
+					if update.stream_id() != &stream_id || !pending.contains(update.event_id()) {
+						continue
+					}
+					if let EventUpdate::Finalized { event_id, .. } = &update {
+						pending.remove(event_id);
+					}
+					return Some((update, (updates, pending)))
+				}
+				None
+			});
+
+		Ok(Response::new(Box::pin(
+			stream::iter(already_finalized)
+				.chain(pending_updates)
+				.map(|update| Ok(watch_event_response(update))),
+		)))
+	}
+}
+
+/// Describes the client which made a request, for logging.
+fn client_identity<T>(request: &Request<T>) -> String {
+	ClientIdentity::of(request)
+		.map(ToString::to_string)
+		.unwrap_or_else(|| "unknown client".to_string())
+}
+
+/// Parses a stream id from a request, treating an empty one as the default (zero) stream.
+fn parse_stream_id(stream_id: &[u8]) -> Result<H256, Status> {
+	match stream_id.len() {
+		0 => Ok(H256::zero()),
+		32 => Ok(H256::from_slice(stream_id)),
+		_ => Err(Status::invalid_argument("invalid stream_id length (expected 32 bytes)")),
+	}
+}
+
+/// Builds the [WitnessedEvent] of an event signed by the client rather than the node.
+fn pre_signed_event(
+	stream_id: H256,
+	event_id: H256,
+	conflict_set: Option<H256>,
+	signature: WitnessedEventSignature,
+) -> Result<WitnessedEvent, Status> {
+	let pub_key = Public::from_slice(signature.pub_key.as_slice())
+		.map_err(|_| Status::invalid_argument("invalid pub_key length (expected 32 bytes)"))?;
+	Ok(WitnessedEvent {
+		signature: signature.signature,
+		pub_key: CryptoTypePublicPair::from(pub_key),
+		stream_id,
+		event_id,
+		conflict_set,
+	})
+}
+
+/// Converts a failure to witness a pre-signed event to a status; bad signatures, or signatures
+/// of keys which are not current authorities, are the client's fault, while events already
+/// validated or conflicting with another vote of the same key are refused as for any event.
+fn pre_signed_event_status(e: Error) -> Status {
+	match e {
+		Error::BadWitnessedEventSignature(_) | Error::UnknownSigner(_) =>
+			Status::invalid_argument(e.to_string()),
+		Error::AlreadyValidated | Error::ConflictSetResolved | Error::ConflictingVote(_) =>
+			Status::failed_precondition(e.to_string()),
+		_ => Status::aborted(e.to_string()),
+	}
+}
+
+/// Parses a feed id, which is always exactly 32 bytes long.
+fn parse_feed_id(feed_id: &[u8]) -> Result<H256, Status> {
+	if feed_id.len() == 32 {
+		Ok(H256::from_slice(feed_id))
+	} else {
+		Err(Status::invalid_argument("invalid feed_id length (expected 32 bytes)"))
+	}
+}
+
+/// Parses an optional conflict set id; empty means the event is not part of a conflict set.
+fn parse_conflict_set(conflict_set: &[u8]) -> Result<Option<H256>, Status> {
+	match conflict_set.len() {
+		0 => Ok(None),
+		32 => Ok(Some(H256::from_slice(conflict_set))),
+		_ => Err(Status::invalid_argument("invalid conflict_set length (expected 32 bytes)")),
+	}
+}
+
+/// Converts an [EventUpdate] to its protobuf representation.
+fn watch_event_response(update: EventUpdate) -> WatchEventResponse {
+	match update {
+		EventUpdate::ProofReceived { stream_id, event_id, signer, proof_count, target } =>
+			WatchEventResponse {
+				stream_id: stream_id.as_ref().to_vec(),
+				event_id: event_id.as_ref().to_vec(),
+				state: EventState::ProofReceived as i32,
+				signer: signer.1,
+				proof_count: proof_count.into(),
+				target: target.into(),
+				..Default::default()
+			},
+		EventUpdate::Submitted { stream_id, event_id } => WatchEventResponse {
+			stream_id: stream_id.as_ref().to_vec(),
+			event_id: event_id.as_ref().to_vec(),
+			state: EventState::Submitted as i32,
+			..Default::default()
+		},
+		EventUpdate::Included { stream_id, event_id, block_number, block_hash } =>
+			WatchEventResponse {
+				stream_id: stream_id.as_ref().to_vec(),
+				event_id: event_id.as_ref().to_vec(),
+				state: EventState::Included as i32,
+				block_number,
+				block_hash: block_hash.as_ref().to_vec(),
+				..Default::default()
+			},
+		EventUpdate::Finalized { stream_id, event_id, block_number, block_hash } =>
+			WatchEventResponse {
+				stream_id: stream_id.as_ref().to_vec(),
+				event_id: event_id.as_ref().to_vec(),
+				state: EventState::Finalized as i32,
+				block_number,
+				block_hash: block_hash.as_ref().to_vec(),
+				..Default::default()
+			},
+	}
+}
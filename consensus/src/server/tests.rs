@@ -0,0 +1,158 @@
+use super::{
+	auth::{AuthTokens, ClientAuthenticator, ClientIdentity},
+	bind_unix, pre_signed_event, pre_signed_event_status,
+	validated_streams_proto::WitnessedEventSignature,
+	EventsCursor,
+};
+use crate::events::{AuthoritiesList, ConflictSetVotes};
+use pallet_validated_streams::witnessed_event_message;
+use rstest::rstest;
+use sc_keystore::LocalKeystore;
+use sp_core::H256;
+use sp_keystore::CryptoStore;
+use sp_runtime::{app_crypto::CryptoTypePublicPair, key_types::AURA};
+use std::{fs, os::unix::fs::PermissionsExt};
+use tonic::{service::Interceptor, Code, Request, Status};
+
+#[rstest]
+fn test_parse_tokens() {
+	let tokens = AuthTokens::parse("# comment\n\nbridge: abc123\nindexer:def456\n").unwrap();
+	assert_eq!(tokens.client_name("abc123"), Some(&"bridge".to_string()));
+	assert_eq!(tokens.client_name("def456"), Some(&"indexer".to_string()));
+	assert_eq!(tokens.client_name("bridge"), None);
+
+	assert!(AuthTokens::parse("no-separator").is_err());
+	assert!(AuthTokens::parse(":token").is_err());
+	assert!(AuthTokens::parse("name:").is_err());
+}
+
+#[rstest]
+fn test_authenticator_without_tokens() {
+	let mut authenticator = ClientAuthenticator::new(None);
+	let request = authenticator.call(Request::new(())).unwrap();
+	let identity = ClientIdentity::of(&request).unwrap();
+	assert_eq!(identity.token_name, None);
+	assert_eq!(identity.to_string(), "unknown client");
+}
+
+#[rstest]
+#[case(None, Some(Code::Unauthenticated))]
+#[case(Some("Bearer wrong"), Some(Code::Unauthenticated))]
+#[case(Some("abc123"), Some(Code::Unauthenticated))]
+#[case(Some("Bearer abc123"), None)]
+fn test_authenticator_with_tokens(#[case] header: Option<&str>, #[case] error: Option<Code>) {
+	let mut authenticator =
+		ClientAuthenticator::new(Some(AuthTokens::parse("bridge:abc123").unwrap()));
+	let mut request = Request::new(());
+	if let Some(header) = header {
+		request.metadata_mut().insert("authorization", header.parse().unwrap());
+	}
+
+	match authenticator.call(request) {
+		Ok(request) => {
+			assert_eq!(error, None);
+			let identity = ClientIdentity::of(&request).unwrap();
+			assert_eq!(identity.token_name, Some("bridge".to_string()));
+			assert_eq!(identity.to_string(), "token bridge");
+		},
+		Err(status) => assert_eq!(Some(status.code()), error),
+	}
+}
+
+#[tokio::test]
+async fn test_bind_unix_socket() {
+	let path = std::env::temp_dir().join(format!("vstreams-test-{}.sock", std::process::id()));
+	fs::write(&path, b"").unwrap();
+	// Regular files are never removed
+	assert!(bind_unix(&path, 0o600).is_err());
+	fs::remove_file(&path).unwrap();
+
+	let listener = bind_unix(&path, 0o600).unwrap();
+	assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+	drop(listener);
+
+	// Stale sockets are replaced
+	let listener = bind_unix(&path, 0o660).unwrap();
+	assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o660);
+	drop(listener);
+
+	fs::remove_file(&path).unwrap();
+}
+
+#[rstest]
+fn test_events_cursor() {
+	let cursor = EventsCursor {
+		block_number: 42,
+		block_hash: H256::repeat_byte(1),
+		genesis_hash: H256::repeat_byte(2),
+	};
+	let encoded = cursor.encode();
+	assert_eq!(EventsCursor::decode(&encoded), Some(cursor));
+
+	assert_eq!(EventsCursor::decode(&encoded[1..]), None);
+	assert_eq!(EventsCursor::decode(&[]), None);
+	let mut wrong_version = encoded;
+	wrong_version[0] = 0;
+	assert_eq!(EventsCursor::decode(&wrong_version), None);
+}
+
+/// test that events pre-signed by a current authority are accepted, and that bad signatures are
+/// rejected as invalid arguments
+#[tokio::test]
+async fn test_pre_signed_events() {
+	let keystore = LocalKeystore::in_memory();
+	let authority = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let outsider = keystore.sr25519_generate_new(AURA, None).await.unwrap();
+	let authorities = AuthoritiesList::new(vec![CryptoTypePublicPair::from(authority)]);
+	let stream_id = H256::repeat_byte(7);
+	let event_id = H256::repeat_byte(1);
+	let conflict_set = Some(H256::repeat_byte(2));
+	let message = witnessed_event_message(&stream_id, &event_id, conflict_set.as_ref());
+	let sign = |key: sp_core::sr25519::Public| {
+		let keystore = &keystore;
+		let message = &message;
+		async move {
+			let signature = keystore
+				.sign_with(AURA, &CryptoTypePublicPair::from(key), message)
+				.await
+				.unwrap()
+				.unwrap();
+			WitnessedEventSignature { signature, pub_key: key.0.to_vec() }
+		}
+	};
+	let witness = |event_id: H256, signature: WitnessedEventSignature| -> Result<_, Status> {
+		let witnessed_event = pre_signed_event(stream_id, event_id, conflict_set, signature)?;
+		authorities
+			.verify_witnessed_event_origin(witnessed_event)
+			.map_err(pre_signed_event_status)
+	};
+
+	let valid = sign(authority).await;
+	let witnessed_event = witness(event_id, valid.clone()).unwrap();
+	assert_eq!(witnessed_event.pub_key, CryptoTypePublicPair::from(authority));
+	assert_eq!(witnessed_event.conflict_set, conflict_set);
+
+	let mut bad_signature = valid.clone();
+	bad_signature.signature[8] ^= 1;
+	assert_eq!(witness(event_id, bad_signature).unwrap_err().code(), Code::InvalidArgument);
+
+	// The signature does not cover other events
+	let other_event = H256::repeat_byte(3);
+	assert_eq!(witness(other_event, valid.clone()).unwrap_err().code(), Code::InvalidArgument);
+
+	let short_key = WitnessedEventSignature { pub_key: vec![1; 31], ..valid };
+	assert_eq!(witness(event_id, short_key).unwrap_err().code(), Code::InvalidArgument);
+
+	let non_authority = sign(outsider).await;
+	assert_eq!(witness(event_id, non_authority).unwrap_err().code(), Code::InvalidArgument);
+
+	// Pre-signed events count as votes in their conflict set, like the events the node signs
+	let conflict_votes = ConflictSetVotes::default();
+	let pub_key = CryptoTypePublicPair::from(authority);
+	let conflict_set = conflict_set.unwrap();
+	conflict_votes.record(stream_id, conflict_set, event_id, &pub_key).unwrap();
+	let conflicting_vote = conflict_votes
+		.record(stream_id, conflict_set, other_event, &pub_key)
+		.unwrap_err();
+	assert_eq!(pre_signed_event_status(conflicting_vote).code(), Code::FailedPrecondition);
+}
@@ -1,16 +1,46 @@
 //! Traits used by Validated Streams code
 
-use crate::errors::Error;
+use crate::{errors::Error, events::EventUpdate, proofs::WitnessedEvent};
 use async_trait::async_trait;
+use futures::Stream;
 use sp_core::H256;
+use sp_runtime::app_crypto::CryptoTypePublicPair;
+use std::pin::Pin;
 
 /// A trait wrapping the functionality of witnessing an event that is called by the trusted client
 /// (e.g. through GRPC).
 #[async_trait]
 pub trait EventWitnesserTrait {
-	/// Witnesses an event by signing it with the key of the current node and gossipping the
-	/// signature to all peers.
-	async fn witness_event(&self, event: H256) -> Result<(), Error>;
+	/// Witnesses an event of the given stream by signing it with the key of the current node and
+	/// gossipping the signature to all peers. When a conflict set is given, the event is witnessed
+	/// as a member of that set, failing if the node already witnessed another member of it.
+	async fn witness_event(
+		&self,
+		stream_id: H256,
+		event: H256,
+		conflict_set: Option<H256>,
+	) -> Result<(), Error>;
+
+	/// Witnesses a batch of events of the given stream by signing them with the key of the current
+	/// node and gossipping all the signatures to peers at once. Returns the outcome for each of the
+	/// events, in order; errors which affect the whole batch (e.g. [Error::NotAValidator]) are
+	/// returned directly.
+	async fn witness_events(
+		&self,
+		stream_id: H256,
+		events: Vec<H256>,
+	) -> Result<Vec<Result<(), Error>>, Error>;
+
+	/// Witnesses an event which was already signed by one of the authorities (e.g. by a trusted
+	/// client holding the authority key), verifying the signature and gossipping it to all peers
+	/// without using the keystore of the current node.
+	async fn witness_signed_event(&self, witnessed_event: WitnessedEvent) -> Result<(), Error>;
+
+	/// Reports a value (e.g. a price or a temperature) of a feed for a round, by signing it with
+	/// the key of the current node and gossipping it to all peers. Once 2/3 of the authorities have
+	/// reported, the median of their values is stored on-chain. Fails with
+	/// [Error::AlreadyValidated] if a value was already stored for the round.
+	async fn report_feed_value(&self, feed_id: H256, round: u64, value: i64) -> Result<(), Error>;
 }
 
 /// A trait responsible for getting a stream of validated/finalized events from the node to a
@@ -21,8 +51,91 @@ pub trait EventValidatorTrait {
 	/// Get the list of events in a specific block. If the block is not ready yet, waits until the
 	/// block is finalized. To use as a stream of events, just query the events in successive block
 	/// numbers.
-	async fn get_finalized_block_events(&self, block_num: u32) -> Result<Vec<H256>, Error>;
+	async fn get_finalized_block_events(
+		&self,
+		block_num: u32,
+	) -> Result<FinalizedBlockEvents, Error>;
 
 	/// Get the latest block's number.
 	async fn get_latest_finalized_block(&self) -> Result<u32, Error>;
+
+	/// Get the hash of a finalized block, or [None] if the block is not finalized yet. Fails with
+	/// [Error::BlockUnavailable] if the node no longer knows about the block.
+	async fn get_finalized_block_hash(&self, block_num: u32) -> Result<Option<H256>, Error>;
+}
+
+/// The validated events of a finalized block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FinalizedBlockEvents {
+	/// The hash of the finalized block.
+	pub block_hash: H256,
+	/// The events validated in the block, in the order of the extrinsics validating them.
+	pub events: Vec<ValidatedEventDetails>,
+}
+
+/// An event validated in a finalized block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidatedEventDetails {
+	/// The id of the stream the event belongs to.
+	pub stream_id: H256,
+	/// The id of the event.
+	pub event_id: H256,
+	/// The conflict set the event was validated as a member of, if any.
+	pub conflict_set: Option<H256>,
+	/// The index of the extrinsic which validated the event within its block.
+	pub extrinsic_index: u32,
+	/// The authorities whose proofs were used to validate the event.
+	pub signers: Vec<CryptoTypePublicPair>,
+}
+
+/// The lifecycle status of an event, as seen by the current node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventStatus {
+	/// The number of proofs by current authorities that the node holds for the event.
+	pub proof_count: u16,
+	/// The number of proofs needed for the event to be submitted on-chain.
+	pub target: u16,
+	/// The authorities whose proofs the node holds.
+	pub signers: Vec<CryptoTypePublicPair>,
+	/// Whether the extrinsic validating the event is in the transaction pool.
+	pub in_transaction_pool: bool,
+	/// The number and hash of the best-chain block which included the event, if any.
+	pub included_in: Option<(u32, H256)>,
+	/// Whether the block which included the event has been finalized.
+	pub finalized: bool,
+}
+
+/// The median value of a feed for a round, as stored on-chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeedValue {
+	/// The round the value was reported for.
+	pub round: u64,
+	/// The median of the values reported by the authorities.
+	pub median: i64,
+	/// The authorities whose reports were used to compute the median.
+	pub signers: Vec<CryptoTypePublicPair>,
+}
+
+/// A trait for inspecting where an event is in its lifecycle, from the first witness signature to
+/// its finalization.
+#[async_trait]
+pub trait EventStatusTrait {
+	/// Get the current status of an event of the given stream.
+	async fn get_event_status(&self, stream_id: H256, event_id: H256)
+		-> Result<EventStatus, Error>;
+
+	/// Get the value of a feed stored in the best block for the given round, or for the latest
+	/// reported round if [None].
+	async fn get_feed_value(
+		&self,
+		feed_id: H256,
+		round: Option<u64>,
+	) -> Result<Option<FeedValue>, Error>;
+}
+
+/// A trait for following the lifecycle of events as it changes, e.g. to show live progress of
+/// specific events to a client.
+pub trait EventWatcherTrait {
+	/// Subscribe to all [EventUpdate]-s happening from now on.
+	fn subscribe(&self) -> Pin<Box<dyn Stream<Item = EventUpdate> + Send>>;
 }
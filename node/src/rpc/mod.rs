@@ -7,7 +7,9 @@
 
 use std::sync::Arc;
 
+use consensus_validated_streams::ValidatedStreamsServices;
 use jsonrpsee::RpcModule;
+use sc_rpc::SubscriptionTaskExecutor;
 use sc_transaction_pool_api::TransactionPool;
 use sp_api::ProvideRuntimeApi;
 use sp_block_builder::BlockBuilder;
@@ -16,6 +18,8 @@ use vstreams_node_runtime::{opaque::Block, AccountId, Balance, Index};
 
 pub use sc_rpc_api::DenyUnsafe;
 
+pub mod streams;
+
 /// Full client dependencies.
 pub struct FullDeps<C, P> {
 	/// The client instance to use.
@@ -24,6 +28,10 @@ pub struct FullDeps<C, P> {
 	pub pool: Arc<P>,
 	/// Whether to deny unsafe calls
 	pub deny_unsafe: DenyUnsafe,
+	/// The running Validated Streams services.
+	pub validated_streams: ValidatedStreamsServices,
+	/// Executor for spawning subscription tasks.
+	pub subscription_executor: SubscriptionTaskExecutor,
 }
 
 /// Instantiate all full RPC extensions.
@@ -40,13 +48,24 @@ where
 	P: TransactionPool + 'static,
 {
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApiServer};
+	use streams::{Streams, StreamsApiServer};
 	use substrate_frame_rpc_system::{System, SystemApiServer};
 
 	let mut module = RpcModule::new(());
-	let FullDeps { client, pool, deny_unsafe } = deps;
+	let FullDeps { client, pool, deny_unsafe, validated_streams, subscription_executor } = deps;
 
 	module.merge(System::new(client.clone(), pool, deny_unsafe).into_rpc())?;
 	module.merge(TransactionPayment::new(client).into_rpc())?;
+	module.merge(
+		Streams::new(
+			validated_streams.event_witnesser,
+			validated_streams.event_validator,
+			validated_streams.event_status,
+			subscription_executor,
+			deny_unsafe,
+		)
+		.into_rpc(),
+	)?;
 
 	// Extend this RPC with a custom API by using the following syntax.
 	// `YourRpcStruct` should have a reference to a client, which is needed
@@ -0,0 +1,347 @@
+//! A JSON-RPC mirror of the Validated Streams GRPC API, served under the `vstreams_` prefix.
+//! Allows web dashboards and non-validator RPC nodes to use the same port as the rest of the node
+//! RPC, without exposing the GRPC server.
+
+use consensus_validated_streams::{
+	errors::Error,
+	traits::{
+		EventStatus, EventStatusTrait, EventValidatorTrait, EventWitnesserTrait, FeedValue,
+		FinalizedBlockEvents,
+	},
+};
+use futures::{future, stream, FutureExt, Stream, StreamExt};
+use jsonrpsee::{
+	core::{async_trait, Error as JsonRpseeError, RpcResult},
+	proc_macros::rpc,
+	types::{
+		error::{CallError, SubscriptionClosed},
+		ErrorObject, SubscriptionResult,
+	},
+	SubscriptionSink,
+};
+use sc_rpc::SubscriptionTaskExecutor;
+use sc_rpc_api::DenyUnsafe;
+use serde::{Deserialize, Serialize};
+use sp_core::{Bytes, H256};
+use std::{collections::HashSet, sync::Arc};
+
+#[cfg(test)]
+mod tests;
+
+/// The JSON-RPC error code for an event which was already validated.
+const ALREADY_VALIDATED_ERROR: i32 = 9000;
+/// The JSON-RPC error code used when the current node is not a validator.
+const NOT_A_VALIDATOR_ERROR: i32 = 9001;
+/// The JSON-RPC error code for any other Validated Streams error.
+const OTHER_ERROR: i32 = 9002;
+/// The JSON-RPC error code for an event conflicting with another member of its conflict set.
+const CONFLICT_ERROR: i32 = 9003;
+
+/// An event validated in a finalized block.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatedEvent {
+	/// The id of the stream the event belongs to.
+	pub stream_id: H256,
+	/// The id of the event.
+	pub event_id: H256,
+	/// The conflict set the event was validated as a member of, if any.
+	pub conflict_set: Option<H256>,
+	/// The index of the extrinsic which validated the event within its block.
+	pub extrinsic_index: u32,
+	/// The public keys of the authorities whose proofs were used to validate the event.
+	pub signers: Vec<Bytes>,
+}
+
+/// The validated events of a finalized block, as sent to `vstreams_subscribeValidatedEvents`
+/// subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatedBlock {
+	/// The number of the finalized block.
+	pub block_number: u32,
+	/// The hash of the finalized block.
+	pub block_hash: H256,
+	/// The events validated in the block.
+	pub events: Vec<ValidatedEvent>,
+}
+
+impl ValidatedBlock {
+	fn new(block_number: u32, block: FinalizedBlockEvents) -> Self {
+		Self {
+			block_number,
+			block_hash: block.block_hash,
+			events: block
+				.events
+				.into_iter()
+				.map(|event| ValidatedEvent {
+					stream_id: event.stream_id,
+					event_id: event.event_id,
+					conflict_set: event.conflict_set,
+					extrinsic_index: event.extrinsic_index,
+					signers: event.signers.into_iter().map(|signer| signer.1.into()).collect(),
+				})
+				.collect(),
+		}
+	}
+}
+
+/// The lifecycle status of an event, as returned by `vstreams_eventStatus`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventStatusResponse {
+	/// The number of proofs by current authorities that the node holds for the event.
+	pub proof_count: u16,
+	/// The number of proofs needed for the event to be submitted on-chain.
+	pub target: u16,
+	/// The public keys of the authorities whose proofs the node holds.
+	pub signers: Vec<Bytes>,
+	/// Whether the extrinsic validating the event is in the transaction pool.
+	pub in_transaction_pool: bool,
+	/// The number of the best-chain block which included the event, if any.
+	pub block_number: Option<u32>,
+	/// The hash of the best-chain block which included the event, if any.
+	pub block_hash: Option<H256>,
+	/// Whether the block which included the event has been finalized.
+	pub finalized: bool,
+}
+
+impl From<EventStatus> for EventStatusResponse {
+	fn from(status: EventStatus) -> Self {
+		Self {
+			proof_count: status.proof_count,
+			target: status.target,
+			signers: status.signers.into_iter().map(|signer| signer.1.into()).collect(),
+			in_transaction_pool: status.in_transaction_pool,
+			block_number: status.included_in.map(|(number, _)| number),
+			block_hash: status.included_in.map(|(_, hash)| hash),
+			finalized: status.finalized,
+		}
+	}
+}
+
+/// The median value of a feed for a round, as returned by `vstreams_feedValue`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedValueResponse {
+	/// The round of the value.
+	pub round: u64,
+	/// The median of the values reported by the authorities.
+	pub median: i64,
+	/// The public keys of the authorities whose reports were used to compute the median.
+	pub signers: Vec<Bytes>,
+}
+
+impl From<FeedValue> for FeedValueResponse {
+	fn from(value: FeedValue) -> Self {
+		Self {
+			round: value.round,
+			median: value.median,
+			signers: value.signers.into_iter().map(|signer| signer.1.into()).collect(),
+		}
+	}
+}
+
+/// The `vstreams_` JSON-RPC API.
+#[rpc(server, namespace = "vstreams")]
+pub trait StreamsApi {
+	/// Witness an event of a stream (the default, zero, stream if omitted), signing it with the
+	/// key of the current node and gossipping the signature to all peers. If a conflict set is
+	/// given, the event is witnessed as a member of it, and at most one member gets validated.
+	/// Unsafe, as it lets anyone able to call it make this node vouch for arbitrary events.
+	#[method(name = "witnessEvent")]
+	async fn witness_event(
+		&self,
+		event_id: H256,
+		stream_id: Option<H256>,
+		conflict_set: Option<H256>,
+	) -> RpcResult<()>;
+
+	/// Get the current status of an event of a stream (the default, zero, stream if omitted).
+	#[method(name = "eventStatus")]
+	async fn event_status(
+		&self,
+		event_id: H256,
+		stream_id: Option<H256>,
+	) -> RpcResult<EventStatusResponse>;
+
+	/// Report a value of a feed for a round, signing it with the key of the current node and
+	/// gossipping it to all peers; once 2/3 of the authorities have reported, the median is stored
+	/// on-chain. Unsafe, as it lets anyone able to call it make this node vouch for arbitrary
+	/// values.
+	#[method(name = "reportFeedValue")]
+	async fn report_feed_value(&self, feed_id: H256, round: u64, value: i64) -> RpcResult<()>;
+
+	/// Get the median value of a feed stored on-chain for a round (the latest round if omitted).
+	#[method(name = "feedValue")]
+	async fn feed_value(
+		&self,
+		feed_id: H256,
+		round: Option<u64>,
+	) -> RpcResult<Option<FeedValueResponse>>;
+
+	/// Subscribe to the validated events of each finalized block, starting from `from_block` (or
+	/// the latest finalized block if omitted). Only the events of `stream_ids` are sent if given,
+	/// and blocks left without events are not sent at all if `skip_empty_blocks` is set. If a
+	/// block cannot be read (e.g. its body was pruned), the subscription is closed with an error.
+	#[subscription(
+		name = "subscribeValidatedEvents" => "validatedEvents",
+		unsubscribe = "unsubscribeValidatedEvents",
+		item = ValidatedBlock
+	)]
+	fn subscribe_validated_events(
+		&self,
+		from_block: Option<u32>,
+		stream_ids: Option<Vec<H256>>,
+		skip_empty_blocks: Option<bool>,
+	);
+}
+
+/// Implements the [StreamsApiServer] using the services started by
+/// [consensus_validated_streams::start].
+pub struct Streams {
+	event_witnesser: Arc<dyn EventWitnesserTrait + Send + Sync>,
+	event_validator: Arc<dyn EventValidatorTrait + Send + Sync>,
+	event_status: Arc<dyn EventStatusTrait + Send + Sync>,
+	executor: SubscriptionTaskExecutor,
+	deny_unsafe: DenyUnsafe,
+}
+
+impl Streams {
+	/// Create a new [Streams] instance.
+	pub fn new(
+		event_witnesser: Arc<dyn EventWitnesserTrait + Send + Sync>,
+		event_validator: Arc<dyn EventValidatorTrait + Send + Sync>,
+		event_status: Arc<dyn EventStatusTrait + Send + Sync>,
+		executor: SubscriptionTaskExecutor,
+		deny_unsafe: DenyUnsafe,
+	) -> Self {
+		Self { event_witnesser, event_validator, event_status, executor, deny_unsafe }
+	}
+}
+
+#[async_trait]
+impl StreamsApiServer for Streams {
+	async fn witness_event(
+		&self,
+		event_id: H256,
+		stream_id: Option<H256>,
+		conflict_set: Option<H256>,
+	) -> RpcResult<()> {
+		self.deny_unsafe.check_if_safe()?;
+		self.event_witnesser
+			.witness_event(stream_id.unwrap_or_default(), event_id, conflict_set)
+			.await
+			.map_err(rpc_error)
+	}
+
+	async fn event_status(
+		&self,
+		event_id: H256,
+		stream_id: Option<H256>,
+	) -> RpcResult<EventStatusResponse> {
+		self.event_status
+			.get_event_status(stream_id.unwrap_or_default(), event_id)
+			.await
+			.map(Into::into)
+			.map_err(rpc_error)
+	}
+
+	async fn report_feed_value(&self, feed_id: H256, round: u64, value: i64) -> RpcResult<()> {
+		self.deny_unsafe.check_if_safe()?;
+		self.event_witnesser
+			.report_feed_value(feed_id, round, value)
+			.await
+			.map_err(rpc_error)
+	}
+
+	async fn feed_value(
+		&self,
+		feed_id: H256,
+		round: Option<u64>,
+	) -> RpcResult<Option<FeedValueResponse>> {
+		self.event_status
+			.get_feed_value(feed_id, round)
+			.await
+			.map(|value| value.map(Into::into))
+			.map_err(rpc_error)
+	}
+
+	fn subscribe_validated_events(
+		&self,
+		mut sink: SubscriptionSink,
+		from_block: Option<u32>,
+		stream_ids: Option<Vec<H256>>,
+		skip_empty_blocks: Option<bool>,
+	) -> SubscriptionResult {
+		let event_validator = self.event_validator.clone();
+
+		let fut = async move {
+			let from_block = match from_block {
+				Some(from_block) => from_block,
+				None => event_validator.get_latest_finalized_block().await.unwrap_or_default(),
+			};
+			let blocks = validated_blocks(
+				event_validator,
+				from_block,
+				stream_ids.unwrap_or_default().into_iter().collect(),
+				skip_empty_blocks.unwrap_or_default(),
+			);
+
+			// Tell the client why the subscription ended, so that it never misses a block silently
+			match sink.pipe_from_try_stream(blocks.boxed()).await {
+				SubscriptionClosed::Success => {
+					sink.close(SubscriptionClosed::Success);
+				},
+				SubscriptionClosed::RemotePeerAborted => (),
+				SubscriptionClosed::Failed(error) => {
+					sink.close(error);
+				},
+			}
+		};
+
+		self.executor.spawn("vstreams-rpc-subscription", Some("rpc"), fut.boxed());
+		Ok(())
+	}
+}
+
+/// Streams the validated events of successive finalized blocks starting from `from_block`, keeping
+/// only the events of `stream_ids` unless it is empty. Ends after the first error.
+fn validated_blocks(
+	event_validator: Arc<dyn EventValidatorTrait + Send + Sync>,
+	from_block: u32,
+	stream_ids: HashSet<H256>,
+	skip_empty_blocks: bool,
+) -> impl Stream<Item = Result<ValidatedBlock, Error>> + Send {
+	stream::unfold((event_validator, Some(from_block)), |(event_validator, block_num)| async move {
+		let block_num = block_num?;
+		let result = event_validator.get_finalized_block_events(block_num).await;
+		if let Err(e) = &result {
+			log::warn!("Failed getting validated events of block {block_num}: {e}");
+		}
+		let next_block = block_num.checked_add(1).filter(|_| result.is_ok());
+		Some((result.map(|block| (block_num, block)), (event_validator, next_block)))
+	})
+	.filter_map(move |result| {
+		future::ready(match result {
+			Ok((block_num, mut block)) => {
+				if !stream_ids.is_empty() {
+					block.events.retain(|event| stream_ids.contains(&event.stream_id));
+				}
+				(!skip_empty_blocks || !block.events.is_empty())
+					.then(|| Ok(ValidatedBlock::new(block_num, block)))
+			},
+			Err(e) => Some(Err(e)),
+		})
+	})
+}
+
+fn rpc_error(error: Error) -> JsonRpseeError {
+	let code = match error {
+		Error::AlreadyValidated => ALREADY_VALIDATED_ERROR,
+		Error::NotAValidator => NOT_A_VALIDATOR_ERROR,
+		Error::ConflictSetResolved | Error::ConflictingVote(_) => CONFLICT_ERROR,
+		_ => OTHER_ERROR,
+	};
+	JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(code, error.to_string(), None::<()>)))
+}
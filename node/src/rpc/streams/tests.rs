@@ -0,0 +1,163 @@
+use super::{validated_blocks, Streams, StreamsApiServer, ValidatedBlock};
+use consensus_validated_streams::{
+	errors::Error,
+	proofs::WitnessedEvent,
+	traits::{
+		EventStatus, EventStatusTrait, EventValidatorTrait, EventWitnesserTrait, FeedValue,
+		FinalizedBlockEvents, ValidatedEventDetails,
+	},
+};
+use futures::StreamExt;
+use jsonrpsee::{core::async_trait, rpc_params};
+use sc_rpc_api::DenyUnsafe;
+use sp_core::{testing::TaskExecutor, H256};
+use std::{collections::HashSet, sync::Arc};
+
+/// A node which finalized the given blocks, and pruned all the ones after them.
+struct MockNode {
+	blocks: Vec<FinalizedBlockEvents>,
+}
+
+impl MockNode {
+	fn new() -> Self {
+		let event = |stream_id: u8, event_id: u8| ValidatedEventDetails {
+			stream_id: H256::repeat_byte(stream_id),
+			event_id: H256::repeat_byte(event_id),
+			conflict_set: None,
+			extrinsic_index: event_id.into(),
+			signers: vec![],
+		};
+		let block =
+			|hash: u8, events| FinalizedBlockEvents { block_hash: H256::repeat_byte(hash), events };
+		Self {
+			blocks: vec![
+				block(10, vec![event(1, 1), event(2, 2)]),
+				block(11, vec![]),
+				block(12, vec![event(2, 3)]),
+			],
+		}
+	}
+
+	fn validated_block(&self, block_num: u32, stream_id: Option<u8>) -> ValidatedBlock {
+		let mut block = self.blocks[block_num as usize].clone();
+		if let Some(stream_id) = stream_id {
+			block.events.retain(|event| event.stream_id == H256::repeat_byte(stream_id));
+		}
+		ValidatedBlock::new(block_num, block)
+	}
+}
+
+#[async_trait]
+impl EventValidatorTrait for MockNode {
+	async fn get_finalized_block_events(
+		&self,
+		block_num: u32,
+	) -> Result<FinalizedBlockEvents, Error> {
+		self.blocks
+			.get(block_num as usize)
+			.cloned()
+			.ok_or(Error::BlockUnavailable(block_num))
+	}
+
+	async fn get_latest_finalized_block(&self) -> Result<u32, Error> {
+		Ok(self.blocks.len() as u32 - 1)
+	}
+
+	async fn get_finalized_block_hash(&self, block_num: u32) -> Result<Option<H256>, Error> {
+		self.get_finalized_block_events(block_num)
+			.await
+			.map(|block| Some(block.block_hash))
+	}
+}
+
+#[async_trait]
+impl EventWitnesserTrait for MockNode {
+	async fn witness_event(&self, _: H256, _: H256, _: Option<H256>) -> Result<(), Error> {
+		Err(Error::NotAValidator)
+	}
+
+	async fn witness_events(&self, _: H256, _: Vec<H256>) -> Result<Vec<Result<(), Error>>, Error> {
+		Err(Error::NotAValidator)
+	}
+
+	async fn witness_signed_event(&self, _: WitnessedEvent) -> Result<(), Error> {
+		Err(Error::NotAValidator)
+	}
+
+	async fn report_feed_value(&self, _: H256, _: u64, _: i64) -> Result<(), Error> {
+		Err(Error::NotAValidator)
+	}
+}
+
+#[async_trait]
+impl EventStatusTrait for MockNode {
+	async fn get_event_status(&self, _: H256, _: H256) -> Result<EventStatus, Error> {
+		Err(Error::NotAValidator)
+	}
+
+	async fn get_feed_value(&self, _: H256, _: Option<u64>) -> Result<Option<FeedValue>, Error> {
+		Ok(None)
+	}
+}
+
+/// test that validated blocks are streamed until the first block which cannot be read, filtered
+/// like the GRPC ValidatedEvents stream
+#[tokio::test]
+async fn test_validated_blocks() {
+	let node = Arc::new(MockNode::new());
+
+	let blocks = validated_blocks(node.clone(), 0, HashSet::new(), false)
+		.collect::<Vec<_>>()
+		.await;
+	assert_eq!(blocks.len(), 4);
+	for (block_num, block) in blocks[..3].iter().enumerate() {
+		assert_eq!(block.as_ref().unwrap(), &node.validated_block(block_num as u32, None));
+	}
+	assert!(matches!(blocks[3], Err(Error::BlockUnavailable(3))));
+
+	// Blocks left without events of the requested streams are skipped
+	let stream_ids = HashSet::from([H256::repeat_byte(2)]);
+	let blocks = validated_blocks(node.clone(), 0, stream_ids.clone(), true)
+		.collect::<Vec<_>>()
+		.await;
+	assert_eq!(blocks.len(), 3);
+	assert_eq!(blocks[0].as_ref().unwrap(), &node.validated_block(0, Some(2)));
+	assert_eq!(blocks[1].as_ref().unwrap(), &node.validated_block(2, Some(2)));
+	assert!(matches!(blocks[2], Err(Error::BlockUnavailable(3))));
+
+	// ...But sent with no events otherwise
+	let blocks = validated_blocks(node.clone(), 1, stream_ids, false).collect::<Vec<_>>().await;
+	assert_eq!(blocks.len(), 3);
+	assert_eq!(blocks[0].as_ref().unwrap(), &node.validated_block(1, Some(2)));
+
+	let blocks = validated_blocks(node, 5, HashSet::new(), false).collect::<Vec<_>>().await;
+	assert!(matches!(blocks[..], [Err(Error::BlockUnavailable(5))]));
+}
+
+/// test that subscribers get the filtered blocks, and that the subscription is closed once a block
+/// cannot be read
+#[tokio::test]
+async fn test_subscribe_validated_events() {
+	let node = Arc::new(MockNode::new());
+	let module = Streams::new(
+		node.clone(),
+		node.clone(),
+		node.clone(),
+		Arc::new(TaskExecutor::new()),
+		DenyUnsafe::No,
+	)
+	.into_rpc();
+
+	let mut subscription = module
+		.subscribe(
+			"vstreams_subscribeValidatedEvents",
+			rpc_params![0, vec![H256::repeat_byte(2)], true],
+		)
+		.await
+		.unwrap();
+	let (block, _) = subscription.next::<ValidatedBlock>().await.unwrap().unwrap();
+	assert_eq!(block, node.validated_block(0, Some(2)));
+	let (block, _) = subscription.next::<ValidatedBlock>().await.unwrap().unwrap();
+	assert_eq!(block, node.validated_block(2, Some(2)));
+	assert!(subscription.next::<ValidatedBlock>().await.is_none());
+}
@@ -1,8 +1,12 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
 #[cfg(feature = "off-chain-proofs")]
+use consensus_validated_streams::events::MissingProofsReceiver;
+#[cfg(feature = "off-chain-proofs")]
 use consensus_validated_streams::ValidatedStreamsBlockImport;
 use consensus_validated_streams::{
-	proofs::OffchainStorageEventProofs, BlockStateCache, ValidatedStreamsNetworkConfiguration,
+	config::GossipTransport, events::missing_proofs_channel, gossip,
+	payloads::OffchainStorageEventPayloads, proofs::OffchainStorageEventProofs, BlockStateCache,
+	ValidatedStreamsNetworkConfiguration,
 };
 use lru::LruCache;
 use sc_client_api::{Backend, BlockBackend};
@@ -84,6 +88,7 @@ type FullPartialComponentsOther = (
 		AuraId,
 	>,
 	Box<dyn FnOnce(Arc<SyncingService<Block>>)>,
+	MissingProofsReceiver,
 	sc_consensus_grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
 	Option<Telemetry>,
 	Arc<OffchainStorageEventProofs<<FullBackend as Backend<Block>>::OffchainStorage>>,
@@ -156,11 +161,14 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 	#[cfg(not(feature = "off-chain-proofs"))]
 	let block_import = grandpa_block_import.clone();
 	#[cfg(feature = "off-chain-proofs")]
+	let (missing_proofs, missing_proofs_receiver) = missing_proofs_channel();
+	#[cfg(feature = "off-chain-proofs")]
 	let (block_import, provide_sync_service) = ValidatedStreamsBlockImport::new(
 		grandpa_block_import.clone(),
 		client.clone(),
 		event_proofs.clone(),
 		block_state.clone(),
+		missing_proofs,
 	);
 	let slot_duration = sc_consensus_aura::slot_duration(&*client)?;
 
@@ -201,6 +209,7 @@ pub fn new_partial(config: &Configuration) -> Result<FullPartialComponents, Serv
 		other: (
 			block_import,
 			Box::new(provide_sync_service),
+			missing_proofs_receiver,
 			grandpa_link,
 			telemetry,
 			event_proofs,
@@ -233,19 +242,25 @@ pub fn new_full(
 			other: (block_import, grandpa_link, mut telemetry, event_proofs, block_state),
 		#[cfg(feature = "off-chain-proofs")]
 			other:
-			(block_import, provide_sync_service, grandpa_link, mut telemetry, event_proofs, block_state),
+			(
+				block_import,
+				provide_sync_service,
+				missing_proofs,
+				grandpa_link,
+				mut telemetry,
+				event_proofs,
+				block_state,
+			),
 	} = new_partial(&config)?;
+	// Without the off-chain proofs block import, nothing reports missing proofs
+	#[cfg(not(feature = "off-chain-proofs"))]
+	let (_, missing_proofs) = missing_proofs_channel();
 
-	consensus_validated_streams::start(consensus_validated_streams::StartParams {
-		spawn_handle: task_manager.spawn_handle(),
-		event_proofs,
-		client: client.clone(),
-		keystore: keystore_container.keystore(),
-		transaction_pool: transaction_pool.clone(),
-		validated_streams_network_config,
-		network_configuration: config.network.clone(),
-		block_state,
-	})?;
+	let event_payloads = Arc::new(OffchainStorageEventPayloads::new(
+		backend
+			.offchain_storage()
+			.ok_or_else(|| ServiceError::Other("Offchain storage is required.".into()))?,
+	));
 
 	if let Some(url) = &config.keystore_remote {
 		match remote_keystore(url) {
@@ -265,6 +280,16 @@ pub fn new_full(
 		.network
 		.extra_sets
 		.push(sc_consensus_grandpa::grandpa_peers_set_config(grandpa_protocol_name.clone()));
+	let gossip_protocol_name = gossip::network::protocol_standard_name(
+		&client.block_hash(0).ok().flatten().expect("Genesis block exists; qed"),
+		config.chain_spec.fork_id(),
+	);
+	if validated_streams_network_config.gossip_transport == GossipTransport::Substrate {
+		config
+			.network
+			.extra_sets
+			.push(gossip::network::peers_set_config(gossip_protocol_name.clone()));
+	}
 	let warp_sync = Arc::new(sc_consensus_grandpa::warp_proof::NetworkProvider::new(
 		backend.clone(),
 		grandpa_link.shared_authority_set().clone(),
@@ -285,6 +310,23 @@ pub fn new_full(
 	#[cfg(feature = "off-chain-proofs")]
 	provide_sync_service(sync_service.clone());
 
+	let validated_streams =
+		consensus_validated_streams::start(consensus_validated_streams::StartParams {
+			spawn_handle: task_manager.spawn_handle(),
+			event_proofs,
+			event_payloads,
+			client: client.clone(),
+			keystore: keystore_container.keystore(),
+			transaction_pool: transaction_pool.clone(),
+			validated_streams_network_config,
+			network_configuration: config.network.clone(),
+			network: network.clone(),
+			sync_service: sync_service.clone(),
+			gossip_protocol_name,
+			missing_proofs,
+			block_state,
+		})?;
+
 	if config.offchain_worker.enabled {
 		sc_service::build_offchain_workers(
 			&config,
@@ -305,9 +347,14 @@ pub fn new_full(
 		let client = client.clone();
 		let pool = transaction_pool.clone();
 
-		Box::new(move |deny_unsafe, _| {
-			let deps =
-				crate::rpc::FullDeps { client: client.clone(), pool: pool.clone(), deny_unsafe };
+		Box::new(move |deny_unsafe, subscription_executor| {
+			let deps = crate::rpc::FullDeps {
+				client: client.clone(),
+				pool: pool.clone(),
+				deny_unsafe,
+				validated_streams: validated_streams.clone(),
+				subscription_executor,
+			};
 			crate::rpc::create_full(deps).map_err(Into::into)
 		})
 	};
@@ -2,7 +2,11 @@
 use super::*;
 use crate::{Config, Pallet as pallet_validated_streams};
 use frame_benchmarking::{benchmarks, BenchmarkError, Vec};
-use frame_support::{ensure, traits::ConstU32, BoundedBTreeMap, BoundedVec};
+use frame_support::{
+	ensure,
+	traits::{ConstU32, Get},
+	BoundedBTreeMap, BoundedVec,
+};
 use frame_system::{pallet_prelude::*, RawOrigin};
 use sp_core::{
 	crypto::key_types::AURA,
@@ -14,10 +18,64 @@ use sp_runtime::{app_crypto::RuntimePublic, RuntimeAppPublic};
 #[cfg(feature = "off-chain-proofs")]
 benchmarks! {
 	validate_event {
+		let stream_id = H256::default();
 		let event_id = H256::default();
-	}: _(RawOrigin::None, event_id, None)
+		// events in a conflict set read and write one more entry
+		let conflict_set = Some(H256::repeat_byte(1));
+	}: _(RawOrigin::None, stream_id, event_id, conflict_set, None)
+	verify {
+		assert!(pallet_validated_streams::<T>::is_event_valid(stream_id, event_id));
+	}
+	report_feed_value {
+		let n in 1 .. 32;
+		let feed_id = H256::default();
+		let round = 1;
+		let reports = (0..n)
+			.map(|i| {
+				let value = i as i64;
+				let key = sr25519_generate(AURA, None);
+				let signature =
+					sr25519_sign(AURA, &key, &feed_report_message(&feed_id, round, value)).unwrap();
+				(key, (value, signature))
+			})
+			.collect::<Vec<_>>();
+	}: {
+		// the generated keys are not authorities of the runtime, so they are looked up among the
+		// runtime's authorities followed by themselves, which costs at least as much as the call's
+		// own membership check
+		ensure!(
+			!FeedValues::<T>::contains_key(feed_id, round),
+			BenchmarkError::Stop("Already reported round")
+		);
+		let mut authorities = pallet_validated_streams::<T>::authority_keys().into_inner();
+		authorities.extend(reports.iter().map(|(key, _)| *key));
+		let mut values = Vec::new();
+		for (key, (value, signature)) in &reports {
+			ensure!(authorities.contains(key), BenchmarkError::Stop("Unrecognized Authority"));
+			ensure!(
+				key.verify(&feed_report_message(&feed_id, round, *value), signature),
+				BenchmarkError::Stop("Invalid proof")
+			);
+			values.push(*value);
+		}
+		let median = median(&mut values).ok_or(BenchmarkError::Stop("No reports"))?;
+		let signers: BoundedVec<Public, T::VSMaxAuthorities> = reports
+			.iter()
+			.map(|(key, _)| *key)
+			.collect::<Vec<_>>()
+			.try_into()
+			.map_err(|_| BenchmarkError::Stop("Too many reports"))?;
+		FeedValues::<T>::insert(feed_id, round, median);
+		FeedSigners::<T>::insert(feed_id, round, signers);
+		LatestFeedRounds::<T>::insert(feed_id, round);
+		pallet_validated_streams::<T>::deposit_event(Event::FeedValueReported {
+			feed_id,
+			round,
+			median,
+		});
+	}
 	verify {
-		assert!(pallet_validated_streams::<T>::is_event_valid(event_id));
+		assert_eq!(pallet_validated_streams::<T>::get_feed_value(feed_id, round), Some((n as i64 - 1) / 2));
 	}
 	impl_benchmark_test_suite!(
 		pallet_validated_streams,
@@ -28,62 +86,92 @@ benchmarks! {
 #[cfg(not(feature = "off-chain-proofs"))]
 benchmarks! {
 	on_chain_proofs {
+		let stream_id = H256::default();
 		let event_id = H256::default();
-		let event_hash = T::Hash::default();
-		// type ProofsMap= BoundedBTreeMap<sp_core::sr25519::Public, BoundedVec<u8, ConstU32<64>>,
-		// ConstU32<32>>;
-		let event_proofs = {
-			let mut proofs = BoundedBTreeMap::new();
-			for i in 0..32 {
-				let key = sr25519_generate(AURA, None);
-				let signature: BoundedVec<_, _> = sr25519_sign(AURA, &key, &event_id.as_bytes())
-					.unwrap()
-					.0
-					.to_vec()
-					.try_into()
-					.unwrap();
-				proofs.try_insert(key, signature).unwrap();
-			}
-			Some(proofs)
-		};
+		let message = witnessed_event_message(&stream_id, &event_id, None);
+		let authorities: BoundedVec<Public, T::VSMaxAuthorities> = (0..T::VSMaxAuthorities::get())
+			.map(|_| sr25519_generate(AURA, None))
+			.collect::<Vec<_>>()
+			.try_into()
+			.unwrap();
+		let signatures = authorities
+			.iter()
+			.map(|key| (*key, sr25519_sign(AURA, key, &message).unwrap()))
+			.collect();
+		let event_proofs = QuorumProof::<T::VSMaxAuthorities>::new(0, &authorities, &signatures);
 	}: {
-		// not going to use this one since it represent real authorities in the actual runtime
-		// just benchmark db read
-		if let Some(proofs) = event_proofs {
-			let authorities: Vec<Public> = T::authorities()
-				.into_iter()
-				.map(|id| Public::from_h256(H256::from_slice(id.to_raw_vec().as_slice())))
-				.collect();
-			let target = (2 * ((authorities.len() - 1) / 3) + 1) as u16;
+		// the generated keys are not authorities of the runtime, so check the proof against them
+		// instead of T::authorities()
+		if let Some(proof) = event_proofs {
 			ensure!(
-				!OnStreams::<T>::contains_key(event_hash),
+				!OnStreams::<T>::contains_key(stream_id, event_id),
 				BenchmarkError::Stop("Already validated event")
 			);
-			// worst case is finding all the keys in authorities!
-			for key in proofs.keys() {
-				if !proofs.contains_key(key) {
-					return Err(BenchmarkError::Stop("Unrecognized Authority"))
-				}
-			}
-			let mut proof_count = 0;
-			for (key, sig) in &proofs {
-				if let Some(signature) = Signature::from_slice(sig.as_slice()) {
-					ensure!(
-						key.verify(&event_id, &signature),
-						BenchmarkError::Stop("Invalid proof")
-					);
-					proof_count += 1;
-				} else {
-					return Err(BenchmarkError::Stop("Bad Signature"))
-				}
-			}
-			if proof_count < target {
-				return Err(BenchmarkError::Stop("not enough proofs"))
-			}
-			OnStreams::<T>::insert(event_hash, proofs);
+			let signers =
+				proof.signers(&authorities).ok_or(BenchmarkError::Stop("Unrecognized Authority"))?;
+			let target = authorities.len() * 2 / 3 + 1;
+			ensure!(signers.len() >= target, BenchmarkError::Stop("not enough proofs"));
+			ensure!(proof.verify(&authorities, &message), BenchmarkError::Stop("Invalid proof"));
+			let current_block = frame_system::Pallet::<T>::block_number();
+			AuthoritySets::<T>::insert(0, authorities);
+			OnStreams::<T>::insert(stream_id, event_id, (current_block, proof));
+			BlockEvents::<T>::insert(current_block, (stream_id, event_id), ());
 			pallet_validated_streams::<T>::deposit_event(Event::ValidatedEvent {
-				event_id: event_hash,
+				stream_id,
+				event_id,
+				conflict_set: None,
 			});
 		}
 	}
+	report_feed_value {
+		let n in 1 .. 32;
+		let feed_id = H256::default();
+		let round = 1;
+		let reports = (0..n)
+			.map(|i| {
+				let value = i as i64;
+				let key = sr25519_generate(AURA, None);
+				let signature =
+					sr25519_sign(AURA, &key, &feed_report_message(&feed_id, round, value)).unwrap();
+				(key, (value, signature))
+			})
+			.collect::<Vec<_>>();
+	}: {
+		// the generated keys are not authorities of the runtime, so they are looked up among the
+		// runtime's authorities followed by themselves, which costs at least as much as the call's
+		// own membership check
+		ensure!(
+			!FeedValues::<T>::contains_key(feed_id, round),
+			BenchmarkError::Stop("Already reported round")
+		);
+		let mut authorities = pallet_validated_streams::<T>::authority_keys().into_inner();
+		authorities.extend(reports.iter().map(|(key, _)| *key));
+		let mut values = Vec::new();
+		for (key, (value, signature)) in &reports {
+			ensure!(authorities.contains(key), BenchmarkError::Stop("Unrecognized Authority"));
+			ensure!(
+				key.verify(&feed_report_message(&feed_id, round, *value), signature),
+				BenchmarkError::Stop("Invalid proof")
+			);
+			values.push(*value);
+		}
+		let median = median(&mut values).ok_or(BenchmarkError::Stop("No reports"))?;
+		let signers: BoundedVec<Public, T::VSMaxAuthorities> = reports
+			.iter()
+			.map(|(key, _)| *key)
+			.collect::<Vec<_>>()
+			.try_into()
+			.map_err(|_| BenchmarkError::Stop("Too many reports"))?;
+		FeedValues::<T>::insert(feed_id, round, median);
+		FeedSigners::<T>::insert(feed_id, round, signers);
+		LatestFeedRounds::<T>::insert(feed_id, round);
+		pallet_validated_streams::<T>::deposit_event(Event::FeedValueReported {
+			feed_id,
+			round,
+			median,
+		});
+	}
+	verify {
+		assert_eq!(pallet_validated_streams::<T>::get_feed_value(feed_id, round), Some((n as i64 - 1) / 2));
+	}
 }
@@ -6,6 +6,7 @@
 //!
 //! ### Dispatchable Functions
 //! * [validate_event](pallet/struct.Pallet.html#method.validate_event)
+//! * [report_feed_value](pallet/struct.Pallet.html#method.report_feed_value)
 // Re-export pallet items so that they can be accessed from the crate namespace.
 pub use pallet::*;
 #[cfg(test)]
@@ -17,9 +18,187 @@ pub mod mock;
 pub mod weights;
 pub use weights::*;
 
+pub mod migrations;
+
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+/// Returns the message that authorities sign when witnessing an event: the 32 bytes of the stream
+/// id followed by the 32 bytes of the event id, and, for members of a conflict set, the 32 bytes of
+/// the conflict set id. Binding the stream id prevents a proof for an event in one stream from
+/// being reused for the same event id in another stream; binding the conflict set prevents a proof
+/// from being used to validate the event outside of its conflict set.
+pub fn witnessed_event_message(
+	stream_id: &sp_core::H256,
+	event_id: &sp_core::H256,
+	conflict_set: Option<&sp_core::H256>,
+) -> sp_std::vec::Vec<u8> {
+	let mut message = sp_std::vec::Vec::with_capacity(96);
+	message.extend_from_slice(stream_id.as_bytes());
+	message.extend_from_slice(event_id.as_bytes());
+	if let Some(conflict_set) = conflict_set {
+		message.extend_from_slice(conflict_set.as_bytes());
+	}
+	message
+}
+
+/// A proof that an authority witnessed an event: the sr25519 signature of the
+/// [witnessed_event_message] by the authority's key. SCALE-encoded, the same proof is gossiped
+/// between validators, stored by them, and can be decoded and checked by light clients and
+/// external tools alike. Extrinsics carry the quorum's signatures as a [QuorumProof] instead.
+#[derive(
+	Clone,
+	PartialEq,
+	Eq,
+	codec::Encode,
+	codec::Decode,
+	codec::MaxEncodedLen,
+	scale_info::TypeInfo,
+	sp_runtime::RuntimeDebug,
+)]
+pub struct WitnessProof {
+	/// The id of the stream (namespace) the event belongs to
+	pub stream_id: sp_core::H256,
+	/// The id/hash of the event
+	pub event_id: sp_core::H256,
+	/// The conflict set the event was witnessed as a member of, if any
+	pub conflict_set: Option<sp_core::H256>,
+	/// The key of the authority which witnessed the event
+	pub signer: sp_core::sr25519::Public,
+	/// The signature of the [witnessed_event_message] by the signer
+	pub signature: sp_core::sr25519::Signature,
+}
+
+impl WitnessProof {
+	/// Returns the message signed by the proof's signer.
+	pub fn message(&self) -> sp_std::vec::Vec<u8> {
+		witnessed_event_message(&self.stream_id, &self.event_id, self.conflict_set.as_ref())
+	}
+
+	/// Checks that the signature was produced by the signer. Whether the signer is an authority is
+	/// left to the caller.
+	pub fn verify(&self) -> bool {
+		sp_io::crypto::sr25519_verify(&self.signature, &self.message(), &self.signer)
+	}
+}
+
+/// The proofs of a quorum of authorities that an event was witnessed, in the compact form kept
+/// on-chain: a bitfield of the signers' indices in the authority set, followed by their signatures
+/// in the order of those indices. Unlike a map from public keys to signatures, the keys are not
+/// repeated for every event, only one bit per authority. An aggregate signature would keep the
+/// same bitfield and replace the list of signatures with the aggregate.
+#[derive(
+	frame_support::CloneNoBound,
+	frame_support::PartialEqNoBound,
+	frame_support::EqNoBound,
+	codec::Encode,
+	codec::Decode,
+	codec::MaxEncodedLen,
+	scale_info::TypeInfo,
+	frame_support::RuntimeDebugNoBound,
+)]
+#[scale_info(skip_type_params(MaxAuthorities))]
+#[codec(mel_bound())]
+pub struct QuorumProof<MaxAuthorities: sp_core::Get<u32>> {
+	/// The index of the authority set the bitfield refers to, as recorded in the pallet's
+	/// `AuthoritySets`; proofs built for another set than the current one are rejected
+	pub authority_set: u32,
+	/// Bit `i % 8` of byte `i / 8` is set if the `i`-th authority signed the event
+	pub signers: frame_support::BoundedVec<u8, MaxAuthorities>,
+	/// The signatures of the signers, ordered by their index in the authority set
+	pub signatures: frame_support::BoundedVec<sp_core::sr25519::Signature, MaxAuthorities>,
+}
+
+impl<MaxAuthorities: sp_core::Get<u32>> QuorumProof<MaxAuthorities> {
+	/// Builds a quorum proof from the signatures of an event, given the authority set they are
+	/// checked against and its index. Fails if one of the signers is not in the authority set.
+	pub fn new(
+		authority_set: u32,
+		authorities: &[sp_core::sr25519::Public],
+		proofs: &sp_std::collections::btree_map::BTreeMap<
+			sp_core::sr25519::Public,
+			sp_core::sr25519::Signature,
+		>,
+	) -> Option<Self> {
+		let mut signers = sp_std::vec![0u8; (authorities.len() + 7) / 8];
+		let mut signatures = sp_std::vec::Vec::with_capacity(proofs.len());
+		for (index, authority) in authorities.iter().enumerate() {
+			if let Some(signature) = proofs.get(authority) {
+				signers[index / 8] |= 1 << (index % 8);
+				signatures.push(signature.clone());
+			}
+		}
+		if signatures.len() != proofs.len() {
+			return None
+		}
+		Some(Self {
+			authority_set,
+			signers: signers.try_into().ok()?,
+			signatures: signatures.try_into().ok()?,
+		})
+	}
+
+	/// Returns the signers of the proof, given the authority set it was built for. Fails if the
+	/// bitfield does not fit the authority set or does not match the number of signatures.
+	pub fn signers(
+		&self,
+		authorities: &[sp_core::sr25519::Public],
+	) -> Option<sp_std::vec::Vec<sp_core::sr25519::Public>> {
+		if self.signers.len() != (authorities.len() + 7) / 8 {
+			return None
+		}
+		let signers: sp_std::vec::Vec<_> = authorities
+			.iter()
+			.enumerate()
+			.filter(|(index, _)| self.signers[index / 8] & (1 << (index % 8)) != 0)
+			.map(|(_, authority)| *authority)
+			.collect();
+		// also rejects bits set past the last authority
+		let bits = self.signers.iter().map(|byte| byte.count_ones() as usize).sum::<usize>();
+		if signers.len() != bits || signers.len() != self.signatures.len() {
+			return None
+		}
+		Some(signers)
+	}
+
+	/// Checks that every signature was produced by its signer over the given message. Whether
+	/// there are enough signers is left to the caller.
+	pub fn verify(&self, authorities: &[sp_core::sr25519::Public], message: &[u8]) -> bool {
+		self.signers(authorities).map_or(false, |signers| {
+			signers.iter().zip(self.signatures.iter()).all(|(signer, signature)| {
+				sp_io::crypto::sr25519_verify(signature, message, signer)
+			})
+		})
+	}
+}
+
+/// Returns the message that authorities sign when reporting a value of a feed: the 32 bytes of
+/// the feed id, followed by the round and the value, both as 8 little-endian bytes. At 48 bytes,
+/// it cannot be mistaken for a [witnessed_event_message].
+pub fn feed_report_message(
+	feed_id: &sp_core::H256,
+	round: u64,
+	value: i64,
+) -> sp_std::vec::Vec<u8> {
+	let mut message = sp_std::vec::Vec::with_capacity(48);
+	message.extend_from_slice(feed_id.as_bytes());
+	message.extend_from_slice(&round.to_le_bytes());
+	message.extend_from_slice(&value.to_le_bytes());
+	message
+}
+
+/// Returns the median of the reported values; for an even number of values, the lower of the two
+/// middle ones, so that the result is always one of the reported values. Since more than 2/3 of
+/// the reports come from honest authorities, the median is always within the range of the honest
+/// values.
+pub fn median(values: &mut [i64]) -> Option<i64> {
+	if values.is_empty() {
+		return None
+	}
+	values.sort_unstable();
+	Some(values[(values.len() - 1) / 2])
+}
+
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
@@ -33,13 +212,15 @@ pub mod pallet {
 		sr25519::{Public, Signature},
 		H256,
 	};
-	#[cfg(not(feature = "off-chain-proofs"))]
-	use sp_runtime::app_crypto::RuntimePublic;
 	pub use sp_runtime::traits::Extrinsic;
-	use sp_runtime::RuntimeAppPublic;
+	use sp_runtime::{app_crypto::RuntimePublic, traits::NumberFor, RuntimeAppPublic};
 	use sp_std::{collections::btree_map::BTreeMap, vec::Vec};
 
+	/// The version of the pallet's storage layout; see [crate::migrations].
+	pub const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+
 	#[pallet::pallet]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	pub struct Pallet<T>(_);
 
 	#[pallet::config]
@@ -61,96 +242,288 @@ pub mod pallet {
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
 		/// An Event has been validated
-		ValidatedEvent { event_id: H256 },
+		ValidatedEvent { stream_id: H256, event_id: H256, conflict_set: Option<H256> },
+		/// The value of a feed for a round has been agreed on
+		FeedValueReported { feed_id: H256, round: u64, median: i64 },
 	}
 	#[pallet::error]
 	pub enum Error<T> {
-		/// The event was already found in the Streams StorageMap which means its already validated
+		/// The event was already found in the Streams (or OnStreams) StorageMap which means its
+		/// already validated
 		AlreadyValidated,
+		/// Another member of the event's conflict set was already validated
+		ConflictSetResolved,
+		/// A value was already reported for this round of the feed
+		FeedRoundReported,
 		BadSignature,
 		InvalidProof,
 		NoProofs,
 		NotEnoughProofs,
 		UnrecognizedAuthority,
+		/// The proofs were built for an authority set other than the current one
+		StaleAuthoritySet,
 	}
 
-	type ProofsMap<T> = BoundedBTreeMap<Public, Signature, <T as Config>::VSMaxAuthorities>;
+	/// The proofs of an event, as submitted and stored on-chain.
+	pub type QuorumProofOf<T> = QuorumProof<<T as Config>::VSMaxAuthorities>;
 
+	/// The values reported by each authority for a round of a feed, along with their signatures.
+	pub type FeedReportsMap<T> =
+		BoundedBTreeMap<Public, (i64, Signature), <T as Config>::VSMaxAuthorities>;
+
+	/// The block number at which each event was validated, keyed by stream id and event id. When
+	/// proofs are kept on-chain, [OnStreams] holds the block number instead.
 	#[cfg(feature = "off-chain-proofs")]
 	#[pallet::storage]
-	pub(super) type Streams<T: Config> = StorageMap<_, Blake2_128Concat, H256, T::BlockNumber>;
+	pub(super) type Streams<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, H256, Blake2_128Concat, H256, T::BlockNumber>;
+
+	/// The events validated in each block, keyed by block number and (stream id, event id), so
+	/// that they can be listed without iterating over all the events ever validated.
+	#[pallet::storage]
+	pub(super) type BlockEvents<T: Config> =
+		StorageDoubleMap<_, Twox64Concat, T::BlockNumber, Blake2_128Concat, (H256, H256), ()>;
+
+	/// The event validated in each conflict set, keyed by stream id and conflict set id. Conflict
+	/// sets group mutually exclusive events, of which at most one may be validated.
+	#[pallet::storage]
+	pub(super) type ConflictSets<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, H256, Blake2_128Concat, H256, H256>;
+
+	/// The median value reported for each round of a feed, keyed by feed id and round.
+	#[pallet::storage]
+	pub(super) type FeedValues<T: Config> =
+		StorageDoubleMap<_, Blake2_128Concat, H256, Twox64Concat, u64, i64>;
+
+	/// The authorities whose reports were used to compute each feed value, keyed by feed id and
+	/// round.
+	#[pallet::storage]
+	pub(super) type FeedSigners<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		H256,
+		Twox64Concat,
+		u64,
+		BoundedVec<Public, T::VSMaxAuthorities>,
+	>;
 
+	/// The latest round for which a value was reported, keyed by feed id.
+	#[pallet::storage]
+	pub(super) type LatestFeedRounds<T: Config> = StorageMap<_, Blake2_128Concat, H256, u64>;
+
+	/// The block number at which each event was validated and the proofs it was validated with
+	/// (which refer to an authority set in [AuthoritySets]), keyed by stream id and event id.
+	#[cfg(not(feature = "off-chain-proofs"))]
+	#[pallet::storage]
+	pub(super) type OnStreams<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		H256,
+		Blake2_128Concat,
+		H256,
+		(T::BlockNumber, QuorumProofOf<T>),
+	>;
+
+	/// The authority sets events were validated under, keyed by index, so that the signers of
+	/// the [QuorumProof]-s in [OnStreams] can be recovered after the authorities change.
 	#[cfg(not(feature = "off-chain-proofs"))]
 	#[pallet::storage]
-	pub(super) type OnStreams<T: Config> = StorageMap<_, Blake2_128Concat, H256, ProofsMap<T>>;
+	pub(super) type AuthoritySets<T: Config> =
+		StorageMap<_, Twox64Concat, u32, BoundedVec<Public, T::VSMaxAuthorities>>;
+
+	/// The index of the latest authority set recorded in [AuthoritySets].
+	#[cfg(not(feature = "off-chain-proofs"))]
+	#[pallet::storage]
+	pub(super) type CurrentAuthoritySet<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Continues a storage migration started by [crate::migrations::MigrateToV1], if any.
+		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+			crate::migrations::migrate_step::<T>()
+		}
+	}
 
 	#[pallet::call]
 	impl<T: Config> Pallet<T> {
-		/// Used to validate an event.
+		/// Used to validate an event within a stream. Streams are namespaces which allow unrelated
+		/// applications to share a chain; event ids only need to be unique within their stream.
+		/// Events may optionally be members of a conflict set, of which at most one event may
+		/// ever be validated.
 		/// Checks if the event has already been validated.
 		/// If so, it raise an `AlreadyValidated` event.
+		/// If another member of its conflict set was validated, it raises `ConflictSetResolved`.
 		/// If not, it inserts the event into storage and emits a `ValidatedEvent` event.
 		#[pallet::call_index(0)]
 		#[pallet::weight(T::WeightInfo::validate_event())]
 		pub fn validate_event(
 			origin: OriginFor<T>,
+			stream_id: H256,
 			event_id: H256,
-			proofs: Option<ProofsMap<T>>,
+			conflict_set: Option<H256>,
+			proofs: Option<QuorumProofOf<T>>,
 		) -> DispatchResult {
 			// indirection because pallet::call does not support cfg feature macro yet
-			Pallet::<T>::validate_event_impl(origin, event_id, proofs)
+			Pallet::<T>::validate_event_impl(origin, stream_id, event_id, conflict_set, proofs)
+		}
+
+		/// Used to record the value of a feed (e.g. a price or a temperature) for a round.
+		/// Unlike events, feed values are always verified on-chain: every report is checked
+		/// against its authority's signature, and at least 2/3 of the authorities must have
+		/// reported. The median of the reported values is stored together with the signers, and a
+		/// `FeedValueReported` event is emitted. Each round of a feed can be reported only once.
+		#[pallet::call_index(1)]
+		#[pallet::weight(T::WeightInfo::report_feed_value(reports.len() as u32))]
+		pub fn report_feed_value(
+			_origin: OriginFor<T>,
+			feed_id: H256,
+			round: u64,
+			reports: FeedReportsMap<T>,
+		) -> DispatchResult {
+			ensure!(!FeedValues::<T>::contains_key(feed_id, round), Error::<T>::FeedRoundReported);
+			ensure!(!reports.is_empty(), Error::<T>::NoProofs);
+
+			let authorities = Self::authority_keys();
+			let target = authorities.len() * 2 / 3 + 1;
+
+			let mut values = Vec::with_capacity(reports.len());
+			for (key, (value, signature)) in &reports {
+				ensure!(authorities.contains(key), Error::<T>::UnrecognizedAuthority);
+				let message = feed_report_message(&feed_id, round, *value);
+				ensure!(key.verify(&message, signature), Error::<T>::InvalidProof);
+				values.push(*value);
+			}
+			ensure!(values.len() >= target, Error::<T>::NotEnoughProofs);
+
+			let median = median(&mut values).ok_or(Error::<T>::NoProofs)?;
+			let signers: BoundedVec<_, _> = reports
+				.into_inner()
+				.into_keys()
+				.collect::<Vec<_>>()
+				.try_into()
+				.map_err(|_| Error::<T>::NotEnoughProofs)?;
+
+			FeedValues::<T>::insert(feed_id, round, median);
+			FeedSigners::<T>::insert(feed_id, round, signers);
+			if LatestFeedRounds::<T>::get(feed_id).map_or(true, |latest| round > latest) {
+				LatestFeedRounds::<T>::insert(feed_id, round);
+			}
+			Self::deposit_event(Event::FeedValueReported { feed_id, round, median });
+			Ok(())
 		}
 	}
 	impl<T: Config> Pallet<T> {
 		#[cfg(feature = "off-chain-proofs")]
 		pub fn validate_event_impl(
 			_origin: OriginFor<T>,
+			stream_id: H256,
 			event_id: H256,
-			_proofs: Option<ProofsMap<T>>,
+			conflict_set: Option<H256>,
+			_proofs: Option<QuorumProofOf<T>>,
 		) -> DispatchResult {
 			let current_block = <frame_system::Pallet<T>>::block_number();
-			ensure!(!Streams::<T>::contains_key(event_id), Error::<T>::AlreadyValidated);
-			Streams::<T>::insert(event_id, current_block);
-			Self::deposit_event(Event::ValidatedEvent { event_id });
+			ensure!(!Self::is_event_valid(stream_id, event_id), Error::<T>::AlreadyValidated);
+			Self::ensure_conflict_set_open(stream_id, conflict_set)?;
+			Streams::<T>::insert(stream_id, event_id, current_block);
+			BlockEvents::<T>::insert(current_block, (stream_id, event_id), ());
+			Self::resolve_conflict_set(stream_id, event_id, conflict_set);
+			Self::deposit_event(Event::ValidatedEvent { stream_id, event_id, conflict_set });
 			Ok(())
 		}
 		#[cfg(not(feature = "off-chain-proofs"))]
 		pub fn validate_event_impl(
 			_origin: OriginFor<T>,
+			stream_id: H256,
 			event_id: H256,
-			event_proofs: Option<ProofsMap<T>>,
+			conflict_set: Option<H256>,
+			event_proofs: Option<QuorumProofOf<T>>,
 		) -> DispatchResult {
-			let authorities: Vec<Public> = T::authorities()
-				.into_iter()
-				.map(|id| Public::from_h256(H256::from_slice(id.to_raw_vec().as_slice())))
-				.collect();
-			if let Some(proofs) = event_proofs {
-				ensure!(!OnStreams::<T>::contains_key(event_id), Error::<T>::AlreadyValidated);
-				for key in proofs.keys() {
-					if !authorities.contains(key) {
-						return Err(Error::<T>::UnrecognizedAuthority.into())
-					}
-				}
-
-				let total = authorities.len();
-				let target = (total * 2 / 3 + 1) as u16;
-				let mut proof_count = 0;
-				for (key, signature) in &proofs {
-					ensure!(key.verify(&event_id, signature), Error::<T>::InvalidProof);
-					proof_count += 1;
-				}
+			let authorities = Self::authority_keys();
+			if let Some(proof) = event_proofs {
+				ensure!(!Self::is_event_valid(stream_id, event_id), Error::<T>::AlreadyValidated);
+				Self::ensure_conflict_set_open(stream_id, conflict_set)?;
+				ensure!(
+					proof.authority_set == Self::authority_set_index(&authorities),
+					Error::<T>::StaleAuthoritySet
+				);
+				let signers =
+					proof.signers(&authorities).ok_or(Error::<T>::UnrecognizedAuthority)?;
 
-				if proof_count < target {
+				let target = authorities.len() * 2 / 3 + 1;
+				if signers.len() < target {
 					return Err(Error::<T>::NotEnoughProofs.into())
 				}
 
-				OnStreams::<T>::insert(event_id, proofs);
-				Self::deposit_event(Event::ValidatedEvent { event_id });
+				let message = witnessed_event_message(&stream_id, &event_id, conflict_set.as_ref());
+				ensure!(proof.verify(&authorities, &message), Error::<T>::InvalidProof);
+
+				let current_block = <frame_system::Pallet<T>>::block_number();
+				Self::record_authority_set(authorities);
+				OnStreams::<T>::insert(stream_id, event_id, (current_block, proof));
+				BlockEvents::<T>::insert(current_block, (stream_id, event_id), ());
+				Self::resolve_conflict_set(stream_id, event_id, conflict_set);
+				Self::deposit_event(Event::ValidatedEvent { stream_id, event_id, conflict_set });
 				Ok(())
 			} else {
 				Err(Error::<T>::NoProofs.into())
 			}
 		}
+
+		/// Returns the index the given authority set has (or will be recorded under) in
+		/// [AuthoritySets]: the latest one if the set is unchanged, or the next one otherwise.
+		#[cfg(not(feature = "off-chain-proofs"))]
+		pub(crate) fn authority_set_index(authorities: &[Public]) -> u32 {
+			let index = CurrentAuthoritySet::<T>::get();
+			match AuthoritySets::<T>::get(index) {
+				Some(set) if set.as_slice() == authorities => index,
+				Some(_) => index.saturating_add(1),
+				None => index,
+			}
+		}
+		/// Authority sets are only recorded when proofs are kept on-chain.
+		#[cfg(feature = "off-chain-proofs")]
+		pub(crate) fn authority_set_index(_authorities: &[Public]) -> u32 {
+			0
+		}
+
+		/// Records the given authority set in [AuthoritySets] as a new set, if it differs from the
+		/// latest one.
+		#[cfg(not(feature = "off-chain-proofs"))]
+		pub(crate) fn record_authority_set(authorities: BoundedVec<Public, T::VSMaxAuthorities>) {
+			let index = Self::authority_set_index(&authorities);
+			if !AuthoritySets::<T>::contains_key(index) {
+				AuthoritySets::<T>::insert(index, authorities);
+				CurrentAuthoritySet::<T>::put(index);
+			}
+		}
+
+		/// Returns the sr25519 keys of the current authorities.
+		pub(crate) fn authority_keys() -> BoundedVec<Public, T::VSMaxAuthorities> {
+			BoundedVec::truncate_from(
+				T::authorities()
+					.into_iter()
+					.map(|id| Public::from_h256(H256::from_slice(id.to_raw_vec().as_slice())))
+					.collect(),
+			)
+		}
+
+		/// Fails with `ConflictSetResolved` if a member of the given conflict set was validated.
+		fn ensure_conflict_set_open(stream_id: H256, conflict_set: Option<H256>) -> DispatchResult {
+			if let Some(conflict_set) = conflict_set {
+				ensure!(
+					!ConflictSets::<T>::contains_key(stream_id, conflict_set),
+					Error::<T>::ConflictSetResolved
+				);
+			}
+			Ok(())
+		}
+
+		/// Records the event as the validated member of its conflict set, if it has one.
+		fn resolve_conflict_set(stream_id: H256, event_id: H256, conflict_set: Option<H256>) {
+			if let Some(conflict_set) = conflict_set {
+				ConflictSets::<T>::insert(stream_id, conflict_set, event_id);
+			}
+		}
 	}
 	#[pallet::validate_unsigned]
 	impl<T: Config> ValidateUnsigned for Pallet<T> {
@@ -160,13 +533,37 @@ pub mod pallet {
 				Err(TransactionValidityError::Invalid(InvalidTransaction::Call))
 			} else {
 				match call {
-					Self::Call::validate_event { event_id: call_data, proofs: _ } =>
-						if Self::is_event_valid(*call_data) {
+					Self::Call::validate_event { stream_id, event_id, conflict_set, proofs } => {
+						// Proofs built for a previous authority set are dropped from the pool, so
+						// that the node builds them anew from the signatures of the current one
+						let stale_proofs = proofs.as_ref().map_or(false, |proof| {
+							proof.authority_set !=
+								Self::authority_set_index(&Self::authority_keys())
+						});
+						if stale_proofs ||
+							Self::is_event_valid(*stream_id, *event_id) ||
+							conflict_set.map_or(false, |conflict_set| {
+								ConflictSets::<T>::contains_key(stream_id, conflict_set)
+							}) {
+							Err(TransactionValidityError::Invalid(InvalidTransaction::Stale))
+						} else {
+							let mut transaction =
+								ValidTransaction::with_tag_prefix("validated_streams")
+									.and_provides(call.encode())
+									.and_provides((*stream_id, *event_id));
+							// Members of the same conflict set exclude each other in the pool
+							if let Some(conflict_set) = conflict_set {
+								transaction = transaction.and_provides((*stream_id, *conflict_set));
+							}
+							transaction.propagate(false).build()
+						}
+					},
+					Self::Call::report_feed_value { feed_id, round, reports: _ } =>
+						if FeedValues::<T>::contains_key(feed_id, round) {
 							Err(TransactionValidityError::Invalid(InvalidTransaction::Stale))
 						} else {
-							ValidTransaction::with_tag_prefix("validated_streams")
-								.and_provides(call.encode())
-								.and_provides(*call_data)
+							ValidTransaction::with_tag_prefix("validated_streams_feeds")
+								.and_provides((*feed_id, *round))
 								.propagate(false)
 								.build()
 						},
@@ -175,45 +572,133 @@ pub mod pallet {
 			}
 		}
 	}
-	#[cfg(feature = "off-chain-proofs")]
 	impl<T: Config> Pallet<T> {
-		/// Returns a vector of all events validated so far.
-		pub fn get_all_events() -> Vec<H256> {
-			Streams::<T>::iter().map(|(k, _)| k).collect()
+		/// Returns all events validated in a particular block, as (stream id, event id) pairs.
+		pub fn get_block_events(block_number: T::BlockNumber) -> Vec<(H256, H256)> {
+			BlockEvents::<T>::iter_key_prefix(block_number).collect()
+		}
+		/// Returns the event validated in a conflict set, if any.
+		pub fn get_conflict_set_event(stream_id: H256, conflict_set: H256) -> Option<H256> {
+			ConflictSets::<T>::get(stream_id, conflict_set)
+		}
+		/// Returns the median value reported for a round of a feed, if any.
+		pub fn get_feed_value(feed_id: H256, round: u64) -> Option<i64> {
+			FeedValues::<T>::get(feed_id, round)
+		}
+		/// Returns the authorities whose reports were used to compute a feed value.
+		pub fn get_feed_signers(feed_id: H256, round: u64) -> Vec<Public> {
+			FeedSigners::<T>::get(feed_id, round)
+				.map(|signers| signers.into_inner())
+				.unwrap_or_default()
+		}
+		/// Returns the latest round of a feed and its value, if any round was reported.
+		pub fn get_latest_feed_value(feed_id: H256) -> Option<(u64, i64)> {
+			let round = LatestFeedRounds::<T>::get(feed_id)?;
+			Some((round, FeedValues::<T>::get(feed_id, round)?))
+		}
+		/// Builds the [QuorumProof] of an event from its signatures, against the current
+		/// authorities. Fails if one of the signers is not a current authority.
+		pub fn quorum_proof(proofs: &BTreeMap<Public, Signature>) -> Option<QuorumProofOf<T>> {
+			let authorities = Self::authority_keys();
+			QuorumProof::new(Self::authority_set_index(&authorities), &authorities, proofs)
 		}
-		/// Returns all events validated in a particular block.
-		pub fn get_block_events(block_number: T::BlockNumber) -> Vec<H256> {
+	}
+	#[cfg(feature = "off-chain-proofs")]
+	impl<T: Config> Pallet<T> {
+		/// Returns a vector of all events validated so far, as (stream id, event id) pairs.
+		pub fn get_all_events() -> Vec<(H256, H256)> {
 			Streams::<T>::iter()
-				.filter(|(_, bn)| *bn == block_number)
-				.map(|(k, _)| k)
+				.map(|(stream_id, event_id, _)| (stream_id, event_id))
 				.collect()
 		}
 		/// Returns whether an event has been validated by validate_event.
-		pub fn is_event_valid(event_id: H256) -> bool {
-			Streams::<T>::contains_key(event_id)
+		pub fn is_event_valid(stream_id: H256, event_id: H256) -> bool {
+			Streams::<T>::contains_key(stream_id, event_id) ||
+				crate::migrations::is_pending_event::<T>(stream_id, event_id)
+		}
+		/// Returns the number of the block in which an event was validated, if it was.
+		pub fn get_event_block(stream_id: H256, event_id: H256) -> Option<T::BlockNumber> {
+			Streams::<T>::get(stream_id, event_id)
+		}
+		/// Returns the authorities whose proofs were used to validate an event. Always empty, as
+		/// proofs are kept off-chain.
+		pub fn get_event_signers(_stream_id: H256, _event_id: H256) -> Vec<Public> {
+			Vec::new()
 		}
 	}
 	#[cfg(not(feature = "off-chain-proofs"))]
 	impl<T: Config> Pallet<T> {
-		/// This function is used to get all events from the Streams StorageMap.
-		pub fn get_all_events() -> Vec<H256> {
-			OnStreams::<T>::iter().map(|(k, _)| k).collect()
+		/// This function is used to get all events from the OnStreams StorageMap, as (stream id,
+		/// event id) pairs.
+		pub fn get_all_events() -> Vec<(H256, H256)> {
+			OnStreams::<T>::iter()
+				.map(|(stream_id, event_id, _)| (stream_id, event_id))
+				.collect()
 		}
 		/// Returns whether an event has been validated by validate_event.
-		pub fn is_event_valid(event_id: H256) -> bool {
-			OnStreams::<T>::contains_key(event_id)
+		pub fn is_event_valid(stream_id: H256, event_id: H256) -> bool {
+			OnStreams::<T>::contains_key(stream_id, event_id) ||
+				crate::migrations::is_pending_event::<T>(stream_id, event_id)
+		}
+		/// Returns the number of the block in which an event was validated, if it was.
+		pub fn get_event_block(stream_id: H256, event_id: H256) -> Option<T::BlockNumber> {
+			OnStreams::<T>::get(stream_id, event_id).map(|(block_number, _)| block_number)
+		}
+		/// Returns the authorities whose proofs were used to validate an event.
+		pub fn get_event_signers(stream_id: H256, event_id: H256) -> Vec<Public> {
+			OnStreams::<T>::get(stream_id, event_id)
+				.and_then(|(_, proof)| {
+					proof.signers(&AuthoritySets::<T>::get(proof.authority_set)?)
+				})
+				.unwrap_or_default()
 		}
 	}
 	sp_api::decl_runtime_apis! {
+		/// Version 2 added streams, conflict sets and feeds.
+		#[api_version(2)]
 		pub trait ValidatedStreamsApi
 		{
-			/// Get event ids from a vector of extrinsics.
-			/// Meant to be used to get a list of all events present in a given block.
+			/// Get event ids from a vector of extrinsics; all events were in the default stream.
+			#[changed_in(2)]
 			#[allow(clippy::ptr_arg)]
 			fn get_extrinsic_ids(extrinsics: &Vec<Block::Extrinsic>) -> Vec<H256>;
-			/// Create a new extrinsic for a given event id.
+			/// Get (stream id, event id) pairs from a vector of extrinsics.
+			/// Meant to be used to get a list of all events present in a given block.
+			#[allow(clippy::ptr_arg)]
+			fn get_extrinsic_ids(extrinsics: &Vec<Block::Extrinsic>) -> Vec<(H256, H256)>;
+			/// Get events from a vector of extrinsics, as (extrinsic index, stream id, event id,
+			/// conflict set id).
+			#[allow(clippy::ptr_arg)]
+			fn get_extrinsic_events(
+				extrinsics: &Vec<Block::Extrinsic>,
+			) -> Vec<(u32, H256, H256, Option<H256>)>;
+			/// Returns the authorities whose proofs were stored on-chain when validating an event.
+			fn get_event_signers(stream_id: H256, event_id: H256) -> Vec<Public>;
+			/// Returns whether an event has already been validated.
+			fn is_event_valid(stream_id: H256, event_id: H256) -> bool;
+			/// Returns the number of the block in which an event was validated, if it was.
+			fn get_event_block(stream_id: H256, event_id: H256) -> Option<NumberFor<Block>>;
+			/// Returns the event validated in a conflict set, if any.
+			fn get_conflict_set_event(stream_id: H256, conflict_set: H256) -> Option<H256>;
+			/// Returns the median value reported for a round of a feed, if any.
+			fn get_feed_value(feed_id: H256, round: u64) -> Option<i64>;
+			/// Returns the authorities whose reports were used to compute a feed value.
+			fn get_feed_signers(feed_id: H256, round: u64) -> Vec<Public>;
+			/// Returns the latest round of a feed and its value, if any round was reported.
+			fn get_latest_feed_value(feed_id: H256) -> Option<(u64, i64)>;
+			/// Create a new extrinsic reporting the value of a feed for a round, from the signed
+			/// reports of the authorities.
+			fn create_feed_extrinsic(
+				feed_id: H256,
+				round: u64,
+				reports: BTreeMap<Public, (i64, Signature)>,
+			) -> Block::Extrinsic;
+			/// Create a new extrinsic for a given event id in a given stream, optionally as a
+			/// member of a conflict set.
 			fn create_unsigned_extrinsic(
+				stream_id: H256,
 				event_id: H256,
+				conflict_set: Option<H256>,
 				event_proofs: Option<BTreeMap<Public, Signature>>,
 			) -> Block::Extrinsic;
 		}
@@ -0,0 +1,259 @@
+//! Storage migrations of the validated streams pallet
+
+use crate::{Config, Pallet};
+use frame_support::{
+	pallet_prelude::*,
+	storage::{unhashed, StoragePrefixedMap},
+	storage_alias,
+	traits::{GetStorageVersion, OnRuntimeUpgrade},
+};
+use sp_core::H256;
+use sp_std::{marker::PhantomData, vec::Vec};
+
+/// How many entries of the version 0 storage are migrated in each block. Each entry costs a read
+/// and a few writes, so a step stays well within the weight of a block.
+pub const MAX_MIGRATED_PER_BLOCK: u32 = 500;
+
+/// The length of the keys of version 0 maps after their prefix: a `Blake2_128Concat`-hashed event
+/// id. Version 1 maps share the same prefixes, but are keyed by a hashed stream id followed by a
+/// hashed event id, so the two are told apart by length while the migration is in progress.
+const V0_KEY_LENGTH: usize = 16 + 32;
+
+/// Storage of the pallet before streams and conflict sets were introduced, in which events were
+/// keyed by their event id alone.
+pub mod v0 {
+	use super::*;
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use frame_support::BoundedBTreeMap;
+	#[cfg(not(feature = "off-chain-proofs"))]
+	use sp_core::sr25519::{Public, Signature};
+
+	/// The block number at which each event was validated, keyed by event id.
+	#[storage_alias]
+	pub type Streams<T: Config> =
+		StorageMap<Pallet<T>, Blake2_128Concat, H256, <T as frame_system::Config>::BlockNumber>;
+
+	/// The proofs each event was validated with, keyed by event id. The signatures are of the
+	/// bare event id.
+	#[cfg(not(feature = "off-chain-proofs"))]
+	#[storage_alias]
+	pub type OnStreams<T: Config> = StorageMap<
+		Pallet<T>,
+		Blake2_128Concat,
+		H256,
+		BoundedBTreeMap<Public, Signature, <T as Config>::VSMaxAuthorities>,
+	>;
+}
+
+/// The raw storage key after which [migrate_step] continues in the next block. Only present
+/// while a migration is in progress.
+#[storage_alias]
+pub type MigrationCursor<T: Config> = StorageValue<Pallet<T>, BoundedVec<u8, ConstU32<160>>>;
+
+/// Whether an event validated before the upgrade is still waiting to be moved to the default
+/// stream by [MigrateToV1].
+pub fn is_pending_event<T: Config>(stream_id: H256, event_id: H256) -> bool {
+	#[cfg(feature = "off-chain-proofs")]
+	let is_v0_event = || v0::Streams::<T>::contains_key(event_id);
+	#[cfg(not(feature = "off-chain-proofs"))]
+	let is_v0_event = || v0::OnStreams::<T>::contains_key(event_id);
+	stream_id == H256::zero() && MigrationCursor::<T>::exists() && is_v0_event()
+}
+
+/// Migrates the storage from version 0 to version 1, moving the events validated so far to the
+/// default stream, `H256::zero()`, which is the one used by clients that do not specify a stream.
+///
+/// The entries are moved [MAX_MIGRATED_PER_BLOCK] at a time: the first batch in the upgrade
+/// block, and the following ones by [migrate_step] in the blocks after it. In the meantime,
+/// events which are yet to be moved are still reported as valid (see [is_pending_event]).
+///
+/// With off-chain proofs, the block at which each event was validated is kept. With on-chain
+/// proofs, version 0 did not record it, so the events are recorded as validated in the block
+/// which moves them; only the proofs of current authorities are kept, and since they are
+/// signatures of the bare event id, they remain a record of who signed rather than proofs that
+/// can be checked against [crate::witnessed_event_message]. Entries which cannot be decoded or
+/// converted are logged and dropped, rather than failing the upgrade.
+pub struct MigrateToV1<T>(PhantomData<T>);
+
+impl<T: Config> OnRuntimeUpgrade for MigrateToV1<T> {
+	fn on_runtime_upgrade() -> Weight {
+		if Pallet::<T>::on_chain_storage_version() != 0 {
+			return T::DbWeight::get().reads(1)
+		}
+		crate::STORAGE_VERSION.put::<Pallet<T>>();
+		#[cfg(feature = "off-chain-proofs")]
+		let first_map = v0::Streams::<T>::final_prefix();
+		#[cfg(not(feature = "off-chain-proofs"))]
+		let first_map = v0::OnStreams::<T>::final_prefix();
+		MigrationCursor::<T>::put(BoundedVec::truncate_from(first_map.to_vec()));
+		T::DbWeight::get().reads_writes(1, 2).saturating_add(migrate_step::<T>())
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+		Ok((count_v0_entries::<T>() as u32).encode())
+	}
+
+	#[cfg(feature = "try-runtime")]
+	fn post_upgrade(state: Vec<u8>) -> Result<(), &'static str> {
+		let before = u32::decode(&mut &state[..]).map_err(|_| "invalid pre-upgrade state")?;
+		ensure!(Pallet::<T>::on_chain_storage_version() == 1, "storage version not updated");
+		let remaining = count_v0_entries::<T>() as u32;
+		ensure!(
+			remaining <= before.saturating_sub(MAX_MIGRATED_PER_BLOCK),
+			"the first migration step did not move enough entries"
+		);
+		ensure!(
+			remaining == 0 || MigrationCursor::<T>::exists(),
+			"the migration stopped before moving all entries"
+		);
+		Ok(())
+	}
+}
+
+/// Moves the next [MAX_MIGRATED_PER_BLOCK] entries of a migration started by [MigrateToV1], if
+/// one is in progress. Called at the start of every block.
+pub fn migrate_step<T: Config>() -> Weight {
+	let mut cursor = match MigrationCursor::<T>::get() {
+		Some(cursor) => cursor.into_inner(),
+		None => return T::DbWeight::get().reads(1),
+	};
+	let streams_prefix = v0::Streams::<T>::final_prefix();
+	let prefix = if cursor.starts_with(&streams_prefix) {
+		streams_prefix
+	} else {
+		#[cfg(not(feature = "off-chain-proofs"))]
+		{
+			v0::OnStreams::<T>::final_prefix()
+		}
+		#[cfg(feature = "off-chain-proofs")]
+		{
+			streams_prefix
+		}
+	};
+	let (mut reads, mut writes, mut migrated) = (1u64, 1u64, 0u32);
+
+	while migrated < MAX_MIGRATED_PER_BLOCK && reads <= 2 * MAX_MIGRATED_PER_BLOCK as u64 {
+		reads += 1;
+		let key = match sp_io::storage::next_key(&cursor).filter(|key| key.starts_with(&prefix)) {
+			Some(key) => key,
+			None => {
+				// Done with this map; with on-chain proofs, the events of version 0 are dropped
+				// after their proofs were moved
+				#[cfg(not(feature = "off-chain-proofs"))]
+				if prefix != streams_prefix {
+					MigrationCursor::<T>::put(BoundedVec::truncate_from(streams_prefix.to_vec()));
+					return T::DbWeight::get().reads_writes(reads, writes)
+				}
+				log::info!(target: "runtime::validated-streams", "Storage migration to v1 done");
+				MigrationCursor::<T>::kill();
+				return T::DbWeight::get().reads_writes(reads, writes)
+			},
+		};
+		if key.len() == prefix.len() + V0_KEY_LENGTH {
+			writes += migrate_entry::<T>(&prefix, &key);
+			migrated += 1;
+			cursor = key;
+		} else {
+			// Entries of version 1 are left alone, skipping past all the events of their stream
+			cursor = key[..(prefix.len() + V0_KEY_LENGTH).min(key.len())].to_vec();
+			cursor.extend_from_slice(&[u8::MAX; V0_KEY_LENGTH]);
+		}
+	}
+
+	MigrationCursor::<T>::put(BoundedVec::truncate_from(cursor));
+	T::DbWeight::get().reads_writes(reads, writes)
+}
+
+/// Moves a single entry of a version 0 map, given its raw key, returning the number of writes.
+fn migrate_entry<T: Config>(prefix: &[u8; 32], key: &[u8]) -> u64 {
+	let event_id = match H256::decode(&mut &key[prefix.len() + 16..]) {
+		Ok(event_id) => event_id,
+		Err(_) => {
+			log::warn!(target: "runtime::validated-streams", "Dropping undecodable key {key:?}");
+			unhashed::kill(key);
+			return 1
+		},
+	};
+
+	#[cfg(feature = "off-chain-proofs")]
+	{
+		let block_number = unhashed::get::<T::BlockNumber>(key);
+		unhashed::kill(key);
+		match block_number {
+			Some(block_number) => {
+				crate::Streams::<T>::insert(H256::zero(), event_id, block_number);
+				crate::BlockEvents::<T>::insert(block_number, (H256::zero(), event_id), ());
+				3
+			},
+			None => {
+				log::warn!(target: "runtime::validated-streams", "Dropping event {event_id:?}");
+				1
+			},
+		}
+	}
+
+	#[cfg(not(feature = "off-chain-proofs"))]
+	{
+		// Events of version 0 are only moved with their proofs
+		if *prefix == v0::Streams::<T>::final_prefix() {
+			unhashed::kill(key);
+			return 1
+		}
+		let signatures = unhashed::get::<
+			frame_support::BoundedBTreeMap<
+				sp_core::sr25519::Public,
+				sp_core::sr25519::Signature,
+				T::VSMaxAuthorities,
+			>,
+		>(key);
+		unhashed::kill(key);
+		let authorities = Pallet::<T>::authority_keys();
+		let authority_set = Pallet::<T>::authority_set_index(&authorities);
+		let proof = signatures.and_then(|signatures| {
+			let signatures = signatures
+				.into_inner()
+				.into_iter()
+				.filter(|(signer, _)| authorities.contains(signer))
+				.collect();
+			crate::QuorumProof::new(authority_set, &authorities, &signatures)
+		});
+		let proof = match proof {
+			Some(proof) => proof,
+			None => {
+				log::warn!(target: "runtime::validated-streams", "Dropping event {event_id:?}");
+				return 1
+			},
+		};
+		let block_number = frame_system::Pallet::<T>::block_number();
+		Pallet::<T>::record_authority_set(authorities);
+		crate::OnStreams::<T>::insert(H256::zero(), event_id, (block_number, proof));
+		crate::BlockEvents::<T>::insert(block_number, (H256::zero(), event_id), ());
+		5
+	}
+}
+
+/// Counts the entries of version 0 maps left to migrate.
+#[cfg(feature = "try-runtime")]
+fn count_v0_entries<T: Config>() -> usize {
+	#[cfg(feature = "off-chain-proofs")]
+	let prefixes = [v0::Streams::<T>::final_prefix()];
+	#[cfg(not(feature = "off-chain-proofs"))]
+	let prefixes = [v0::OnStreams::<T>::final_prefix()];
+	prefixes
+		.iter()
+		.map(|prefix| {
+			let mut count = 0;
+			let mut key = prefix.to_vec();
+			while let Some(next) =
+				sp_io::storage::next_key(&key).filter(|next| next.starts_with(prefix))
+			{
+				if next.len() == prefix.len() + V0_KEY_LENGTH {
+					count += 1;
+				}
+				key = next;
+			}
+			count
+		})
+		.sum()
+}
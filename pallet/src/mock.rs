@@ -93,33 +93,62 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 	system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
 }
 
+/// Signs the given values as reports for a round of a feed, one per authority.
+pub fn feed_reports(
+	feed_id: &H256,
+	round: u64,
+	values: &[i64],
+) -> pallet_validated_streams::FeedReportsMap<Test> {
+	get_pairs(PAIRS.lock().unwrap().as_mut(), values.len() as u16)
+		.zip(values)
+		.map(|(key, value)| {
+			let message = crate::feed_report_message(feed_id, round, *value);
+			let signature = KEYSTORE
+				.sign_with(AURA, &sp_core::crypto::CryptoTypePublicPair::from(key), &message)
+				.unwrap()
+				.unwrap();
+			(*key, (*value, signature.as_slice().try_into().unwrap()))
+		})
+		.collect::<std::collections::BTreeMap<_, _>>()
+		.try_into()
+		.unwrap()
+}
+
 #[cfg(not(feature = "off-chain-proofs"))]
 pub mod onchain_mod {
 	use crate::mock::*;
-	pub use crate::Config;
-	pub use frame_support::BoundedBTreeMap;
+	pub use crate::{Config, QuorumProofOf};
 	pub use sp_core::{crypto::CryptoTypePublicPair, sr25519::Signature};
 	use std::collections::BTreeMap;
-	pub fn proofs(
-		event_id: &H256,
-	) -> BoundedBTreeMap<Public, Signature, <Test as Config>::VSMaxAuthorities> {
-		proofs_n(event_id, AuthoritiesCount::get())
+
+	/// The keys of the current authorities.
+	pub fn authorities() -> Vec<Public> {
+		get_pairs(PAIRS.lock().unwrap().as_mut(), AuthoritiesCount::get())
+			.cloned()
+			.collect()
+	}
+
+	pub fn proofs(stream_id: &H256, event_id: &H256) -> QuorumProofOf<Test> {
+		proofs_n(stream_id, event_id, None, AuthoritiesCount::get())
 	}
 
 	pub fn proofs_n(
+		stream_id: &H256,
 		event_id: &H256,
+		conflict_set: Option<&H256>,
 		count: u16,
-	) -> BoundedBTreeMap<Public, Signature, <Test as Config>::VSMaxAuthorities> {
-		get_pairs(PAIRS.lock().unwrap().as_mut(), count)
+	) -> QuorumProofOf<Test> {
+		let message = crate::witnessed_event_message(stream_id, event_id, conflict_set);
+		let signatures = get_pairs(PAIRS.lock().unwrap().as_mut(), count)
 			.map(|key| {
 				let signature = KEYSTORE
-					.sign_with(AURA, &CryptoTypePublicPair::from(key), event_id.as_bytes())
+					.sign_with(AURA, &CryptoTypePublicPair::from(key), &message)
 					.unwrap()
 					.unwrap();
 				(*key, signature.as_slice().try_into().unwrap())
 			})
-			.collect::<BTreeMap<_, _>>()
-			.try_into()
-			.unwrap()
+			.collect::<BTreeMap<_, _>>();
+		// the authority set is recorded under index 0 when the first event is validated
+		QuorumProofOf::<Test>::new(0, &authorities(), &signatures).unwrap()
 	}
 }
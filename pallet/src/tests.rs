@@ -10,9 +10,14 @@ use sp_runtime::{
 fn test_validate_unsigned() {
 	new_test_ext().execute_with(|| {
 		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
 		let event_id = H256::repeat_byte(0);
-		let call =
-			pallet_validated_streams::Call::<Test>::validate_event { event_id, proofs: None };
+		let call = pallet_validated_streams::Call::<Test>::validate_event {
+			stream_id,
+			event_id,
+			conflict_set: None,
+			proofs: None,
+		};
 
 		assert_err!(
 			ValidatedStreams::validate_unsigned(TransactionSource::External, &call),
@@ -22,11 +27,17 @@ fn test_validate_unsigned() {
 		assert_ok!(ValidatedStreams::validate_unsigned(TransactionSource::InBlock, &call));
 
 		#[cfg(not(feature = "off-chain-proofs"))]
-		let proofs_map = Some(crate::mock::onchain_mod::proofs(&event_id));
+		let proofs_map = Some(crate::mock::onchain_mod::proofs(&stream_id, &event_id));
 		#[cfg(feature = "off-chain-proofs")]
 		let proofs_map = None;
 
-		assert_ok!(ValidatedStreams::validate_event(RuntimeOrigin::none(), event_id, proofs_map));
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			event_id,
+			None,
+			proofs_map
+		));
 		assert_err!(
 			ValidatedStreams::validate_unsigned(TransactionSource::Local, &call),
 			TransactionValidityError::Invalid(InvalidTransaction::Stale)
@@ -42,23 +53,55 @@ fn it_adds_event() {
 	new_test_ext().execute_with(|| {
 		// Go past genesis block so events get deposited
 		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
 		let event_id = H256::repeat_byte(0);
-		assert!(!ValidatedStreams::is_event_valid(event_id));
+		assert!(!ValidatedStreams::is_event_valid(stream_id, event_id));
 		// Dispatch an extrinsic
 		// signature should not matter since it should pass through validate_unsigned.
-		assert_ok!(ValidatedStreams::validate_event(RuntimeOrigin::none(), event_id, None));
-		assert_eq!(ValidatedStreams::get_all_events(), vec![event_id]);
-		assert!(ValidatedStreams::is_event_valid(event_id));
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			event_id,
+			None,
+			None
+		));
+		assert_eq!(ValidatedStreams::get_all_events(), vec![(stream_id, event_id)]);
+		assert!(ValidatedStreams::is_event_valid(stream_id, event_id));
 		System::assert_last_event(
-			pallet_validated_streams::Event::ValidatedEvent { event_id }.into(),
+			pallet_validated_streams::Event::ValidatedEvent {
+				stream_id,
+				event_id,
+				conflict_set: None,
+			}
+			.into(),
 		);
 		//double check the first block events
-		assert_eq!(ValidatedStreams::get_block_events(1), vec![event_id]);
+		assert_eq!(ValidatedStreams::get_block_events(1), vec![(stream_id, event_id)]);
+		assert_eq!(ValidatedStreams::get_event_block(stream_id, event_id), Some(1));
+		assert_eq!(ValidatedStreams::get_event_block(stream_id, H256::repeat_byte(1)), None);
+		assert_eq!(ValidatedStreams::get_block_events(2), vec![]);
 		//dispatch an extrinsic with an already validated event
 		assert_err!(
-			ValidatedStreams::validate_event(RuntimeOrigin::root(), event_id, None),
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::root(),
+				stream_id,
+				event_id,
+				None,
+				None
+			),
 			pallet_validated_streams::Error::<Test>::AlreadyValidated
 		);
+		//the same event id in another stream is a different event
+		let other_stream_id = H256::repeat_byte(8);
+		assert!(!ValidatedStreams::is_event_valid(other_stream_id, event_id));
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			other_stream_id,
+			event_id,
+			None,
+			None
+		));
+		assert!(ValidatedStreams::is_event_valid(other_stream_id, event_id));
 	})
 }
 
@@ -69,77 +112,100 @@ fn it_validates_event() {
 	new_test_ext().execute_with(|| {
 		// Go past genesis block so events get deposited
 		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
 		let event_id = H256::repeat_byte(0);
-		let proofs_map = proofs(&event_id);
-		assert!(!ValidatedStreams::is_event_valid(event_id));
+		let proofs_map = proofs(&stream_id, &event_id);
+		assert!(!ValidatedStreams::is_event_valid(stream_id, event_id));
 		// Dispatch an extrinsic
 		// signature should not matter since it should pass through validate_unsigned.
 		assert_ok!(ValidatedStreams::validate_event(
 			RuntimeOrigin::none(),
+			stream_id,
 			event_id,
+			None,
 			Some(proofs_map.clone())
 		));
-		assert_eq!(ValidatedStreams::get_all_events(), vec![event_id]);
-		assert!(ValidatedStreams::is_event_valid(event_id));
+		assert_eq!(ValidatedStreams::get_all_events(), vec![(stream_id, event_id)]);
+		assert!(ValidatedStreams::is_event_valid(stream_id, event_id));
+		assert_eq!(ValidatedStreams::get_event_block(stream_id, event_id), Some(1));
+		assert_eq!(ValidatedStreams::get_block_events(1), vec![(stream_id, event_id)]);
+		assert_eq!(ValidatedStreams::get_event_signers(stream_id, event_id), authorities());
 		System::assert_last_event(
-			pallet_validated_streams::Event::ValidatedEvent { event_id }.into(),
+			pallet_validated_streams::Event::ValidatedEvent {
+				stream_id,
+				event_id,
+				conflict_set: None,
+			}
+			.into(),
 		);
 		//dispatch an extrinsic with an already validated event
 		assert_err!(
-			ValidatedStreams::validate_event(RuntimeOrigin::root(), event_id, Some(proofs_map)),
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::root(),
+				stream_id,
+				event_id,
+				None,
+				Some(proofs_map.clone())
+			),
 			pallet_validated_streams::Error::<Test>::AlreadyValidated
 		);
+		//proofs for one stream are not valid for another stream
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::root(),
+				H256::repeat_byte(8),
+				event_id,
+				None,
+				Some(proofs_map)
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
 		//corrupt a signature
 		let event_id = H256::repeat_byte(1);
-		let mut proofs_map = proofs(&event_id);
-		*proofs_map
-			.get_mut(&proofs_map.iter().next().unwrap().0.clone())
-			.unwrap()
-			.as_mut()
-			.get_mut(0)
-			.unwrap() += 1;
+		let proofs_map = proofs(&stream_id, &event_id);
+		let mut signatures = proofs_map.signatures.to_vec();
+		signatures[0].as_mut()[0] ^= 1;
+		let proofs_map =
+			QuorumProofOf::<Test> { signatures: signatures.try_into().unwrap(), ..proofs_map };
 		assert_err!(
 			ValidatedStreams::validate_event(
 				RuntimeOrigin::root(),
+				stream_id,
 				event_id,
+				None,
 				Some(proofs_map.clone())
 			),
 			pallet_validated_streams::Error::<Test>::InvalidProof
 		);
-		//inject an unrecognized authority proof
-		let unrecognized_authority = KEYSTORE.sr25519_generate_new(AURA, None).unwrap();
-		proofs_map
-			.try_insert(
-				unrecognized_authority,
-				KEYSTORE
-					.sign_with(
-						AURA,
-						&CryptoTypePublicPair::from(unrecognized_authority),
-						event_id.as_bytes(),
-					)
-					.unwrap()
-					.unwrap()
-					.as_slice()
-					.try_into()
-					.unwrap(),
-			)
-			.unwrap();
+		//claim a signature from an authority past the end of the authority set
+		let proofs_map = proofs(&stream_id, &event_id);
+		let mut signers = proofs_map.signers.to_vec();
+		signers[0] |= 1 << AuthoritiesCount::get();
+		let mut signatures = proofs_map.signatures.to_vec();
+		signatures.push(signatures[0].clone());
+		let proofs_map = QuorumProofOf::<Test> {
+			authority_set: 0,
+			signers: signers.try_into().unwrap(),
+			signatures: signatures.try_into().unwrap(),
+		};
 		assert_err!(
 			ValidatedStreams::validate_event(
 				RuntimeOrigin::root(),
+				stream_id,
 				event_id,
+				None,
 				Some(proofs_map.clone())
 			),
 			pallet_validated_streams::Error::<Test>::UnrecognizedAuthority
 		);
 		//provide unsifficient amount of proofs by removing two proofs since target is 3
-		let mut proofs_map = proofs(&event_id);
-		proofs_map.remove(&proofs_map.iter().next().unwrap().0.clone());
-		proofs_map.remove(&proofs_map.iter().next().unwrap().0.clone());
+		let proofs_map = proofs_n(&stream_id, &event_id, None, AuthoritiesCount::get() - 2);
 		assert_err!(
 			ValidatedStreams::validate_event(
 				RuntimeOrigin::root(),
+				stream_id,
 				event_id,
+				None,
 				Some(proofs_map.clone())
 			),
 			pallet_validated_streams::Error::<Test>::NotEnoughProofs
@@ -147,7 +213,13 @@ fn it_validates_event() {
 
 		//provide no proofs
 		assert_err!(
-			ValidatedStreams::validate_event(RuntimeOrigin::root(), event_id, None),
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::root(),
+				stream_id,
+				event_id,
+				None,
+				None
+			),
 			pallet_validated_streams::Error::<Test>::NoProofs
 		);
 	})
@@ -165,21 +237,423 @@ fn it_computes_target_correctly(#[case] total: u16, #[case] target: u16) {
 	new_test_ext().execute_with(|| {
 		AuthoritiesCount::set(&total);
 		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
 		let event_id = H256::repeat_byte(0);
-		let proofs_few = proofs_n(&event_id, target - 1);
+		let proofs_few = proofs_n(&stream_id, &event_id, None, target - 1);
 		assert_err!(
 			ValidatedStreams::validate_event(
 				RuntimeOrigin::root(),
+				stream_id,
 				event_id,
+				None,
 				Some(proofs_few.clone())
 			),
 			pallet_validated_streams::Error::<Test>::NotEnoughProofs
 		);
-		let proofs_exact = proofs_n(&event_id, target);
+		let proofs_exact = proofs_n(&stream_id, &event_id, None, target);
 		assert_ok!(ValidatedStreams::validate_event(
 			RuntimeOrigin::none(),
+			stream_id,
 			event_id,
+			None,
 			Some(proofs_exact.clone())
 		));
 	})
 }
+
+/// validate an event, change the authorities, then check that proofs built for the previous set
+/// are rejected both by validate_unsigned and validate_event
+#[cfg(not(feature = "off-chain-proofs"))]
+#[test]
+fn it_rejects_proofs_of_stale_authority_sets() {
+	use crate::mock::onchain_mod::*;
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			H256::repeat_byte(0),
+			None,
+			Some(proofs(&stream_id, &H256::repeat_byte(0)))
+		));
+
+		AuthoritiesCount::set(&5);
+		let event_id = H256::repeat_byte(1);
+		let stale_proofs = proofs(&stream_id, &event_id);
+		let call = pallet_validated_streams::Call::<Test>::validate_event {
+			stream_id,
+			event_id,
+			conflict_set: None,
+			proofs: Some(stale_proofs.clone()),
+		};
+		assert_err!(
+			ValidatedStreams::validate_unsigned(TransactionSource::Local, &call),
+			TransactionValidityError::Invalid(InvalidTransaction::Stale)
+		);
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::none(),
+				stream_id,
+				event_id,
+				None,
+				Some(stale_proofs.clone())
+			),
+			pallet_validated_streams::Error::<Test>::StaleAuthoritySet
+		);
+
+		let current_proofs = QuorumProofOf::<Test> { authority_set: 1, ..stale_proofs };
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			event_id,
+			None,
+			Some(current_proofs)
+		));
+		assert_eq!(ValidatedStreams::get_event_signers(stream_id, event_id), authorities());
+	})
+}
+
+/// validate one member of a conflict set, then check that the other members are rejected both by
+/// validate_unsigned and by the call itself
+#[test]
+fn it_resolves_conflict_sets() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
+		let conflict_set = H256::repeat_byte(9);
+		let event_a = H256::repeat_byte(1);
+		let event_b = H256::repeat_byte(2);
+
+		#[cfg(not(feature = "off-chain-proofs"))]
+		let proofs_for = |event_id: &H256| {
+			Some(crate::mock::onchain_mod::proofs_n(
+				&stream_id,
+				event_id,
+				Some(&conflict_set),
+				AuthoritiesCount::get(),
+			))
+		};
+		#[cfg(feature = "off-chain-proofs")]
+		let proofs_for = |_: &H256| None;
+
+		let call_b = pallet_validated_streams::Call::<Test>::validate_event {
+			stream_id,
+			event_id: event_b,
+			conflict_set: Some(conflict_set),
+			proofs: proofs_for(&event_b),
+		};
+		assert_ok!(ValidatedStreams::validate_unsigned(TransactionSource::Local, &call_b));
+
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			event_a,
+			Some(conflict_set),
+			proofs_for(&event_a)
+		));
+		assert_eq!(
+			ValidatedStreams::get_conflict_set_event(stream_id, conflict_set),
+			Some(event_a)
+		);
+		System::assert_last_event(
+			pallet_validated_streams::Event::ValidatedEvent {
+				stream_id,
+				event_id: event_a,
+				conflict_set: Some(conflict_set),
+			}
+			.into(),
+		);
+
+		assert_err!(
+			ValidatedStreams::validate_unsigned(TransactionSource::Local, &call_b),
+			TransactionValidityError::Invalid(InvalidTransaction::Stale)
+		);
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::none(),
+				stream_id,
+				event_b,
+				Some(conflict_set),
+				proofs_for(&event_b)
+			),
+			pallet_validated_streams::Error::<Test>::ConflictSetResolved
+		);
+		assert!(!ValidatedStreams::is_event_valid(stream_id, event_b));
+
+		// the same conflict set id in another stream is a different conflict set
+		let other_stream_id = H256::repeat_byte(8);
+		assert_eq!(ValidatedStreams::get_conflict_set_event(other_stream_id, conflict_set), None);
+	})
+}
+
+/// proofs signed for an event as a member of a conflict set cannot be used to validate it outside
+/// of the set, or in another set
+#[cfg(not(feature = "off-chain-proofs"))]
+#[test]
+fn it_binds_proofs_to_conflict_sets() {
+	use crate::mock::onchain_mod::*;
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let stream_id = H256::repeat_byte(7);
+		let conflict_set = H256::repeat_byte(9);
+		let event_id = H256::repeat_byte(1);
+		let proofs_map =
+			proofs_n(&stream_id, &event_id, Some(&conflict_set), AuthoritiesCount::get());
+
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::none(),
+				stream_id,
+				event_id,
+				None,
+				Some(proofs_map.clone())
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
+		assert_err!(
+			ValidatedStreams::validate_event(
+				RuntimeOrigin::none(),
+				stream_id,
+				event_id,
+				Some(H256::repeat_byte(10)),
+				Some(proofs_map.clone())
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
+		assert_ok!(ValidatedStreams::validate_event(
+			RuntimeOrigin::none(),
+			stream_id,
+			event_id,
+			Some(conflict_set),
+			Some(proofs_map)
+		));
+	})
+}
+
+#[rstest::rstest]
+#[case(vec![], None)]
+#[case(vec![5], Some(5))]
+#[case(vec![3, 1, 2], Some(2))]
+#[case(vec![4, 1, 3, 2], Some(2))]
+#[case(vec![i64::MAX, -7, i64::MIN, 0, 9], Some(0))]
+fn it_computes_median_correctly(#[case] values: Vec<i64>, #[case] median: Option<i64>) {
+	let mut values = values;
+	assert_eq!(crate::median(&mut values), median);
+}
+
+/// report a feed value from the signed values of all authorities, then check that the median is
+/// stored and that the round cannot be reported again
+#[test]
+fn it_reports_feed_values() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let feed_id = H256::repeat_byte(3);
+		let reports = feed_reports(&feed_id, 1, &[105, 99, 100, 250]);
+		let call = pallet_validated_streams::Call::<Test>::report_feed_value {
+			feed_id,
+			round: 1,
+			reports: reports.clone(),
+		};
+		assert_ok!(ValidatedStreams::validate_unsigned(TransactionSource::Local, &call));
+
+		assert_ok!(ValidatedStreams::report_feed_value(
+			RuntimeOrigin::none(),
+			feed_id,
+			1,
+			reports.clone()
+		));
+		assert_eq!(ValidatedStreams::get_feed_value(feed_id, 1), Some(100));
+		assert_eq!(ValidatedStreams::get_latest_feed_value(feed_id), Some((1, 100)));
+		assert_eq!(
+			ValidatedStreams::get_feed_signers(feed_id, 1),
+			reports.keys().cloned().collect::<Vec<_>>()
+		);
+		System::assert_last_event(
+			pallet_validated_streams::Event::FeedValueReported { feed_id, round: 1, median: 100 }
+				.into(),
+		);
+
+		assert_err!(
+			ValidatedStreams::validate_unsigned(TransactionSource::Local, &call),
+			TransactionValidityError::Invalid(InvalidTransaction::Stale)
+		);
+		assert_err!(
+			ValidatedStreams::report_feed_value(RuntimeOrigin::none(), feed_id, 1, reports),
+			pallet_validated_streams::Error::<Test>::FeedRoundReported
+		);
+
+		// an older round does not replace the latest one
+		assert_ok!(ValidatedStreams::report_feed_value(
+			RuntimeOrigin::none(),
+			feed_id,
+			0,
+			feed_reports(&feed_id, 0, &[1, 1, 1, 1])
+		));
+		assert_eq!(ValidatedStreams::get_latest_feed_value(feed_id), Some((1, 100)));
+	})
+}
+
+/// feed values need reports from 2/3 of the authorities, each signed for the exact value, round
+/// and feed
+#[test]
+fn it_verifies_feed_reports() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		let feed_id = H256::repeat_byte(3);
+
+		assert_err!(
+			ValidatedStreams::report_feed_value(
+				RuntimeOrigin::none(),
+				feed_id,
+				1,
+				feed_reports(&feed_id, 1, &[100, 100])
+			),
+			pallet_validated_streams::Error::<Test>::NotEnoughProofs
+		);
+
+		let mut tampered = feed_reports(&feed_id, 1, &[100, 100, 100]).into_inner();
+		tampered.values_mut().next().unwrap().0 = 1_000_000;
+		assert_err!(
+			ValidatedStreams::report_feed_value(
+				RuntimeOrigin::none(),
+				feed_id,
+				1,
+				tampered.try_into().unwrap()
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
+
+		assert_err!(
+			ValidatedStreams::report_feed_value(
+				RuntimeOrigin::none(),
+				feed_id,
+				1,
+				feed_reports(&feed_id, 2, &[100, 100, 100])
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
+		assert_err!(
+			ValidatedStreams::report_feed_value(
+				RuntimeOrigin::none(),
+				H256::repeat_byte(4),
+				1,
+				feed_reports(&feed_id, 1, &[100, 100, 100])
+			),
+			pallet_validated_streams::Error::<Test>::InvalidProof
+		);
+
+		assert_ok!(ValidatedStreams::report_feed_value(
+			RuntimeOrigin::none(),
+			feed_id,
+			1,
+			feed_reports(&feed_id, 1, &[100, 100, 100])
+		));
+	})
+}
+
+#[test]
+fn test_witness_proof() {
+	use codec::{Decode, Encode};
+	use sp_core::crypto::CryptoTypePublicPair;
+
+	let signer = KEYSTORE.sr25519_generate_new(AURA, None).unwrap();
+	let stream_id = H256::repeat_byte(7);
+	let event_id = H256::repeat_byte(1);
+	let conflict_set = Some(H256::repeat_byte(2));
+	let message = crate::witnessed_event_message(&stream_id, &event_id, conflict_set.as_ref());
+	let signature = KEYSTORE
+		.sign_with(AURA, &CryptoTypePublicPair::from(signer), &message)
+		.unwrap()
+		.unwrap();
+	let proof = crate::WitnessProof {
+		stream_id,
+		event_id,
+		conflict_set,
+		signer,
+		signature: signature.as_slice().try_into().unwrap(),
+	};
+	assert!(proof.verify());
+
+	// the encoding is SCALE, and round-trips
+	let encoded = proof.encode();
+	assert_eq!(encoded.len(), 32 + 32 + 33 + 32 + 64);
+	assert_eq!(crate::WitnessProof::decode(&mut encoded.as_slice()).unwrap(), proof);
+
+	// the signature covers the conflict set
+	let mut other_conflict_set = proof.clone();
+	other_conflict_set.conflict_set = None;
+	assert!(!other_conflict_set.verify());
+}
+
+#[test]
+fn test_quorum_proof() {
+	use crate::QuorumProof;
+	use codec::Encode;
+	use sp_core::{
+		sr25519::{Public, Signature},
+		ConstU32,
+	};
+	use std::collections::BTreeMap;
+
+	let authorities: Vec<_> = (1..=10).map(|i| Public::from_raw([i; 32])).collect();
+	let signature = |i| Signature::from_raw([i; 64]);
+	let proofs: BTreeMap<_, _> =
+		[0, 3, 9].into_iter().map(|i| (authorities[i], signature(i as u8))).collect();
+
+	let proof = QuorumProof::<ConstU32<32>>::new(0, &authorities, &proofs).unwrap();
+	assert_eq!(proof.signers.to_vec(), vec![0b0000_1001, 0b0000_0010]);
+	assert_eq!(proof.signatures.to_vec(), vec![signature(0), signature(3), signature(9)]);
+	assert_eq!(
+		proof.signers(&authorities),
+		Some(vec![authorities[0], authorities[3], authorities[9]])
+	);
+	// one bit per authority and 64 bytes per signer, instead of 96 bytes per signer
+	assert_eq!(proof.encoded_size(), 1 + 2 + 1 + 3 * 64);
+
+	// the bitfield is only meaningful for the authority set it was built for
+	assert_eq!(proof.signers(&authorities[..8]), None);
+	// signers must be authorities
+	let mut outsider = proofs.clone();
+	outsider.insert(Public::from_raw([42; 32]), signature(42));
+	assert_eq!(QuorumProof::<ConstU32<32>>::new(0, &authorities, &outsider), None);
+}
+
+/// events validated before streams existed are moved to the default stream, keeping their block
+#[cfg(feature = "off-chain-proofs")]
+#[test]
+fn it_migrates_events_to_the_default_stream() {
+	use crate::migrations::{
+		migrate_step, v0, MigrateToV1, MigrationCursor, MAX_MIGRATED_PER_BLOCK,
+	};
+	use frame_support::traits::{GetStorageVersion, OnRuntimeUpgrade, StorageVersion};
+
+	new_test_ext().execute_with(|| {
+		StorageVersion::new(0).put::<ValidatedStreams>();
+		let event_ids: Vec<H256> = (0..=MAX_MIGRATED_PER_BLOCK)
+			.map(|i| H256::from_low_u64_be(i as u64 + 1))
+			.collect();
+		for event_id in &event_ids {
+			v0::Streams::<Test>::insert(event_id, 3);
+		}
+
+		MigrateToV1::<Test>::on_runtime_upgrade();
+
+		assert_eq!(ValidatedStreams::on_chain_storage_version(), 1);
+		assert!(MigrationCursor::<Test>::exists());
+		let pending = event_ids.iter().filter(|id| v0::Streams::<Test>::contains_key(id));
+		assert_eq!(pending.count(), 1);
+		assert_eq!(ValidatedStreams::get_block_events(3).len(), MAX_MIGRATED_PER_BLOCK as usize);
+		// Events yet to be migrated are still valid
+		assert!(event_ids.iter().all(|id| ValidatedStreams::is_event_valid(H256::zero(), *id)));
+
+		migrate_step::<Test>();
+
+		assert!(!MigrationCursor::<Test>::exists());
+		assert!(event_ids.iter().all(|id| !v0::Streams::<Test>::contains_key(id)));
+		assert_eq!(ValidatedStreams::get_block_events(3).len(), event_ids.len());
+		for event_id in &event_ids {
+			assert_eq!(ValidatedStreams::get_event_block(H256::zero(), *event_id), Some(3));
+		}
+	})
+}
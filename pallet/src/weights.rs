@@ -1,13 +1,17 @@
 
-//! Autogenerated weights for `pallet_validated_streams`
+//! Weights for `pallet_validated_streams`
 //!
-//! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 4.0.0-dev
-//! DATE: 2023-05-11, STEPS: `100`, REPEAT: `100`, LOW RANGE: `[]`, HIGH RANGE: `[]`
-//! WORST CASE MAP SIZE: `1000000`
-//! HOSTNAME: `apocryph-server-7d59769d7-6rm6l`, CPU: `Intel(R) Xeon(R) Gold 6126 CPU @ 2.60GHz`
-//! EXECUTION: None, WASM-EXECUTION: Compiled, CHAIN: Some("dev"), DB CACHE: 1024
+//! PLACEHOLDER: the values below were NOT generated for the current calls, and must be
+//! regenerated with the command below, on reference hardware, before this pallet is used in
+//! production.
+//! - `validate_event` was last benchmarked on 2023-05-11 (Intel Xeon Gold 6126, STEPS: `100`,
+//!   REPEAT: `100`), when it only wrote `Streams`. The storage accesses it has gained since
+//!   (`ConflictSets`, `BlockEvents`) are counted below, but their execution time is not, nor is
+//!   the verification of the signatures of on-chain proofs.
+//! - `report_feed_value` was never benchmarked; its figures are a rough estimate of one sr25519
+//!   verification per report.
 
-// Executed Command:
+// Command to regenerate them:
 // ./target/release/vstreams_node
 // benchmark
 // pallet
@@ -32,12 +36,15 @@ use frame_support::{traits::Get, weights::Weight};
 use sp_std::marker::PhantomData;
 pub trait WeightInfo {
 	fn validate_event() -> Weight;
+	fn report_feed_value(n: u32, ) -> Weight;
 }
 /// Weight functions for `pallet_validated_streams`.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 	/// Storage: ValidatedStreams Streams (r:1 w:1)
 	/// Proof: ValidatedStreams Streams (max_values: None, max_size: Some(52), added: 2527, mode: MaxEncodedLen)
+	/// Storage: ValidatedStreams ConflictSets (r:1 w:1)
+	/// Storage: ValidatedStreams BlockEvents (r:0 w:1)
 	fn validate_event() -> Weight {
 		// Proof Size summary in bytes:
 		//  Measured:  `6`
@@ -45,7 +52,22 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 		// Minimum execution time: 7_873_000 picoseconds.
 		Weight::from_parts(8_834_000, 0)
 			.saturating_add(Weight::from_parts(0, 3517))
-			.saturating_add(T::DbWeight::get().reads(1))
-			.saturating_add(T::DbWeight::get().writes(1))
+			.saturating_add(T::DbWeight::get().reads(2))
+			.saturating_add(T::DbWeight::get().writes(3))
+	}
+	/// Storage: ValidatedStreams FeedValues (r:1 w:1)
+	/// Proof: ValidatedStreams FeedValues (max_values: None, max_size: Some(64), added: 2539, mode: MaxEncodedLen)
+	/// Storage: ValidatedStreams LatestFeedRounds (r:1 w:1)
+	/// Proof: ValidatedStreams LatestFeedRounds (max_values: None, max_size: Some(56), added: 2531, mode: MaxEncodedLen)
+	/// Storage: ValidatedStreams FeedSigners (r:0 w:1)
+	/// Proof: ValidatedStreams FeedSigners (max_values: None, max_size: Some(1081), added: 3556, mode: MaxEncodedLen)
+	/// The range of component `n` is `[1, 32]`.
+	fn report_feed_value(n: u32, ) -> Weight {
+		// Estimated, not measured: a base cost plus one sr25519 verification per report.
+		Weight::from_parts(16_000_000, 0)
+			.saturating_add(Weight::from_parts(0, 7058))
+			.saturating_add(Weight::from_parts(48_000_000, 0).saturating_mul(n.into()))
+			.saturating_add(T::DbWeight::get().reads(2))
+			.saturating_add(T::DbWeight::get().writes(3))
 	}
 }
@@ -108,10 +108,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
 	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
 	//   the compatible custom types.
-	spec_version: 100,
+	spec_version: 101,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 2,
 	state_version: 1,
 };
 
@@ -329,8 +329,12 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPalletsWithSystem,
+	Migrations,
 >;
 
+/// Storage migrations to run on the next runtime upgrade.
+pub type Migrations = (pallet_validated_streams::migrations::MigrateToV1<Runtime>,);
+
 #[cfg(feature = "runtime-benchmarks")]
 #[macro_use]
 extern crate frame_benchmarking;
@@ -388,23 +392,83 @@ impl_runtime_apis! {
 		}
 	}
 	impl pallet_validated_streams::ValidatedStreamsApi<Block> for Runtime {
-		fn get_extrinsic_ids(extrinsics: &Vec<<Block as BlockT>::Extrinsic>) -> Vec<H256> {
+		fn get_extrinsic_ids(extrinsics: &Vec<<Block as BlockT>::Extrinsic>) -> Vec<(H256, H256)> {
 			let mut ids = Vec::new();
 			for extrinsic in extrinsics.iter() {
 				if let RuntimeCall::ValidatedStreams(
 					pallet_validated_streams::Call::<Runtime>::validate_event {
-						event_id: call_data,
+						stream_id,
+						event_id,
+						conflict_set: _,
 						proofs: _,
 					},
 				) = &extrinsic.function
 				{
-					ids.push(*call_data);
+					ids.push((*stream_id, *event_id));
 				}
 			}
 			ids
 		}
+		fn get_extrinsic_events(
+			extrinsics: &Vec<<Block as BlockT>::Extrinsic>,
+		) -> Vec<(u32, H256, H256, Option<H256>)> {
+			let mut events = Vec::new();
+			for (index, extrinsic) in extrinsics.iter().enumerate() {
+				if let RuntimeCall::ValidatedStreams(
+					pallet_validated_streams::Call::<Runtime>::validate_event {
+						stream_id,
+						event_id,
+						conflict_set,
+						proofs: _,
+					},
+				) = &extrinsic.function
+				{
+					events.push((index as u32, *stream_id, *event_id, *conflict_set));
+				}
+			}
+			events
+		}
+		fn get_event_signers(stream_id: H256, event_id: H256) -> Vec<Public> {
+			ValidatedStreams::get_event_signers(stream_id, event_id)
+		}
+		fn is_event_valid(stream_id: H256, event_id: H256) -> bool {
+			ValidatedStreams::is_event_valid(stream_id, event_id)
+		}
+		fn get_event_block(stream_id: H256, event_id: H256) -> Option<NumberFor<Block>> {
+			ValidatedStreams::get_event_block(stream_id, event_id)
+		}
+		fn get_conflict_set_event(stream_id: H256, conflict_set: H256) -> Option<H256> {
+			ValidatedStreams::get_conflict_set_event(stream_id, conflict_set)
+		}
+		fn get_feed_value(feed_id: H256, round: u64) -> Option<i64> {
+			ValidatedStreams::get_feed_value(feed_id, round)
+		}
+		fn get_feed_signers(feed_id: H256, round: u64) -> Vec<Public> {
+			ValidatedStreams::get_feed_signers(feed_id, round)
+		}
+		fn get_latest_feed_value(feed_id: H256) -> Option<(u64, i64)> {
+			ValidatedStreams::get_latest_feed_value(feed_id)
+		}
+		fn create_feed_extrinsic(
+			feed_id: H256,
+			round: u64,
+			reports: BTreeMap<Public, (i64, SrSignature)>,
+		) -> <Block as BlockT>::Extrinsic {
+			let reports = reports.try_into().unwrap_or_default(); // NOTE: As above, too many reports result in none being submitted, and thus produce an error.
+			UncheckedExtrinsic {
+				signature: None,
+				function: pallet_validated_streams::Call::<Runtime>::report_feed_value {
+					feed_id,
+					round,
+					reports,
+				}
+				.into(),
+			}
+		}
 		fn create_unsigned_extrinsic(
+			stream_id: H256,
 			event_id: H256,
+			conflict_set: Option<H256>,
 			event_proofs: Option<
 				BTreeMap<
 					Public,
@@ -412,11 +476,13 @@ impl_runtime_apis! {
 				>,
 			>,
 		) -> <Block as BlockT>::Extrinsic {
-			let proofs = event_proofs.and_then(|x| x.try_into().ok()); // NOTE: Ignoring the try_into error here. It is (very) likely that passing the wrong number of proofs would just result in no proofs being submitted, and thus produce an error.
+			let proofs = event_proofs.and_then(|x| ValidatedStreams::quorum_proof(&x)); // NOTE: Proofs from signers outside of the current authorities result in no proofs being submitted, and thus produce an error.
 			UncheckedExtrinsic {
 				signature: None,
 				function: pallet_validated_streams::Call::<Runtime>::validate_event {
+					stream_id,
 					event_id,
+					conflict_set,
 					proofs,
 				}
 				.into(),
@@ -1,4 +1,6 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-	tonic_build::compile_protos("../../proto/streams.proto")?;
+	tonic_build::configure()
+		.protoc_arg("--experimental_allow_proto3_optional")
+		.compile(&["../../client/proto/streams.proto"], &["../../client/proto"])?;
 	Ok(())
 }
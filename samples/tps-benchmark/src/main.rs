@@ -12,9 +12,11 @@ mod validated_streams {
 }
 use validated_streams::{
 	streams_client::StreamsClient, ValidatedEventsRequest, WitnessEventRequest,
+	WitnessEventsRequest,
 };
 
 const CONCURRENT_REQUESTS: usize = 8;
+const BATCH_SIZE: usize = 1000;
 
 fn event_num_to_event_id(event_num: u32) -> Vec<u8> {
 	let num_bytes = event_num.to_be_bytes();
@@ -29,7 +31,13 @@ fn event_id_to_event_num(event_id: &[u8]) -> u32 {
 }
 
 async fn wait_validators(mut client: StreamsClient<Channel>) {
-	let request = WitnessEventRequest { event_id: event_num_to_event_id(0) };
+	let request = WitnessEventRequest {
+		event_id: event_num_to_event_id(0),
+		signature: None,
+		payload: vec![],
+		stream_id: vec![],
+		conflict_set: vec![],
+	};
 	loop {
 		let request = Request::new(request.clone());
 		if client.witness_event(request).await.is_err() {
@@ -41,16 +49,17 @@ async fn wait_validators(mut client: StreamsClient<Channel>) {
 	}
 }
 async fn send_events(client: StreamsClient<Channel>, from_num: u32, to_num: u32) {
-	let mut events = Vec::new();
-	for i in from_num + 1..to_num + 1 {
-		events.push(WitnessEventRequest { event_id: event_num_to_event_id(i) });
-	}
-	stream::iter(events)
-		.map(|event| {
+	let event_ids = (from_num + 1..to_num + 1).map(event_num_to_event_id).collect::<Vec<_>>();
+	let batches = event_ids
+		.chunks(BATCH_SIZE)
+		.map(|event_ids| WitnessEventsRequest { event_ids: event_ids.to_vec(), stream_id: vec![] })
+		.collect::<Vec<_>>();
+	stream::iter(batches)
+		.map(|batch| {
 			let mut client = client.clone();
 			tokio::spawn(async move {
-				let request = Request::new(event.clone());
-				client.witness_event(request).await
+				let request = Request::new(batch);
+				client.witness_events(request).await
 			})
 		})
 		.buffer_unordered(CONCURRENT_REQUESTS)
@@ -66,7 +75,15 @@ async fn witness_events(
 	increase_factor: f32,
 	decrease_factor: f32,
 ) {
-	let request = Request::new(ValidatedEventsRequest { from_block: 0, from_latest: true });
+	let request = Request::new(ValidatedEventsRequest {
+		from_block: 0,
+		from_latest: true,
+		to_block: None,
+		cursor: vec![],
+		skip_empty_blocks: false,
+		heartbeat_interval: 0,
+		stream_ids: vec![],
+	});
 	let mut stream = client.validated_events(request).await.unwrap().into_inner();
 	let mut max_block_events = 0;
 	let mut received_events: u32 = 0;